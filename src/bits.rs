@@ -0,0 +1,176 @@
+// Bytes parsed bit by bit, most significant bit first, e.g packed binary formats such as DNS
+// headers or codec bitstreams. Tokens are bits, `bits` reads several of them as an integer.
+// Offsets converted to `usize`, and thus the spans of errors, count bits.
+use crate::error::Expected;
+use crate::input::{ByValue, Input, InputRef, SliceInput};
+use crate::{ParseResult, Parser};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BitInput<'input> {
+    bytes: &'input [u8],
+}
+
+impl<'input> BitInput<'input> {
+    pub fn new(bytes: &'input [u8]) -> Self {
+        Self { bytes }
+    }
+}
+
+// Position in a `BitInput`: a byte, and a bit in that byte counted from the most significant one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitOffset {
+    byte: usize,
+    bit: u8,
+}
+
+impl BitOffset {
+    #[inline]
+    pub fn byte(&self) -> usize {
+        self.byte
+    }
+
+    #[inline]
+    pub fn bit(&self) -> u8 {
+        self.bit
+    }
+
+    // Whether the offset lies on a byte boundary.
+    #[inline]
+    pub fn is_aligned(&self) -> bool {
+        self.bit == 0
+    }
+}
+
+impl From<BitOffset> for usize {
+    #[inline]
+    fn from(offset: BitOffset) -> Self {
+        offset.byte * 8 + offset.bit as usize
+    }
+}
+
+// Bits parsed by a parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitSlice<'input> {
+    bytes: &'input [u8],
+    start: BitOffset,
+    end: BitOffset,
+}
+
+impl<'input> BitSlice<'input> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        usize::from(self.end) - usize::from(self.start)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn bits(&self) -> impl Iterator<Item = bool> + 'input {
+        let bytes = self.bytes;
+        (usize::from(self.start)..usize::from(self.end))
+            .map(move |bit| bytes[bit / 8] & (0x80 >> (bit % 8)) != 0)
+    }
+
+    // The bytes the slice lies in, provided it starts and ends on byte boundaries.
+    pub fn as_bytes(&self) -> Option<&'input [u8]> {
+        (self.start.is_aligned() && self.end.is_aligned())
+            .then(|| &self.bytes[self.start.byte..self.end.byte])
+    }
+}
+
+impl<'input> Input<'input> for BitInput<'input> {
+    type Token = bool;
+
+    type Offset = BitOffset;
+
+    type Span = Range<BitOffset>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset.byte < self.bytes.len() || (offset.byte == self.bytes.len() && offset.bit == 0)
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.bytes.get(offset.byte) {
+            Some(byte) => {
+                let bit = byte & (0x80 >> offset.bit) != 0;
+                let next = match offset.bit {
+                    7 => BitOffset {
+                        byte: offset.byte + 1,
+                        bit: 0,
+                    },
+                    bit => BitOffset {
+                        byte: offset.byte,
+                        bit: bit + 1,
+                    },
+                };
+                (next, Some(bit))
+            }
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        BitOffset { byte: 0, bit: 0 }
+    }
+}
+
+impl<'input> SliceInput<'input> for BitInput<'input> {
+    type Slice = BitSlice<'input>;
+
+    #[inline]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        BitSlice {
+            bytes: self.bytes,
+            start,
+            end,
+        }
+    }
+}
+
+// `Bits` primitive, reads `count` bits as an unsigned integer, most significant bit first.
+#[derive(Debug, Clone, Copy)]
+pub struct Bits {
+    count: u32,
+}
+
+// Panics if `count` is greater than 64.
+pub const fn bits(count: u32) -> Bits {
+    assert!(count <= u64::BITS, "at most 64 bits can be read at once");
+    Bits { count }
+}
+
+impl<'input> Parser<'input, BitInput<'input>, u64> for Bits {
+    fn go(&self, input_ref: &mut InputRef<'input, '_, BitInput<'input>>) -> ParseResult<u64> {
+        let mut value = 0;
+        for read in 0..self.count {
+            match input_ref.next_token() {
+                Some(bit) => value = value << 1 | bit as u64,
+                None => {
+                    input_ref.record_expected(Expected::Any);
+                    return Err(input_ref.mismatch(Some((self.count - read) as usize)));
+                }
+            }
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,204 @@
+// Scans over runs of bytes, e.g whitespace, digits or everything up to a delimiter, for inputs laid
+// out in a byte buffer. The buffer is walked through at once rather than token by token: searches
+// go through `memchr` if the feature is enabled, runs of up to three distinct bytes are skipped
+// eight bytes at a time.
+use crate::input::{InputRef, SliceInput};
+use crate::{ParseError, ParseResult, Parser};
+use std::marker::PhantomData;
+
+// Input whose offsets are byte offsets in a buffer, e.g `&[u8]`, or text, e.g `&str`.
+pub trait ByteInput<'input>: SliceInput<'input> {
+    // Offset past the run of bytes `scan` counts from `offset` on, along with the number of
+    // tokens in the run. Runs of text are cut short to end on a character boundary.
+    fn scan_bytes(
+        &self,
+        offset: Self::Offset,
+        scan: impl FnOnce(&[u8]) -> usize,
+    ) -> (Self::Offset, usize);
+}
+
+impl<'input> ByteInput<'input> for &'input str {
+    fn scan_bytes(&self, offset: usize, scan: impl FnOnce(&[u8]) -> usize) -> (usize, usize) {
+        let mut end = offset + scan(&self.as_bytes()[offset..]);
+        while !self.is_char_boundary(end) {
+            end -= 1;
+        }
+        (end, self[offset..end].chars().count())
+    }
+}
+
+impl<'input> ByteInput<'input> for &'input [u8] {
+    fn scan_bytes(&self, offset: usize, scan: impl FnOnce(&[u8]) -> usize) -> (usize, usize) {
+        let len = scan(&self[offset..]);
+        (offset + len, len)
+    }
+}
+
+// Set of bytes to scan for. The bytes of text inputs are meant to be ASCII characters.
+#[derive(Debug, Clone, Copy)]
+pub struct ByteSet {
+    table: [bool; 256],
+    // Up to the first three bytes added, scanned for with `memchr` or eight bytes at a time when
+    // there are no others.
+    first: [u8; 3],
+    len: usize,
+}
+
+impl ByteSet {
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut set = ByteSet {
+            table: [false; 256],
+            first: [0; 3],
+            len: 0,
+        };
+        for &byte in bytes {
+            if !set.contains(byte) {
+                set.table[byte as usize] = true;
+                if let Some(first) = set.first.get_mut(set.len) {
+                    *first = byte;
+                }
+                set.len += 1;
+            }
+        }
+        set
+    }
+
+    #[inline(always)]
+    pub fn contains(&self, byte: u8) -> bool {
+        self.table[byte as usize]
+    }
+
+    // Length of the run of bytes not in the set at the start of `bytes`.
+    fn find_in(&self, bytes: &[u8]) -> usize {
+        #[cfg(feature = "memchr")]
+        let found = match (self.len, self.first) {
+            (1, [a, ..]) => memchr::memchr(a, bytes),
+            (2, [a, b, _]) => memchr::memchr2(a, b, bytes),
+            (3, [a, b, c]) => memchr::memchr3(a, b, c, bytes),
+            _ => bytes.iter().position(|&byte| self.contains(byte)),
+        };
+        #[cfg(not(feature = "memchr"))]
+        let found = bytes.iter().position(|&byte| self.contains(byte));
+        found.unwrap_or(bytes.len())
+    }
+
+    // Length of the run of bytes in the set at the start of `bytes`.
+    fn skip_in(&self, bytes: &[u8]) -> usize {
+        let mut skipped = 0;
+        if self.len <= self.first.len() {
+            let first = &self.first[..self.len];
+            for chunk in bytes.chunks_exact(8) {
+                let word = u64::from_le_bytes(chunk.try_into().unwrap());
+                let outside = !first
+                    .iter()
+                    .fold(0, |found, &byte| found | zeros(word ^ splat(byte)));
+                match outside & HIGH_BITS {
+                    0 => skipped += 8,
+                    outside => return skipped + outside.trailing_zeros() as usize / 8,
+                }
+            }
+        }
+        (bytes[skipped..].iter())
+            .position(|&byte| !self.contains(byte))
+            .map_or(bytes.len(), |len| skipped + len)
+    }
+}
+
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+// `byte` in each byte of a word.
+#[inline(always)]
+fn splat(byte: u8) -> u64 {
+    u64::from(byte) * 0x0101_0101_0101_0101
+}
+
+// High bit set in each zero byte of `word`, and only in those: unlike the usual `(word - 0x01..) &
+// !word` trick, no borrow carries over from a byte to the next.
+#[inline(always)]
+fn zeros(word: u64) -> u64 {
+    const LOW_BITS: u64 = !HIGH_BITS;
+    !(((word & LOW_BITS) + LOW_BITS) | word | LOW_BITS)
+}
+
+// Runs into the end of input: there may be more of the run in the next chunk of a partial input.
+fn scanned<'input, I>(
+    input_ref: &mut InputRef<'input, '_, I>,
+    run: (I::Offset, usize),
+    rest: usize,
+) -> ParseResult<I::Slice>
+where
+    I: ByteInput<'input>,
+{
+    if rest == 0 && input_ref.is_partial() {
+        return Err(ParseError::Incomplete { needed: Some(1) });
+    }
+    let start = input_ref.offset();
+    input_ref.advance(run.0, run.1);
+    Ok(input_ref.slice(start, input_ref.offset()))
+}
+
+// `TakeWhileIn` primitive, outputs the run of bytes in the set under the cursor, possibly empty,
+// e.g whitespace.
+#[derive(Debug, Clone, Copy)]
+pub struct TakeWhileIn<I> {
+    set: ByteSet,
+    phantom: PhantomData<I>,
+}
+
+pub fn take_while_in<'input, I>(bytes: &[u8]) -> TakeWhileIn<I>
+where
+    I: ByteInput<'input>,
+{
+    TakeWhileIn {
+        set: ByteSet::new(bytes),
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I> Parser<'input, I, I::Slice> for TakeWhileIn<I>
+where
+    I: ByteInput<'input>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let mut rest = 0;
+        let run = input_ref.scan_bytes(|bytes| {
+            let len = self.set.skip_in(bytes);
+            rest = bytes.len() - len;
+            len
+        });
+        scanned(input_ref, run, rest)
+    }
+}
+
+// `TakeUntil` primitive, outputs the bytes under the cursor up to the first one in the set, or to
+// the end of input, e.g the content of a quoted string.
+#[derive(Debug, Clone, Copy)]
+pub struct TakeUntil<I> {
+    set: ByteSet,
+    phantom: PhantomData<I>,
+}
+
+pub fn take_until<'input, I>(bytes: &[u8]) -> TakeUntil<I>
+where
+    I: ByteInput<'input>,
+{
+    TakeUntil {
+        set: ByteSet::new(bytes),
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I> Parser<'input, I, I::Slice> for TakeUntil<I>
+where
+    I: ByteInput<'input>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let mut rest = 0;
+        let run = input_ref.scan_bytes(|bytes| {
+            let len = self.set.find_in(bytes);
+            rest = bytes.len() - len;
+            len
+        });
+        scanned(input_ref, run, rest)
+    }
+}
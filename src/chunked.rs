@@ -0,0 +1,155 @@
+// Text split in several non-contiguous chunks, e.g the chunks of a rope in a text editor, parsed
+// without being copied into a single buffer first. Offsets are byte offsets in the whole text.
+use crate::input::{Input, SliceInput};
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub struct ChunkedInput<'input> {
+    chunks: &'input [&'input str],
+    // Offset at which each chunk starts.
+    starts: Vec<usize>,
+}
+
+impl<'input> ChunkedInput<'input> {
+    pub fn new(chunks: &'input [&'input str]) -> Self {
+        let starts = chunks
+            .iter()
+            .scan(0, |start, chunk| {
+                let chunk_start = *start;
+                *start += chunk.len();
+                Some(chunk_start)
+            })
+            .collect();
+        Self { chunks, starts }
+    }
+
+    // Index of the chunk holding the byte at `offset`. Empty chunks start where the next one does,
+    // so the last chunk starting at or before `offset` is the one.
+    fn chunk_at(&self, offset: usize) -> Option<usize> {
+        let idx = self
+            .starts
+            .partition_point(|start| *start <= offset)
+            .checked_sub(1)?;
+        (offset < self.starts[idx] + self.chunks[idx].len()).then_some(idx)
+    }
+}
+
+// Part of a `ChunkedInput`, it may span several chunks.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkedSlice<'input> {
+    chunks: &'input [&'input str],
+    start: usize,
+    end: usize,
+}
+
+impl<'input> ChunkedSlice<'input> {
+    // Pieces of the chunks covered by the slice, in order.
+    pub fn chunks(&self) -> impl Iterator<Item = &'input str> {
+        let (start, end) = (self.start, self.end);
+        let mut chunk_start = 0;
+        self.chunks.iter().filter_map(move |chunk| {
+            let range = (chunk_start, chunk_start + chunk.len());
+            chunk_start = range.1;
+            let from = start.clamp(range.0, range.1) - range.0;
+            let to = end.clamp(range.0, range.1) - range.0;
+            (from < to).then(|| &chunk[from..to])
+        })
+    }
+
+    // Borrowed when the slice lies in a single chunk.
+    pub fn to_cow(&self) -> Cow<'input, str> {
+        let mut chunks = self.chunks();
+        match (chunks.next(), chunks.next()) {
+            (None, _) => Cow::Borrowed(""),
+            (Some(chunk), None) => Cow::Borrowed(chunk),
+            (Some(first), Some(second)) => {
+                let mut text = String::with_capacity(self.len());
+                text.push_str(first);
+                text.push_str(second);
+                text.extend(chunks);
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for ChunkedSlice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl<'input> Input<'input> for ChunkedInput<'input> {
+    type Token = char;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        match self.chunk_at(offset) {
+            Some(idx) => self.chunks[idx].is_char_boundary(offset - self.starts[idx]),
+            // Past the last character, only the end of input is.
+            None => offset == self.chunks.iter().map(|chunk| chunk.len()).sum::<usize>(),
+        }
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        let Some(idx) = self.chunk_at(offset) else {
+            return (offset, None);
+        };
+
+        // Characters never straddle chunks, chunks are valid UTF-8 on their own.
+        let chunk = &self.chunks[idx][offset - self.starts[idx]..];
+        match chunk.chars().next() {
+            Some(c) => (offset + c.len_utf8(), Some(c)),
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+}
+
+impl<'input> SliceInput<'input> for ChunkedInput<'input> {
+    type Slice = ChunkedSlice<'input>;
+
+    #[inline]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        ChunkedSlice {
+            chunks: self.chunks,
+            start,
+            end,
+        }
+    }
+}
@@ -0,0 +1,996 @@
+use crate::input::SourceId;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+// Set of what the parser expected at a given position. Sets are built by merging what every
+// alternative tried at that position expected, so union is cheap: no allocation is needed for a
+// single item, which is the case of most primitives, and duplicates are dropped on insertion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedSet<T>(ExpectedRepr<T>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExpectedRepr<T> {
+    Empty,
+    One(Expected<T>),
+    Many(Vec<Expected<T>>),
+}
+
+impl<T> ExpectedSet<T> {
+    #[inline]
+    pub fn new() -> Self {
+        ExpectedSet(ExpectedRepr::Empty)
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[Expected<T>] {
+        match &self.0 {
+            ExpectedRepr::Empty => &[],
+            ExpectedRepr::One(expected) => std::slice::from_ref(expected),
+            ExpectedRepr::Many(expected) => expected,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Expected<T>> {
+        self.as_slice().iter()
+    }
+}
+
+impl<T> ExpectedSet<T>
+where
+    T: PartialEq,
+{
+    pub fn insert(&mut self, expected: Expected<T>) {
+        if self.as_slice().contains(&expected) {
+            return;
+        }
+
+        self.0 = match std::mem::replace(&mut self.0, ExpectedRepr::Empty) {
+            ExpectedRepr::Empty => ExpectedRepr::One(expected),
+            ExpectedRepr::One(first) => ExpectedRepr::Many(vec![first, expected]),
+            ExpectedRepr::Many(mut all) => {
+                all.push(expected);
+                ExpectedRepr::Many(all)
+            }
+        };
+    }
+
+    // Adds the items of `other` that aren't in the set yet, after the existing ones.
+    pub fn union(&mut self, other: ExpectedSet<T>) {
+        match other.0 {
+            ExpectedRepr::Empty => {}
+            ExpectedRepr::One(expected) => self.insert(expected),
+            ExpectedRepr::Many(all) => all.into_iter().for_each(|expected| self.insert(expected)),
+        }
+    }
+}
+
+impl<T> Default for ExpectedSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<Expected<T>> for ExpectedSet<T> {
+    fn from(expected: Expected<T>) -> Self {
+        ExpectedSet(ExpectedRepr::One(expected))
+    }
+}
+
+impl<T> From<Vec<Expected<T>>> for ExpectedSet<T>
+where
+    T: PartialEq,
+{
+    fn from(all: Vec<Expected<T>>) -> Self {
+        all.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<Expected<T>> for ExpectedSet<T>
+where
+    T: PartialEq,
+{
+    fn from_iter<It: IntoIterator<Item = Expected<T>>>(iter: It) -> Self {
+        let mut set = ExpectedSet::new();
+        iter.into_iter().for_each(|expected| set.insert(expected));
+        set
+    }
+}
+
+// Stable, machine readable category of a failure, meant for tooling (editors, CI annotations...)
+// that needs to filter errors without matching on messages. Codes are displayed as `E0001`,
+// `E0002`... and the ones chosen by users as `U0001`, `U0002`...
+// Codes are never renumbered, new ones are only appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    // E0001: a token didn't match the expected one.
+    UnexpectedToken,
+    // E0002: the input ended while more tokens were expected.
+    UnexpectedEndOfInput,
+    // E0003: the input wasn't fully consumed.
+    TrailingInput,
+    // E0004: a parsed token was rejected by a `filter` or a `guard`.
+    RejectedToken,
+    // E0005: failure raised by user code.
+    Custom,
+    // E0006: the parse was aborted because too many errors were recovered from.
+    TooManyErrors,
+    // E0007: the input isn't validly encoded text.
+    InvalidEncoding,
+    // E0008: the parse was aborted because the input is too deeply nested.
+    DepthExceeded,
+    // E0009: the parse was aborted because it backtracked over too much input.
+    BudgetExceeded,
+    // E0010: a parse was resumed from a mark of another input.
+    StaleMark,
+    // E0011: a key was already given an earlier value, see `DuplicateKeys::Fail`.
+    DuplicateKey,
+    // Code chosen by the user.
+    User(u16),
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCode::UnexpectedToken => write!(f, "E0001"),
+            ErrorCode::UnexpectedEndOfInput => write!(f, "E0002"),
+            ErrorCode::TrailingInput => write!(f, "E0003"),
+            ErrorCode::RejectedToken => write!(f, "E0004"),
+            ErrorCode::Custom => write!(f, "E0005"),
+            ErrorCode::TooManyErrors => write!(f, "E0006"),
+            ErrorCode::InvalidEncoding => write!(f, "E0007"),
+            ErrorCode::DepthExceeded => write!(f, "E0008"),
+            ErrorCode::BudgetExceeded => write!(f, "E0009"),
+            ErrorCode::StaleMark => write!(f, "E0010"),
+            ErrorCode::DuplicateKey => write!(f, "E0011"),
+            ErrorCode::User(code) => write!(f, "U{:04}", code),
+        }
+    }
+}
+
+// Failure raised by user code (e.g a `try_map` closure), optionally caused by an external error
+// such as a `ParseIntError`.
+#[derive(Debug)]
+pub struct CustomError {
+    message: String,
+    code: ErrorCode,
+    cause: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl CustomError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code: ErrorCode::Custom,
+            cause: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn with_cause(mut self, cause: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        self.cause = Some(cause.into());
+        self
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+}
+
+// Causes are opaque, only messages are compared.
+impl PartialEq for CustomError {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl fmt::Display for CustomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for CustomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.cause
+            .as_deref()
+            .map(|cause| cause as &(dyn Error + 'static))
+    }
+}
+
+// Invalid sequence met by an input decoding text, e.g an unpaired UTF-16 surrogate. The span is in
+// the offsets of the input, see `Input::encoding_error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingError {
+    span: Range<usize>,
+    encoding: &'static str,
+}
+
+impl EncodingError {
+    pub fn new(span: Range<usize>, encoding: &'static str) -> Self {
+        Self { span, encoding }
+    }
+
+    #[inline]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    #[inline]
+    pub fn encoding(&self) -> &'static str {
+        self.encoding
+    }
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} sequence at offset {}",
+            self.encoding, self.span.start
+        )
+    }
+}
+
+impl Error for EncodingError {}
+
+// e.g "left: 'a', 'b', ...".
+impl<T> fmt::Display for Trailing<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "left:")?;
+        for (i, token) in self.preview.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{:?}", separator, token)?;
+        }
+        if self.truncated {
+            write!(f, ", ...")?;
+        }
+        Ok(())
+    }
+}
+
+// Something the parser was expecting to find at the position where it failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected<T> {
+    Token(T),
+    // Human level description of what was expected, see `Parser::expect`.
+    Label(&'static str),
+    // Any token at all, i.e anything but the end of input.
+    Any,
+    EndOfInput,
+    // Any token from the first to the last, see `range`.
+    Range(T, T),
+}
+
+impl<T> fmt::Display for Expected<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Token(token) => write!(f, "{:?}", token),
+            Expected::Label(label) => write!(f, "{}", label),
+            Expected::Any => write!(f, "any token"),
+            Expected::EndOfInput => write!(f, "end of input"),
+            Expected::Range(start, end) => write!(f, "{:?}..={:?}", start, end),
+        }
+    }
+}
+
+// Detailed description of a parse failure: where it happened, what the parser expected there and
+// what it found instead.
+// `InputRef` keeps track of the failure that happened the furthest in the input, this is the one
+// reported to the user by `Parser::parse`.
+#[derive(Debug, PartialEq)]
+pub struct ErrorReport<T> {
+    span: Range<usize>,
+    expected: ExpectedSet<T>,
+    // `None` means the end of input was found.
+    found: Option<T>,
+    code: ErrorCode,
+    // Set when the failure was raised by user code rather than by a mismatch.
+    custom: Option<Box<CustomError>>,
+    // Most failures are never reported, what only some reports need is allocated on demand to
+    // keep them small.
+    annotations: Option<Box<Annotations<T>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Annotations<T> {
+    // Secondary spans giving context to the failure, e.g where an unclosed delimiter was opened.
+    labels: Vec<(Range<usize>, String)>,
+    // Names of the `labelled` parsers the failure bubbled up through, innermost first, along with
+    // the offset at which they started.
+    context: Vec<(&'static str, usize)>,
+    // Preview of the input left over when the end of input was expected.
+    trailing: Option<Trailing<T>>,
+    source_id: Option<SourceId>,
+}
+
+impl<T> Default for Annotations<T> {
+    fn default() -> Self {
+        Self {
+            labels: Vec::new(),
+            context: Vec::new(),
+            trailing: None,
+            source_id: None,
+        }
+    }
+}
+
+// The first tokens of the input left unconsumed, see `ErrorReport::trailing`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trailing<T> {
+    preview: Vec<T>,
+    truncated: bool,
+}
+
+impl<T> Trailing<T> {
+    pub fn new(preview: Vec<T>, truncated: bool) -> Self {
+        Self { preview, truncated }
+    }
+
+    #[inline]
+    pub fn preview(&self) -> &[T] {
+        &self.preview
+    }
+
+    // Whether more input is left past the preview.
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<T> ErrorReport<T> {
+    // The code of the report is deduced from what was expected and found, use `with_code` to
+    // override it.
+    pub fn new(span: Range<usize>, expected: impl Into<ExpectedSet<T>>, found: Option<T>) -> Self {
+        let expected = expected.into();
+        let code = match (&found, expected.as_slice()) {
+            (None, _) => ErrorCode::UnexpectedEndOfInput,
+            (Some(_), [Expected::EndOfInput]) => ErrorCode::TrailingInput,
+            (Some(_), _) => ErrorCode::UnexpectedToken,
+        };
+
+        Self {
+            span,
+            expected,
+            found,
+            code,
+            custom: None,
+            annotations: None,
+        }
+    }
+
+    pub fn custom(span: Range<usize>, found: Option<T>, custom: CustomError) -> Self {
+        Self {
+            span,
+            expected: ExpectedSet::new(),
+            found,
+            code: custom.code(),
+            custom: Some(Box::new(custom)),
+            annotations: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    #[inline]
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn set_expected(&mut self, expected: impl Into<ExpectedSet<T>>) {
+        self.expected = expected.into();
+    }
+
+    pub(crate) fn take_expected(&mut self) -> ExpectedSet<T> {
+        std::mem::take(&mut self.expected)
+    }
+
+    fn annotations_mut(&mut self) -> &mut Annotations<T> {
+        self.annotations.get_or_insert_with(Default::default)
+    }
+
+    pub fn with_source_id(mut self, source_id: SourceId) -> Self {
+        self.annotations_mut().source_id = Some(source_id);
+        self
+    }
+
+    // The source the failure happened in, see `Sourced`.
+    #[inline]
+    pub fn source_id(&self) -> Option<&SourceId> {
+        self.annotations.as_ref()?.source_id.as_ref()
+    }
+
+    pub fn with_trailing(mut self, trailing: Trailing<T>) -> Self {
+        self.annotations_mut().trailing = Some(trailing);
+        self
+    }
+
+    // Input left over, set on the failures of `end`.
+    #[inline]
+    pub fn trailing(&self) -> Option<&Trailing<T>> {
+        self.annotations.as_ref()?.trailing.as_ref()
+    }
+
+    pub fn with_label(mut self, span: Range<usize>, message: impl Into<String>) -> Self {
+        self.add_label(span, message);
+        self
+    }
+
+    pub fn add_label(&mut self, span: Range<usize>, message: impl Into<String>) {
+        self.annotations_mut().labels.push((span, message.into()));
+    }
+
+    // Moves the offsets of the report by `by`, e.g when it was produced on a chunk of a larger
+    // input.
+    pub(crate) fn shift(&mut self, by: usize) {
+        self.span = self.span.start + by..self.span.end + by;
+        if let Some(annotations) = &mut self.annotations {
+            for (span, _) in &mut annotations.labels {
+                *span = span.start + by..span.end + by;
+            }
+            for (_, start) in &mut annotations.context {
+                *start += by;
+            }
+        }
+    }
+
+    pub(crate) fn push_context(&mut self, name: &'static str, start: usize, max_depth: usize) {
+        if self.context().len() < max_depth {
+            self.annotations_mut().context.push((name, start));
+        }
+    }
+
+    // "While parsing" breadcrumbs, innermost first.
+    #[inline]
+    pub fn context(&self) -> &[(&'static str, usize)] {
+        self.annotations
+            .as_ref()
+            .map_or(&[], |annotations| &annotations.context)
+    }
+
+    #[inline]
+    pub fn secondary_labels(&self) -> &[(Range<usize>, String)] {
+        self.annotations
+            .as_ref()
+            .map_or(&[], |annotations| &annotations.labels)
+    }
+
+    #[inline]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    #[inline]
+    pub fn expected(&self) -> &[Expected<T>] {
+        self.expected.as_slice()
+    }
+
+    #[inline]
+    pub fn found(&self) -> Option<&T> {
+        self.found.as_ref()
+    }
+
+    // Message of the failure raised by user code, if any.
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.custom.as_deref().map(CustomError::message)
+    }
+
+    // Position of the start of the failure in the source it was produced from. Spans are byte
+    // offsets, this is where they're translated to lines and columns.
+    pub fn position(&self, source: &str) -> Position {
+        Position::locate(source, self.span.start)
+    }
+
+    // Renders the error against the source it was produced from: the offending line is printed
+    // with a caret underline below the failing span, e.g.
+    //
+    // error[E0001]: found 'q', expected 'o'
+    //  --> 1:5
+    //   |
+    // 1 | hellq world
+    //   |     ^
+    pub fn display<'a>(&'a self, source: &'a str) -> ReportDisplay<'a, T> {
+        ReportDisplay {
+            report: self,
+            source,
+            context_lines: 0,
+            max_line_width: None,
+        }
+    }
+
+    // Copy of the report, unless it was raised by user code: the cause of a custom error can't be
+    // copied.
+    pub(crate) fn try_clone(&self) -> Option<Self>
+    where
+        T: Clone,
+    {
+        self.custom.is_none().then(|| Self {
+            span: self.span.clone(),
+            expected: self.expected.clone(),
+            found: self.found.clone(),
+            code: self.code,
+            custom: None,
+            annotations: self.annotations.clone(),
+        })
+    }
+}
+
+impl<T> ErrorReport<T>
+where
+    T: fmt::Debug,
+{
+    // Main message of the report, e.g "found 'a'", or the message of the custom failure.
+    pub(crate) fn headline(&self) -> String {
+        match (&self.custom, &self.found) {
+            (Some(custom), _) => custom.to_string(),
+            (None, Some(_)) if self.trailing().is_some() => {
+                format!("found trailing input at offset {}", self.span.start)
+            }
+            (None, Some(token)) => format!("found {:?}", token),
+            (None, None) => String::from("found end of input"),
+        }
+    }
+
+    // e.g "expected one of 'a', 'b'", `None` if nothing in particular was expected.
+    pub(crate) fn expected_message(&self) -> Option<String> {
+        match self.expected.as_slice() {
+            [] => None,
+            [expected] => Some(format!("expected {}", expected)),
+            [init @ .., last] => {
+                let mut message = String::from("expected one of ");
+                for expected in init {
+                    message.push_str(&format!("{}, ", expected));
+                }
+                message.push_str(&last.to_string());
+                Some(message)
+            }
+        }
+    }
+}
+
+impl<T> fmt::Display for ErrorReport<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.headline())?;
+        if let Some(expected) = self.expected_message() {
+            write!(f, ", {}", expected)?;
+        }
+        if let Some(trailing) = self.trailing() {
+            write!(f, ", {}", trailing)?;
+        }
+        for (name, _) in self.context() {
+            write!(f, ", in {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Error for ErrorReport<T>
+where
+    T: fmt::Debug,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.custom.as_ref().and_then(|custom| custom.source())
+    }
+}
+
+// Helper returned by `ErrorReport::display`.
+pub struct ReportDisplay<'a, T> {
+    report: &'a ErrorReport<T>,
+    source: &'a str,
+    context_lines: usize,
+    max_line_width: Option<usize>,
+}
+
+impl<'a, T> ReportDisplay<'a, T> {
+    // Number of lines printed before and after the failing line, none by default.
+    pub fn context_lines(mut self, context_lines: usize) -> Self {
+        self.context_lines = context_lines;
+        self
+    }
+
+    // Lines longer than `max_line_width` characters, e.g minified JSON, are cut around the failure
+    // and what is cut off is replaced by "...". Unlimited by default.
+    pub fn max_line_width(mut self, max_line_width: usize) -> Self {
+        self.max_line_width = Some(max_line_width);
+        self
+    }
+}
+
+impl<'a, T> fmt::Display for ReportDisplay<'a, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let locate = |span| Snippet::locate(self.source, span, self.context_lines);
+        let primary = locate(self.report.span());
+        let labels = self
+            .report
+            .secondary_labels()
+            .iter()
+            .map(|(span, message)| (locate(span.clone()), message))
+            .collect::<Vec<_>>();
+
+        let gutter_width = labels
+            .iter()
+            .map(|(snippet, _)| snippet.last_line_number())
+            .chain(std::iter::once(primary.last_line_number()))
+            .max()
+            .unwrap_or_default()
+            .to_string()
+            .len();
+        let gutter = " ".repeat(gutter_width);
+
+        writeln!(f, "error[{}]: {}", self.report.code(), self.report)?;
+        write!(f, "{}--> ", gutter)?;
+        if let Some(source_id) = self.report.source_id() {
+            write!(f, "{}:", source_id)?;
+        }
+        writeln!(f, "{}:{}", primary.line_number, primary.column + 1)?;
+        primary.write(f, &gutter, self.max_line_width, '^', None)?;
+
+        for (snippet, message) in labels {
+            writeln!(f)?;
+            snippet.write(f, &gutter, self.max_line_width, '-', Some(message))?;
+        }
+        Ok(())
+    }
+}
+
+// Line of the source covered by the start of a span, along with the lines around it.
+struct Snippet<'a> {
+    line: &'a str,
+    line_number: usize,
+    // In characters.
+    column: usize,
+    // In characters, at least 1 so that empty spans are still pointed at.
+    underline: usize,
+    before: Vec<&'a str>,
+    after: Vec<&'a str>,
+}
+
+impl<'a> Snippet<'a> {
+    fn locate(source: &'a str, span: Range<usize>, context_lines: usize) -> Self {
+        // Spans are byte offsets, clamp them so that reports produced against a different
+        // (shorter) source don't panic.
+        let start = floor_char_boundary(source, span.start);
+        let end = floor_char_boundary(source, span.end.max(start));
+
+        let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |idx| start + idx);
+
+        let mut before = source[..line_start.saturating_sub(1)]
+            .lines()
+            .rev()
+            .take(if line_start == 0 { 0 } else { context_lines })
+            .collect::<Vec<_>>();
+        before.reverse();
+        let after = source
+            .get(line_end + 1..)
+            .map(|rest| rest.lines().take(context_lines).collect())
+            .unwrap_or_default();
+
+        Self {
+            line: source[line_start..line_end].trim_end_matches('\r'),
+            line_number: source[..line_start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count(),
+            underline: source[start..end.min(line_end)].chars().count().max(1),
+            before,
+            after,
+        }
+    }
+
+    fn last_line_number(&self) -> usize {
+        self.line_number + self.after.len()
+    }
+
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        gutter: &str,
+        max_line_width: Option<usize>,
+        marker: char,
+        message: Option<&String>,
+    ) -> fmt::Result {
+        // Every line is cut the same way to keep them aligned.
+        let window = Window::new(self.line, self.column, max_line_width);
+        let number = |line_number: usize| format!("{:>1$}", line_number, gutter.len());
+
+        writeln!(f, "{} |", gutter)?;
+        for (idx, line) in self.before.iter().enumerate() {
+            let line_number = self.line_number - self.before.len() + idx;
+            writeln!(f, "{} | {}", number(line_number), window.cut(line))?;
+        }
+        writeln!(
+            f,
+            "{} | {}",
+            number(self.line_number),
+            window.cut(self.line)
+        )?;
+
+        let (column, underline) = window.shift(self.column, self.underline);
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(column),
+            marker.to_string().repeat(underline)
+        )?;
+        if let Some(message) = message {
+            write!(f, " {}", message)?;
+        }
+
+        for (idx, line) in self.after.iter().enumerate() {
+            write!(
+                f,
+                "\n{} | {}",
+                number(self.line_number + idx + 1),
+                window.cut(line)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+const ELLIPSIS: &str = "...";
+
+// Range of characters of a line shown in a snippet, centered on the failure when the line is too
+// long.
+struct Window {
+    start: usize,
+    width: Option<usize>,
+}
+
+impl Window {
+    fn new(line: &str, column: usize, max_width: Option<usize>) -> Self {
+        let len = line.chars().count();
+        match max_width {
+            Some(width) if len > width => Self {
+                start: column.saturating_sub(width / 2).min(len - width),
+                width: Some(width),
+            },
+            _ => Self {
+                start: 0,
+                width: None,
+            },
+        }
+    }
+
+    fn cut(&self, line: &str) -> String {
+        let Some(width) = self.width else {
+            return line.to_string();
+        };
+
+        let mut cut = String::new();
+        if self.start > 0 {
+            cut.push_str(ELLIPSIS);
+        }
+        cut.extend(line.chars().skip(self.start).take(width));
+        if line.chars().count() > self.start + width {
+            cut.push_str(ELLIPSIS);
+        }
+        cut
+    }
+
+    // Moves a column and an underline into the window.
+    fn shift(&self, column: usize, underline: usize) -> (usize, usize) {
+        let Some(width) = self.width else {
+            return (column, underline);
+        };
+
+        let prefix = if self.start > 0 { ELLIPSIS.len() } else { 0 };
+        let column = column.saturating_sub(self.start);
+        (prefix + column, underline.min(width - column).max(1))
+    }
+}
+
+// Position of a byte offset in a source, in the various units tools count in: bytes, characters
+// and UTF-16 code units (e.g the LSP). Lines and columns start at 0, they're displayed starting at
+// 1, e.g "2:10".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    byte: usize,
+    char: usize,
+    line: usize,
+    column: usize,
+    utf16_column: usize,
+}
+
+impl Position {
+    // Offsets past the end of `source` or inside of a character are clamped.
+    pub fn locate(source: &str, byte: usize) -> Self {
+        let byte = floor_char_boundary(source, byte);
+        let line_start = source[..byte].rfind('\n').map_or(0, |idx| idx + 1);
+        let line = &source[line_start..byte];
+
+        Self {
+            byte,
+            char: source[..byte].chars().count(),
+            line: source[..line_start].matches('\n').count(),
+            column: line.chars().count(),
+            utf16_column: line.encode_utf16().count(),
+        }
+    }
+
+    // Position right after `c`, which is found at this position.
+    pub(crate) fn advance(self, c: char) -> Self {
+        if c == '\n' {
+            Self {
+                byte: self.byte + 1,
+                char: self.char + 1,
+                line: self.line + 1,
+                column: 0,
+                utf16_column: 0,
+            }
+        } else {
+            Self {
+                byte: self.byte + c.len_utf8(),
+                char: self.char + 1,
+                line: self.line,
+                column: self.column + 1,
+                utf16_column: self.utf16_column + c.len_utf16(),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn byte(&self) -> usize {
+        self.byte
+    }
+
+    // Number of characters before the position.
+    #[inline]
+    pub fn char(&self) -> usize {
+        self.char
+    }
+
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    // In characters.
+    #[inline]
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    // In UTF-16 code units.
+    #[inline]
+    pub fn utf16_column(&self) -> usize {
+        self.utf16_column
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+fn floor_char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+// Severity of a non fatal diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+// A warning or a note emitted while parsing. Unlike `ErrorReport`, diagnostics don't make the parse
+// fail, they are returned alongside the output by `Parser::parse_with_diagnostics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    severity: Severity,
+    span: Range<usize>,
+    message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            span,
+            message: message.into(),
+        }
+    }
+
+    #[inline]
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    #[inline]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} at {}..{}",
+            self.severity, self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+// Handed to `validate` closures to emit diagnostics.
+pub struct Emitter<'a> {
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a> Emitter<'a> {
+    pub(crate) fn new(diagnostics: &'a mut Vec<Diagnostic>) -> Self {
+        Self { diagnostics }
+    }
+
+    pub fn emit(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn warning(&mut self, span: Range<usize>, message: impl Into<String>) {
+        self.emit(Diagnostic::new(Severity::Warning, span, message));
+    }
+
+    pub fn note(&mut self, span: Range<usize>, message: impl Into<String>) {
+        self.emit(Diagnostic::new(Severity::Note, span, message));
+    }
+}
@@ -1,4 +1,21 @@
-// The input trait abstracts over &str and &[u8] input streams.
+use crate::bytes::ByteInput;
+use crate::error::{
+    CustomError, Diagnostic, Emitter, EncodingError, ErrorCode, ErrorReport, Expected, ExpectedSet,
+    Trailing,
+};
+use crate::sequence::OrderedSequence;
+use crate::{ParseError, ParseResult, Parser};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, Range};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// The input trait abstracts over &str and &[T] (e.g &[u8]) input streams.
 // The tokens yielded by each of those input streams are cheap to copy, in fact, copying the tokens
 // aforementioned is faster than copying their references.
 pub trait Input<'input>: 'input {
@@ -6,13 +23,276 @@ pub trait Input<'input>: 'input {
 
     type Offset: Copy + Eq + Into<usize>;
 
-    type Slice: Copy;
+    // Spans handed to user code, e.g by `Parser::validate`. Byte ranges for text, ranges in the
+    // source for tokens, along with the source for `Sourced` inputs...
+    type Span: Clone;
+
+    // What an `InputRef` holds on to while parsing the input. Inputs that are cheap to copy, e.g
+    // `&str`, are held by value (see `ByValue`) so that reading a token doesn't go through a
+    // reference to the input first. The others, e.g `String`, are held by reference.
+    type Handle<'parse>: Deref<Target = Self>
+    where
+        Self: 'parse;
+
+    fn handle(&self) -> Self::Handle<'_>;
 
     fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>);
 
-    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice;
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span;
 
     fn start(&self) -> Self::Offset;
+
+    // Whether `offset` lies within the input, on a token boundary, e.g before resuming from a mark
+    // saved on another input (see `InputRef::resume`). Inputs that can't tell accept any offset.
+    #[inline]
+    fn is_valid_offset(&self, _offset: Self::Offset) -> bool {
+        true
+    }
+
+    // Where the input comes from, e.g a file name, see `Sourced`.
+    #[inline]
+    fn source_id(&self) -> Option<&SourceId> {
+        None
+    }
+
+    // Inputs decoding text stop yielding tokens at the first invalid sequence, which then tells
+    // it apart from the end of input.
+    #[inline]
+    fn encoding_error(&self, _offset: Self::Offset) -> Option<EncodingError> {
+        None
+    }
+
+    // Diagnostics the input emitted itself before `end`, e.g while skipping trivia (see
+    // `SkipTrivia`), taken out of it once the parse is over, see `InputRef::take_diagnostics`.
+    #[inline]
+    fn take_diagnostics(&self, _end: Self::Offset) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+
+    // Same as `take_diagnostics` for the errors recovered from.
+    #[inline]
+    fn take_errors(&self, _end: Self::Offset) -> Vec<ErrorReport<Self::Token>> {
+        Vec::new()
+    }
+
+    // Offset past `prefix` if the input continues with it at `offset`, for inputs storing their
+    // tokens as UTF-8, e.g `&str`. Lets `exact` match a literal with a single comparison rather
+    // than token by token. `None` if the input doesn't continue with it, or can't tell.
+    #[inline]
+    fn match_str(&self, _offset: Self::Offset, _prefix: &str) -> Option<Self::Offset> {
+        None
+    }
+
+    // Same as `match_str`, for inputs storing their tokens in a slice, e.g `&[u8]`.
+    #[inline]
+    fn match_tokens(&self, _offset: Self::Offset, _prefix: &[Self::Token]) -> Option<Self::Offset> {
+        None
+    }
+
+    // Offset past the longest start of `prefix` the input continues with at `offset`, along with
+    // its number of tokens, so that `exact` finds where the input differs from a literal without
+    // walking it token by token. `None` if the input can't tell.
+    #[inline]
+    fn match_prefix(
+        &self,
+        _offset: Self::Offset,
+        _prefix: &[Self::Token],
+    ) -> Option<(Self::Offset, usize)> {
+        None
+    }
+
+    // Transforms each token on the fly, see `MapTokens`.
+    fn map_tokens<U, F>(self, mapper: F) -> MapTokens<Self, F, U>
+    where
+        F: Fn(Self::Token) -> U,
+        Self: Sized,
+    {
+        MapTokens {
+            input: self,
+            mapper,
+            phantom: PhantomData,
+        }
+    }
+}
+
+// Inputs able to hand out the part of the input between two offsets, e.g `&str` slices. Inputs
+// pulling their tokens from a stream may not be, primitives outputting slices (`exact`,
+// `one_of`) have counterparts that don't need them (`tag`, `one_of_index`).
+pub trait SliceInput<'input>: Input<'input> {
+    type Slice: Clone;
+
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice;
+
+    // Input from `start` to the end. The tokens left are walked through to find where it ends,
+    // inputs knowing it slice straight to it.
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        let mut end = start;
+        while let (next, Some(_)) = self.next(end) {
+            end = next;
+        }
+        self.slice(start, end)
+    }
+}
+
+// Identifies the source an input comes from, e.g a file name, so that errors from multi-file
+// projects tell which file they come from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceId(Arc<str>);
+
+impl SourceId {
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        Self(name.into())
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for SourceId {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for SourceId {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Attaches a `SourceId` to an input, it's carried by the errors reported on it.
+#[derive(Debug, Clone)]
+pub struct Sourced<I> {
+    input: I,
+    source_id: SourceId,
+}
+
+impl<I> Sourced<I> {
+    pub fn new(input: I, source_id: impl Into<SourceId>) -> Self {
+        Self {
+            input,
+            source_id: source_id.into(),
+        }
+    }
+}
+
+impl<'input, I> Input<'input> for Sourced<I>
+where
+    I: Input<'input>,
+{
+    type Token = I::Token;
+
+    type Offset = I::Offset;
+
+    type Span = (SourceId, I::Span);
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.input.is_valid_offset(offset)
+    }
+
+    #[inline(always)]
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        self.input.next(offset)
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        (self.source_id.clone(), self.input.span(start, end))
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        self.input.start()
+    }
+
+    #[inline]
+    fn source_id(&self) -> Option<&SourceId> {
+        Some(&self.source_id)
+    }
+
+    #[inline]
+    fn encoding_error(&self, offset: Self::Offset) -> Option<EncodingError> {
+        self.input.encoding_error(offset)
+    }
+
+    #[inline]
+    fn take_diagnostics(&self, end: Self::Offset) -> Vec<Diagnostic> {
+        self.input.take_diagnostics(end)
+    }
+
+    #[inline]
+    fn take_errors(&self, end: Self::Offset) -> Vec<ErrorReport<Self::Token>> {
+        self.input.take_errors(end)
+    }
+
+    #[inline]
+    fn match_str(&self, offset: Self::Offset, prefix: &str) -> Option<Self::Offset> {
+        self.input.match_str(offset, prefix)
+    }
+
+    #[inline]
+    fn match_tokens(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<Self::Offset> {
+        self.input.match_tokens(offset, prefix)
+    }
+
+    #[inline]
+    fn match_prefix(
+        &self,
+        offset: Self::Offset,
+        prefix: &[Self::Token],
+    ) -> Option<(Self::Offset, usize)> {
+        self.input.match_prefix(offset, prefix)
+    }
+}
+
+impl<'input, I> ByteInput<'input> for Sourced<I>
+where
+    I: ByteInput<'input>,
+{
+    #[inline]
+    fn scan_bytes(
+        &self,
+        offset: Self::Offset,
+        scan: impl FnOnce(&[u8]) -> usize,
+    ) -> (Self::Offset, usize) {
+        self.input.scan_bytes(offset, scan)
+    }
+}
+
+impl<'input, I> SliceInput<'input> for Sourced<I>
+where
+    I: SliceInput<'input>,
+{
+    type Slice = I::Slice;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        self.input.slice(start, end)
+    }
+
+    #[inline(always)]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        self.input.slice_to_end(start)
+    }
 }
 
 impl<'input> Input<'input> for &'input str {
@@ -20,7 +300,22 @@ impl<'input> Input<'input> for &'input str {
 
     type Offset = usize;
 
-    type Slice = &'input str;
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.is_char_boundary(offset)
+    }
 
     fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
         if let Some(c) = self[offset..].chars().next() {
@@ -30,106 +325,1597 @@ impl<'input> Input<'input> for &'input str {
         }
     }
 
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    #[inline]
+    fn match_str(&self, offset: Self::Offset, prefix: &str) -> Option<Self::Offset> {
+        self[offset..]
+            .starts_with(prefix)
+            .then_some(offset + prefix.len())
+    }
+}
+
+impl<'input> SliceInput<'input> for &'input str {
+    type Slice = &'input str;
+
     #[inline(always)]
     fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
         &self[start..end]
     }
 
     #[inline(always)]
-    fn start(&self) -> Self::Offset {
-        0
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        &self[start..]
     }
 }
 
-impl<'input> Input<'input> for &'input [u8] {
-    type Token = u8;
+// Slices of tokens, e.g bytes or the tokens produced by a lexer.
+impl<'input, T> Input<'input> for &'input [T]
+where
+    T: Copy + Eq + 'input,
+{
+    type Token = T;
 
     type Offset = usize;
 
-    type Slice = &'input [u8];
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset <= self.len()
+    }
 
     fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
-        if let Some(byte) = self[offset..].iter().next().copied() {
-            (offset + 1, Some(byte))
+        if let Some(token) = self[offset..].iter().next().copied() {
+            (offset + 1, Some(token))
         } else {
             (offset, None)
         }
     }
 
-    #[inline(always)]
-    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
-        &self[start..end]
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
     }
 
     #[inline(always)]
     fn start(&self) -> Self::Offset {
         0
     }
+
+    #[inline]
+    fn match_tokens(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<Self::Offset> {
+        self[offset..]
+            .starts_with(prefix)
+            .then_some(offset + prefix.len())
+    }
+
+    #[inline]
+    fn match_prefix(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<(usize, usize)> {
+        let rest = &self[offset..];
+        let len = rest.len().min(prefix.len());
+        // Compared a chunk at a time, only the chunk where they differ is walked.
+        let mut matched = 0;
+        for (chunk, prefix_chunk) in rest[..len].chunks(16).zip(prefix[..len].chunks(16)) {
+            if chunk != prefix_chunk {
+                matched += chunk
+                    .iter()
+                    .zip(prefix_chunk)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                break;
+            }
+            matched += chunk.len();
+        }
+        Some((offset + matched, matched))
+    }
 }
 
-// Why are we even take the input by reference?
-// the input is cheaply copiable so maybe store it by value instead?
-pub struct InputRef<'input, 'parse, I>
+impl<'input, T> SliceInput<'input> for &'input [T]
 where
-    I: Input<'input>,
+    T: Copy + Eq + 'input,
 {
-    input: &'parse I,
-    offset: I::Offset,
+    type Slice = &'input [T];
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        &self[start..end]
+    }
+
+    #[inline(always)]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        &self[start..]
+    }
 }
 
-impl<'input, 'parse, I> InputRef<'input, 'parse, I>
+// Input whose tokens are those of `input` transformed by `mapper`, e.g lowercased characters for
+// a case insensitive language, or the kinds of the tokens produced by a lexer. Nothing is
+// materialized, slices are the ones of `input`.
+#[derive(Debug, Clone, Copy)]
+pub struct MapTokens<I, F, U> {
+    input: I,
+    mapper: F,
+    phantom: PhantomData<U>,
+}
+
+impl<'input, I, F, U> Input<'input> for MapTokens<I, F, U>
 where
     I: Input<'input>,
+    F: Fn(I::Token) -> U + 'input,
+    U: Copy + Eq + 'input,
 {
-    pub fn new(input: &'parse I) -> Self {
-        Self {
-            input,
-            offset: input.start(),
-        }
+    type Token = U;
+
+    type Offset = I::Offset;
+
+    type Span = I::Span;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
     }
 
-    pub fn next(&mut self) -> (I::Offset, Option<I::Token>) {
-        let (next_offset, next) = self.input.next(self.offset);
-        self.offset = next_offset;
-        (self.offset, next)
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.input.is_valid_offset(offset)
     }
 
-    #[inline(always)]
-    pub fn next_offset(&mut self) -> I::Offset {
-        self.next().0
+    #[inline]
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        let (next, token) = self.input.next(offset);
+        (next, token.map(&self.mapper))
     }
 
     #[inline(always)]
-    pub fn next_token(&mut self) -> Option<I::Token> {
-        self.next().1
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        self.input.span(start, end)
     }
 
-    pub fn peek(&self) -> (I::Offset, Option<I::Token>) {
-        self.input.next(self.offset)
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        self.input.start()
     }
 
-    #[inline(always)]
-    pub fn peek_token(&self) -> Option<I::Token> {
-        self.peek().1
+    #[inline]
+    fn source_id(&self) -> Option<&SourceId> {
+        self.input.source_id()
     }
 
     #[inline]
-    pub fn rewind(&mut self, offset: I::Offset) {
-        self.offset = offset;
+    fn encoding_error(&self, offset: Self::Offset) -> Option<EncodingError> {
+        self.input.encoding_error(offset)
     }
 
+    // The errors of the input are about the tokens before they were transformed, so only the
+    // diagnostics are handed over.
     #[inline]
-    pub fn start(&self) -> I::Offset {
-        self.input.start()
+    fn take_diagnostics(&self, end: Self::Offset) -> Vec<Diagnostic> {
+        self.input.take_diagnostics(end)
     }
+}
+
+impl<'input, I, F, U> SliceInput<'input> for MapTokens<I, F, U>
+where
+    I: SliceInput<'input>,
+    F: Fn(I::Token) -> U + 'input,
+    U: Copy + Eq + 'input,
+{
+    type Slice = I::Slice;
 
     #[inline(always)]
-    pub fn offset(&self) -> I::Offset {
-        self.offset
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        self.input.slice(start, end)
     }
 
     #[inline(always)]
-    pub fn slice(&self, start: I::Offset, end: I::Offset) -> I::Slice {
-        self.input.slice(start, end)
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        self.input.slice_to_end(start)
+    }
+}
+
+// Skips the trivia of a language, e.g white space and comments, at the stream level: the parsers
+// running on the input never see them, so terminals don't need to be `padded`. Trivia are skipped
+// after each token, so offsets, and the spans of errors, always point at tokens. The slices of the
+// input may end with trivia though. The diagnostics emitted and the errors recovered from while
+// skipping trivia are handed over to the parse, see `Input::take_diagnostics`.
+pub struct SkipTrivia<'input, I: Input<'input>, P, O> {
+    input: I,
+    trivia: P,
+    // Buffers of the `InputRef` the trivia are parsed with, reused from one token to the next.
+    scratch: RefCell<Scratch<I::Offset>>,
+    // What skipping the trivia at an offset emitted. Trivia are skipped again whenever the parse
+    // goes back over them, the last run replaces the previous ones.
+    diagnostics: RefCell<BTreeMap<usize, Vec<Diagnostic>>>,
+    errors: RefCell<BTreeMap<usize, Vec<ErrorReport<I::Token>>>>,
+    phantom: PhantomData<O>,
+}
+
+impl<'input, I, P, O> SkipTrivia<'input, I, P, O>
+where
+    I: Input<'input>,
+{
+    pub fn new(input: I, trivia: P) -> Self {
+        Self {
+            input,
+            trivia,
+            scratch: RefCell::default(),
+            diagnostics: RefCell::default(),
+            errors: RefCell::default(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I, P, O> fmt::Debug for SkipTrivia<'input, I, P, O>
+where
+    I: Input<'input> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SkipTrivia")
+            .field("input", &self.input)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'input, I, P, O> SkipTrivia<'input, I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+{
+    fn skip(&self, offset: I::Offset) -> I::Offset {
+        let scratch = self.scratch.take();
+        let mut input_ref = InputRef::with_scratch(&self.input, ParseConfig::default(), scratch);
+        input_ref.move_to(offset);
+        // Only the outcome matters, the trivia's outputs aren't built.
+        let end = loop {
+            let before = input_ref.raw_offset();
+            let checkpoint = input_ref.save();
+            match self.trivia.go_check(&mut input_ref) {
+                Ok(()) if input_ref.raw_offset() != before => input_ref.commit(checkpoint),
+                _ => {
+                    // What the failed attempt emitted is thrown away along with it.
+                    input_ref.restore(checkpoint).ok();
+                    break before;
+                }
+            }
+        };
+
+        let at = offset.into();
+        if input_ref.diagnostics.is_empty() {
+            self.diagnostics.borrow_mut().remove(&at);
+        } else {
+            let diagnostics = std::mem::take(&mut input_ref.diagnostics);
+            self.diagnostics.borrow_mut().insert(at, diagnostics);
+        }
+        if input_ref.errors.is_empty() {
+            self.errors.borrow_mut().remove(&at);
+        } else {
+            let errors = std::mem::take(&mut input_ref.errors);
+            self.errors.borrow_mut().insert(at, errors);
+        }
+        self.scratch.replace(input_ref.into_scratch());
+        end
+    }
+}
+
+// Entries of `reports` recorded before `end`, in order.
+fn take_before<T>(reports: &RefCell<BTreeMap<usize, Vec<T>>>, end: usize) -> Vec<T> {
+    let mut reports = reports.borrow_mut();
+    let after = reports.split_off(&end);
+    std::mem::replace(&mut *reports, after)
+        .into_values()
+        .flatten()
+        .collect()
+}
+
+impl<'input, I, P, O> Input<'input> for SkipTrivia<'input, I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O> + 'input,
+    O: 'input,
+{
+    type Token = I::Token;
+
+    type Offset = I::Offset;
+
+    type Span = I::Span;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.input.is_valid_offset(offset)
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.input.next(offset) {
+            (next, Some(token)) => (self.skip(next), Some(token)),
+            (next, None) => (next, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        self.input.span(start, end)
+    }
+
+    fn start(&self) -> Self::Offset {
+        self.skip(self.input.start())
+    }
+
+    #[inline]
+    fn source_id(&self) -> Option<&SourceId> {
+        self.input.source_id()
+    }
+
+    #[inline]
+    fn encoding_error(&self, offset: Self::Offset) -> Option<EncodingError> {
+        self.input.encoding_error(offset)
+    }
+
+    fn take_diagnostics(&self, end: Self::Offset) -> Vec<Diagnostic> {
+        let mut diagnostics = self.input.take_diagnostics(end);
+        diagnostics.extend(take_before(&self.diagnostics, end.into()));
+        diagnostics
+    }
+
+    fn take_errors(&self, end: Self::Offset) -> Vec<ErrorReport<Self::Token>> {
+        let mut errors = self.input.take_errors(end);
+        errors.extend(take_before(&self.errors, end.into()));
+        errors
+    }
+}
+
+impl<'input, I, P, O> SliceInput<'input> for SkipTrivia<'input, I, P, O>
+where
+    I: SliceInput<'input>,
+    P: Parser<'input, I, O> + 'input,
+    O: 'input,
+{
+    type Slice = I::Slice;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        self.input.slice(start, end)
+    }
+
+    #[inline(always)]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        self.input.slice_to_end(start)
+    }
+}
+
+// Stack left below which a nested level runs on a new stack segment of `STACK_GROWTH` bytes, see
+// `InputRef::nest`.
+#[cfg(feature = "stacker")]
+const STACK_RED_ZONE: usize = 64 * 1024;
+#[cfg(feature = "stacker")]
+const STACK_GROWTH: usize = 1024 * 1024;
+
+// Number of leftover tokens shown when the end of input was expected.
+const TRAILING_PREVIEW_LEN: usize = 16;
+
+// Counters of how the input was consumed during a parse, see `Parser::parse_with_stats`. Amounts
+// are in offsets converted to `usize`, e.g bytes for text. Lots of input backtracked over points
+// at alternatives that share long prefixes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    tokens: usize,
+    advanced: usize,
+    rewinds: usize,
+    backtracked: usize,
+    furthest: usize,
+    memo_hits: usize,
+    memo_misses: usize,
+}
+
+impl ParseStats {
+    // Tokens consumed, the ones consumed again after backtracking included.
+    #[inline]
+    pub fn tokens(&self) -> usize {
+        self.tokens
+    }
+
+    // Input advanced over, backtracking included.
+    #[inline]
+    pub fn advanced(&self) -> usize {
+        self.advanced
+    }
+
+    // Number of times the parse went back in the input.
+    #[inline]
+    pub fn rewinds(&self) -> usize {
+        self.rewinds
+    }
+
+    // Input gone back over.
+    #[inline]
+    pub fn backtracked(&self) -> usize {
+        self.backtracked
+    }
+
+    // Furthest offset reached.
+    #[inline]
+    pub fn furthest(&self) -> usize {
+        self.furthest
+    }
+
+    // Number of times a rule's result was reused rather than parsed again, see `Parser::rule`.
+    #[inline]
+    pub fn memo_hits(&self) -> usize {
+        self.memo_hits
+    }
+
+    // Number of times a rule was parsed as its result wasn't cached yet, see `Parser::rule`.
+    #[inline]
+    pub fn memo_misses(&self) -> usize {
+        self.memo_misses
+    }
+}
+
+// Where a parse spent its time, see `Parser::parse_with_profile`.
+#[derive(Debug, Clone, Default)]
+pub struct ParseProfile {
+    stats: ParseStats,
+    elapsed: Duration,
+    rules: Vec<RuleProfile>,
+}
+
+impl ParseProfile {
+    #[inline]
+    pub fn stats(&self) -> ParseStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    // Tokens consumed per second, backtracking included.
+    pub fn tokens_per_sec(&self) -> f64 {
+        self.stats.tokens as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    // Counters of each `labelled` parser, in the order they were first entered.
+    #[inline]
+    pub fn rules(&self) -> &[RuleProfile] {
+        &self.rules
+    }
+
+    pub fn rule(&self, name: &str) -> Option<&RuleProfile> {
+        self.rules.iter().find(|rule| rule.name == name)
+    }
+}
+
+// Counters of a `labelled` parser, see `ParseProfile`. The rewinds are the ones made by the
+// parsers it's made of, not counting the `labelled` parsers nested within, e.g a rule trying
+// alternatives that share long prefixes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleProfile {
+    name: &'static str,
+    calls: usize,
+    rewinds: usize,
+    backtracked: usize,
+}
+
+impl RuleProfile {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn calls(&self) -> usize {
+        self.calls
+    }
+
+    #[inline]
+    pub fn rewinds(&self) -> usize {
+        self.rewinds
+    }
+
+    #[inline]
+    pub fn backtracked(&self) -> usize {
+        self.backtracked
+    }
+}
+
+// Counters of the `labelled` parsers kept while profiling, see `InputRef::enter_rule`.
+#[derive(Default)]
+struct Profiler {
+    rules: Vec<RuleProfile>,
+    // Rules being parsed, innermost last, as indices in `rules`.
+    active: Vec<usize>,
+}
+
+// Settings of a parse run, see `Parser::parse_with_config`.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseConfig {
+    max_errors: Option<usize>,
+    max_context_depth: usize,
+    max_depth: usize,
+    max_backtrack: Option<usize>,
+    packrat: Option<usize>,
+    partial: bool,
+    zero_alloc: bool,
+}
+
+impl Default for ParseConfig {
+    fn default() -> Self {
+        Self {
+            max_errors: None,
+            max_context_depth: 8,
+            max_depth: 256,
+            max_backtrack: None,
+            packrat: None,
+            partial: false,
+            zero_alloc: false,
+        }
+    }
+}
+
+impl ParseConfig {
+    // Maximum number of `labelled` breadcrumbs kept on an error, the outermost ones are dropped.
+    // 8 by default.
+    pub fn max_context_depth(mut self, max_context_depth: usize) -> Self {
+        self.max_context_depth = max_context_depth;
+        self
+    }
+
+    // Maximum number of nested levels (see `InputRef::nest`) before the parse is aborted, so that
+    // deeply nested input, e.g "((((...", can't overflow the stack. 256 by default.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Maximum amount of input (see `ParseStats::backtracked`) gone back over before the parse is
+    // aborted, so that pathological input can't make a grammar with lots of alternatives take
+    // exponential time. Unlimited by default.
+    pub fn max_backtrack(mut self, max_backtrack: usize) -> Self {
+        self.max_backtrack = Some(max_backtrack);
+        self
+    }
+
+    // Caches the results of the rules of the grammar (see `Parser::rule`) by offset, so that no
+    // rule parses the same input twice and the parse takes linear time whatever the grammar
+    // backtracks over. Each rule keeps up to `capacity` results, the one furthest behind in the
+    // input is evicted to make room past that. Disabled by default.
+    pub fn packrat(mut self, capacity: usize) -> Self {
+        self.packrat = Some(capacity);
+        self
+    }
+
+    // Whether the input is a chunk of a stream that more input may follow. Running out of a
+    // partial input makes the parse fail with `ParseError::Incomplete` rather than with a
+    // mismatch. Disabled by default.
+    pub fn partial(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    // Maximum number of errors recovered from (see `Parser::recover_with`) before the parse is
+    // aborted with a summary error. Unlimited by default.
+    pub fn max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    // Makes sure that the parse doesn't allocate unless it fails, for embedded and low latency
+    // uses, at the cost of less detailed errors: the failures at the same offset aren't merged,
+    // the latest one is reported, and no labels or breadcrumbs are attached to it. Only parsers
+    // built from the following are covered, the others may allocate on their own:
+    // - `exact`, `tag`, `any`, `end`, `take_while_in` and `take_until`,
+    // - `and`, `left_bind`, `right_bind`, `or`, `choice`, `or_not`, `padded`,
+    //   `delimited_by`, `labelled`, `map`, `filter` and `nested`, provided their closures don't
+    //   allocate,
+    // - the `to_slice`, `fold_collect`, `for_each`, `last` and `collect_array` repetitions.
+    // Disabled by default.
+    pub fn zero_alloc(mut self, zero_alloc: bool) -> Self {
+        self.zero_alloc = zero_alloc;
+        self
+    }
+}
+
+// State of a parse saved by `InputRef::save`. It's restored or committed at most once, by the
+// `InputRef` that saved it: debug builds check it, e.g that a checkpoint of a buffer since
+// compacted isn't restored on the next parse.
+#[derive(Debug)]
+pub struct Checkpoint<O> {
+    offset: O,
+    // Lengths of the stacks of diagnostics and recovered errors.
+    diagnostics: usize,
+    errors: usize,
+    #[cfg(debug_assertions)]
+    generation: u64,
+}
+
+impl<O> Checkpoint<O>
+where
+    O: Copy,
+{
+    // Offset the parse will resume from once restored.
+    #[inline]
+    pub fn offset(&self) -> Offset<O> {
+        Offset {
+            offset: self.offset,
+            #[cfg(debug_assertions)]
+            generation: self.generation,
+        }
+    }
+}
+
+// Offset in the input of an `InputRef`, e.g `InputRef::offset`, to slice the input or rewind the
+// cursor with later on. Like a `Checkpoint`, it belongs to the `InputRef` it comes from: debug
+// builds check it wherever it's handed back, e.g that the offset of another input isn't sliced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offset<O> {
+    offset: O,
+    #[cfg(debug_assertions)]
+    generation: u64,
+}
+
+impl<O> Offset<O>
+where
+    O: Copy,
+{
+    // The offset as the input knows it, see `Input::Offset`.
+    #[inline]
+    pub fn get(&self) -> O {
+        self.offset
+    }
+}
+
+impl<O> From<Offset<O>> for usize
+where
+    O: Into<usize>,
+{
+    #[inline]
+    fn from(offset: Offset<O>) -> usize {
+        offset.offset.into()
+    }
+}
+
+impl<O> PartialEq<usize> for Offset<O>
+where
+    O: Copy + Into<usize>,
+{
+    #[inline]
+    fn eq(&self, other: &usize) -> bool {
+        self.offset.into() == *other
+    }
+}
+
+// Position reached by a parse, kept to resume parsing from there later on, possibly with another
+// grammar, see `Parser::parse_from`. Unlike a `Checkpoint`, it can outlive the parse it comes
+// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mark<O, T> {
+    offset: O,
+    // Token found at the offset, checked on resume along with the source id to catch marks of
+    // another input.
+    token: Option<T>,
+    source_id: Option<SourceId>,
+}
+
+impl<O, T> Mark<O, T>
+where
+    O: Copy,
+{
+    #[inline]
+    pub fn offset(&self) -> O {
+        self.offset
+    }
+}
+
+// Handle of an input held by value, see `Input::Handle`.
+#[derive(Debug, Clone, Copy)]
+pub struct ByValue<I>(pub I);
+
+impl<I> Deref for ByValue<I> {
+    type Target = I;
+
+    #[inline(always)]
+    fn deref(&self) -> &I {
+        &self.0
+    }
+}
+
+// Mark of a position in an input of type `I`.
+pub type InputMark<'input, I> = Mark<<I as Input<'input>>::Offset, <I as Input<'input>>::Token>;
+
+// Tells the `InputRef`s apart, see `Checkpoint` and `Parser::rule`.
+fn next_generation() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+// Cursor over an input, handed to the parsers. The input is held through its `Input::Handle`: by
+// value if it's cheap to copy, e.g `&str`, by reference otherwise, e.g `String`.
+pub struct InputRef<'input, 'parse, I>
+where
+    I: Input<'input>,
+{
+    input: I::Handle<'parse>,
+    offset: I::Offset,
+    // Furthest failure recorded so far, along with the offset at which it happened.
+    error: Option<(usize, ErrorReport<I::Token>)>,
+    // See `error_generation`.
+    error_generation: usize,
+    // Warnings and notes emitted so far.
+    diagnostics: Vec<Diagnostic>,
+    // Errors recovered from so far.
+    errors: Vec<ErrorReport<I::Token>>,
+    // Number of nested levels entered, see `nest`.
+    depth: usize,
+    stats: ParseStats,
+    config: ParseConfig,
+    generation: u64,
+    // Left recursive rules being parsed, innermost last, see `Parser::left_recursive`.
+    seeds: Vec<Seed<I::Offset>>,
+    // Only kept when profiling, see `Parser::parse_with_profile`.
+    profiler: Option<Box<Profiler>>,
+    // The input outlives the parse, whether it's held by value or not.
+    phantom: PhantomData<&'parse I>,
+}
+
+// Buffers of an `InputRef` carried over from one parse to the next, see `InputRef::into_scratch`.
+pub(crate) struct Scratch<Off> {
+    diagnostics: Vec<Diagnostic>,
+    seeds: Vec<Seed<Off>>,
+}
+
+impl<Off> Default for Scratch<Off> {
+    fn default() -> Self {
+        Scratch {
+            diagnostics: Vec::new(),
+            seeds: Vec::new(),
+        }
+    }
+}
+
+// Result a left recursive rule grew so far at an offset, what the rule parses to when it calls
+// itself there.
+struct Seed<O> {
+    rule: &'static str,
+    offset: O,
+    // Output along with where it ends and the number of tokens it spans, `None` until the rule
+    // first matches.
+    grown: Option<(Box<dyn Any>, O, usize)>,
+}
+
+impl<'input, 'parse, I> InputRef<'input, 'parse, I>
+where
+    I: Input<'input>,
+{
+    pub fn new(input: &'parse I) -> Self {
+        Self::with_config(input, ParseConfig::default())
+    }
+
+    pub fn with_config(input: &'parse I, config: ParseConfig) -> Self {
+        Self::with_scratch(input, config, Scratch::default())
+    }
+
+    // Same as `with_config` but with the buffers of a previous parse, see `into_scratch`.
+    pub(crate) fn with_scratch(
+        input: &'parse I,
+        config: ParseConfig,
+        scratch: Scratch<I::Offset>,
+    ) -> Self {
+        Self {
+            input: input.handle(),
+            offset: input.start(),
+            error: None,
+            error_generation: 0,
+            diagnostics: scratch.diagnostics,
+            errors: Vec::new(),
+            depth: 0,
+            stats: ParseStats::default(),
+            config,
+            generation: next_generation(),
+            seeds: scratch.seeds,
+            profiler: None,
+            phantom: PhantomData,
+        }
+    }
+
+    // Buffers left once the parse is over, emptied, so that the next parse reuses their room
+    // rather than allocating it again, see `Parser::parse_all`.
+    pub(crate) fn into_scratch(mut self) -> Scratch<I::Offset> {
+        self.diagnostics.clear();
+        self.seeds.clear();
+        Scratch {
+            diagnostics: self.diagnostics,
+            seeds: self.seeds,
+        }
+    }
+
+    // Starts keeping the counters of the `labelled` parsers, see `ParseProfile`.
+    pub(crate) fn profile(&mut self) {
+        self.profiler = Some(Box::default());
+    }
+
+    // Counters of the parse so far, with the time it took.
+    pub(crate) fn take_profile(&mut self, elapsed: Duration) -> ParseProfile {
+        ParseProfile {
+            stats: self.stats,
+            elapsed,
+            rules: self
+                .profiler
+                .take()
+                .map_or(Vec::new(), |profiler| profiler.rules),
+        }
+    }
+
+    // Counts the rewinds from now on against the `labelled` parser `name` while profiling, until
+    // `exit_rule`.
+    #[inline]
+    pub(crate) fn enter_rule(&mut self, name: &'static str) {
+        let Some(profiler) = &mut self.profiler else {
+            return;
+        };
+        let idx = match profiler.rules.iter().position(|rule| rule.name == name) {
+            Some(idx) => idx,
+            None => {
+                profiler.rules.push(RuleProfile {
+                    name,
+                    calls: 0,
+                    rewinds: 0,
+                    backtracked: 0,
+                });
+                profiler.rules.len() - 1
+            }
+        };
+        profiler.rules[idx].calls += 1;
+        profiler.active.push(idx);
+    }
+
+    #[inline]
+    pub(crate) fn exit_rule(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.active.pop();
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> (Offset<I::Offset>, Option<I::Token>) {
+        let (next_offset, next) = self.input.next(self.offset);
+        if next.is_some() {
+            self.advance(next_offset, 1);
+        }
+        (self.offset(), next)
+    }
+
+    // Moves past `seq` at once if the input can compare it as a whole, see `Input::match_str`.
+    pub(crate) fn match_sequence<S>(&mut self, seq: &S) -> bool
+    where
+        S: OrderedSequence<Token = I::Token>,
+    {
+        let matched = if let Some(text) = seq.as_str() {
+            (self.input.match_str(self.offset, text)).map(|end| (end, text.chars().count()))
+        } else if let Some(tokens) = seq.as_tokens() {
+            (self.input.match_tokens(self.offset, tokens)).map(|end| (end, tokens.len()))
+        } else {
+            None
+        };
+
+        match matched {
+            Some((end, tokens)) => {
+                self.advance(end, tokens);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Offset past the longest start of `prefix` under the cursor, see `Input::match_prefix`.
+    #[inline]
+    pub(crate) fn match_prefix(&self, prefix: &[I::Token]) -> Option<(I::Offset, usize)> {
+        self.input.match_prefix(self.offset, prefix)
+    }
+
+    // Moves the cursor forward to `to`, `tokens` tokens ahead.
+    pub(crate) fn advance(&mut self, to: I::Offset, tokens: usize) {
+        let (from, to_usize) = (self.offset.into(), to.into());
+        self.stats.tokens += tokens;
+        self.stats.advanced += to_usize - from;
+        self.stats.furthest = self.stats.furthest.max(to_usize);
+        self.offset = to;
+    }
+
+    #[inline]
+    pub fn stats(&self) -> ParseStats {
+        self.stats
+    }
+
+    #[inline]
+    pub(crate) fn count_memo_hit(&mut self) {
+        self.stats.memo_hits += 1;
+    }
+
+    pub(crate) fn count_memo_miss(&mut self) {
+        self.stats.memo_misses += 1;
+    }
+
+    #[inline]
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Seed of `rule` if it's being grown at `offset`: `Some(None)` if it didn't match yet.
+    pub(crate) fn seed<O>(
+        &self,
+        rule: &'static str,
+        offset: I::Offset,
+    ) -> Option<Option<(O, I::Offset, usize)>>
+    where
+        O: Clone + 'static,
+    {
+        let seed =
+            (self.seeds.iter().rev()).find(|seed| seed.rule == rule && seed.offset == offset)?;
+        Some(seed.grown.as_ref().map(|(out, end, tokens)| {
+            let out = out
+                .downcast_ref::<O>()
+                .expect("rules sharing a name have the same output");
+            (out.clone(), *end, *tokens)
+        }))
+    }
+
+    pub(crate) fn push_seed(&mut self, rule: &'static str, offset: I::Offset) {
+        self.seeds.push(Seed {
+            rule,
+            offset,
+            grown: None,
+        });
+    }
+
+    // Grows the innermost seed.
+    pub(crate) fn grow_seed<O>(&mut self, grown: Option<(O, I::Offset, usize)>)
+    where
+        O: 'static,
+    {
+        let seed = self.seeds.last_mut().expect("a seed is being grown");
+        seed.grown = grown.map(|(out, end, tokens)| (Box::new(out) as Box<dyn Any>, end, tokens));
+    }
+
+    pub(crate) fn pop_seed(&mut self) {
+        self.seeds.pop();
+    }
+
+    #[inline]
+    pub(crate) fn packrat(&self) -> Option<usize> {
+        self.config.packrat
+    }
+
+    fn count_rewind(&mut self, to: I::Offset) {
+        let (from, to) = (self.offset.into(), to.into());
+        if to < from {
+            self.stats.rewinds += 1;
+            self.stats.backtracked += from - to;
+            if let Some(profiler) = &mut self.profiler {
+                if let Some(&idx) = profiler.active.last() {
+                    profiler.rules[idx].rewinds += 1;
+                    profiler.rules[idx].backtracked += from - to;
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn next_offset(&mut self) -> Offset<I::Offset> {
+        self.next().0
+    }
+
+    #[inline(always)]
+    pub fn next_token(&mut self) -> Option<I::Token> {
+        self.next().1
+    }
+
+    pub fn peek(&self) -> (Offset<I::Offset>, Option<I::Token>) {
+        let (next_offset, token) = self.input.next(self.offset);
+        (self.tag(next_offset), token)
+    }
+
+    #[inline(always)]
+    pub(crate) fn peek_at(&self, offset: I::Offset) -> (I::Offset, Option<I::Token>) {
+        self.input.next(offset)
+    }
+
+    // Offset of the token boundary at `to` past `from`, or the closest one before it, e.g to turn
+    // the offset of an error back into one of the input.
+    pub(crate) fn seek(&self, from: Offset<I::Offset>, to: usize) -> Offset<I::Offset> {
+        let mut from = self.untag(from);
+        while from.into() < to {
+            match self.input.next(from) {
+                (next, Some(_)) if next.into() <= to => from = next,
+                _ => break,
+            }
+        }
+        self.tag(from)
+    }
+
+    #[inline(always)]
+    pub fn peek_token(&self) -> Option<I::Token> {
+        self.peek().1
+    }
+
+    // Looks `n` tokens past the one under the cursor, `peek_n(0)` being `peek()`. The offset
+    // returned is the one past the token.
+    pub fn peek_n(&self, n: usize) -> (Offset<I::Offset>, Option<I::Token>) {
+        let mut offset = self.offset;
+        for _ in 0..n {
+            match self.input.next(offset) {
+                (next, Some(_)) => offset = next,
+                (next, None) => return (self.tag(next), None),
+            }
+        }
+        let (next_offset, token) = self.input.next(offset);
+        (self.tag(next_offset), token)
+    }
+
+    // Tokens from the cursor on, without moving it. e.g to tell `<=` from `<` or check that a
+    // keyword isn't followed by an identifier character.
+    pub fn lookahead(&self) -> impl Iterator<Item = I::Token> + '_ {
+        let (input, mut offset) = (&*self.input, self.offset);
+        std::iter::from_fn(move || {
+            let (next, token) = input.next(offset);
+            offset = next;
+            token
+        })
+    }
+
+    #[inline]
+    pub fn span(&self, start: Offset<I::Offset>, end: Offset<I::Offset>) -> I::Span {
+        self.input.span(self.untag(start), self.untag(end))
+    }
+
+    // Saves the state of the parse before an attempt that may have to be rolled back, e.g an
+    // alternative. Unless its failure propagates, the attempt ends with either `restore` or
+    // `commit`.
+    #[inline]
+    pub fn save(&self) -> Checkpoint<I::Offset> {
+        Checkpoint {
+            offset: self.offset,
+            diagnostics: self.diagnostics.len(),
+            errors: self.errors.len(),
+            #[cfg(debug_assertions)]
+            generation: self.generation,
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn check(&self, checkpoint: &Checkpoint<I::Offset>) {
+        assert_eq!(
+            checkpoint.generation, self.generation,
+            "checkpoint saved by another parse"
+        );
+        assert!(
+            checkpoint.diagnostics <= self.diagnostics.len()
+                && checkpoint.errors <= self.errors.len(),
+            "checkpoint saved within an attempt since rolled back"
+        );
+    }
+
+    pub fn mark(&self) -> InputMark<'input, I> {
+        Mark {
+            offset: self.offset,
+            token: self.peek_token(),
+            source_id: self.input.source_id().cloned(),
+        }
+    }
+
+    // Moves the cursor to `mark`. If the mark doesn't belong to the input, i.e the source id or
+    // the token found at the mark differ, the parse is aborted with a `StaleMark` error. Marks
+    // lying past the end of the input, or within a token, don't belong to it either. Going back
+    // counts against `ParseConfig::max_backtrack` as `restore` does.
+    pub fn resume(&mut self, mark: &InputMark<'input, I>) -> Result<(), ParseError> {
+        if mark.source_id.as_ref() == self.input.source_id()
+            && self.input.is_valid_offset(mark.offset)
+            && self.input.next(mark.offset).1 == mark.token
+        {
+            self.count_rewind(mark.offset);
+            self.offset = mark.offset;
+            return self.check_budget();
+        }
+
+        let (end, found) = self.peek();
+        let custom = CustomError::new("the mark resumed from doesn't belong to the input")
+            .with_code(ErrorCode::StaleMark);
+        self.set_error(
+            self.offset.into(),
+            ErrorReport::custom(self.offset.into()..end.into(), found, custom),
+        );
+        Err(ParseError::Fatal(None))
+    }
+
+    // Rolls back everything parsed since `checkpoint` was saved, including the diagnostics
+    // emitted and the errors recovered from. The furthest failure is kept, it may still be the
+    // one to report. Once more input than `ParseConfig::max_backtrack` was gone back over, the
+    // parse is aborted with a `BudgetExceeded` error.
+    #[inline]
+    pub fn restore(&mut self, checkpoint: Checkpoint<I::Offset>) -> Result<(), ParseError> {
+        #[cfg(debug_assertions)]
+        self.check(&checkpoint);
+        self.count_rewind(checkpoint.offset);
+        self.offset = checkpoint.offset;
+        self.diagnostics.truncate(checkpoint.diagnostics);
+        self.errors.truncate(checkpoint.errors);
+        self.check_budget()
+    }
+
+    fn check_budget(&mut self) -> Result<(), ParseError> {
+        match self.config.max_backtrack {
+            Some(max_backtrack) if self.stats.backtracked > max_backtrack => {
+                let (end, found) = self.peek();
+                let custom = CustomError::new(format!(
+                    "the parse backtracked over {} units of input, more than the maximum of {}",
+                    self.stats.backtracked, max_backtrack
+                ))
+                .with_code(ErrorCode::BudgetExceeded);
+                self.set_error(
+                    self.offset.into(),
+                    ErrorReport::custom(self.offset.into()..end.into(), found, custom),
+                );
+                Err(ParseError::Fatal(None))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Whether diagnostics were emitted or errors recovered from since `checkpoint` was saved.
+    pub(crate) fn emitted_since(&self, checkpoint: &Checkpoint<I::Offset>) -> bool {
+        self.diagnostics.len() > checkpoint.diagnostics || self.errors.len() > checkpoint.errors
+    }
+
+    // Keeps everything parsed since `checkpoint` was saved.
+    #[inline]
+    pub fn commit(&mut self, checkpoint: Checkpoint<I::Offset>) {
+        #[cfg(debug_assertions)]
+        self.check(&checkpoint);
+        _ = checkpoint;
+    }
+
+    // Rewinding abandons everything parsed past `offset`, including the diagnostics emitted and
+    // the errors recovered from there. Prefer `save` and `restore` to roll back attempts. Going
+    // back counts against `ParseConfig::max_backtrack` as `restore` does.
+    #[inline]
+    pub fn rewind(&mut self, offset: Offset<I::Offset>) -> Result<(), ParseError> {
+        self.move_to(self.untag(offset));
+        self.check_budget()
+    }
+
+    // Moves the cursor to `offset`, e.g one reached by an earlier `InputRef` over the same input.
+    pub(crate) fn move_to(&mut self, offset: I::Offset) {
+        self.count_rewind(offset);
+        self.offset = offset;
+        while self
+            .diagnostics
+            .last()
+            .is_some_and(|diagnostic| diagnostic.span().start >= offset.into())
+        {
+            self.diagnostics.pop();
+        }
+        while self
+            .errors
+            .last()
+            .is_some_and(|error| error.span().start >= offset.into())
+        {
+            self.errors.pop();
+        }
+    }
+
+    #[inline]
+    pub fn start(&self) -> Offset<I::Offset> {
+        self.tag(self.input.start())
+    }
+
+    #[inline(always)]
+    pub fn offset(&self) -> Offset<I::Offset> {
+        self.tag(self.offset)
+    }
+
+    // Offset of the cursor as the input knows it, see `Input::Offset`.
+    #[inline(always)]
+    pub(crate) fn raw_offset(&self) -> I::Offset {
+        self.offset
+    }
+
+    #[inline(always)]
+    pub(crate) fn tag(&self, offset: I::Offset) -> Offset<I::Offset> {
+        Offset {
+            offset,
+            #[cfg(debug_assertions)]
+            generation: self.generation,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn untag(&self, offset: Offset<I::Offset>) -> I::Offset {
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            offset.generation, self.generation,
+            "offset of another parse"
+        );
+        offset.offset
+    }
+
+    fn set_error(&mut self, at: usize, report: ErrorReport<I::Token>) {
+        self.error = Some((at, report));
+        self.error_generation += 1;
+    }
+
+    // Number of times the furthest failure changed so far. Saved before an attempt, it tells
+    // whether the furthest failure was recorded during the attempt rather than by an earlier one
+    // that went further, see `push_error_context`.
+    #[inline]
+    pub fn error_generation(&self) -> usize {
+        self.error_generation
+    }
+
+    // Records a failure. Only the failure that happened the furthest in the input is kept as it is
+    // usually the most relevant one to report. On ties, the latest failure wins.
+    pub fn record_error(&mut self, report: ErrorReport<I::Token>) {
+        self.record_error_at(report.span().start, report);
+    }
+
+    // When several alternatives fail at the same offset, what they expected is merged.
+    pub(crate) fn record_error_at(&mut self, at: usize, mut report: ErrorReport<I::Token>) {
+        match &mut self.error {
+            Some((error_at, _)) if *error_at > at => {}
+            Some((error_at, error))
+                if *error_at == at
+                    && error.message().is_none()
+                    && report.message().is_none()
+                    && !self.config.zero_alloc =>
+            {
+                let mut expected = error.take_expected();
+                expected.union(report.take_expected());
+                report.set_expected(expected);
+                *error = report;
+                self.error_generation += 1;
+            }
+            _ => self.set_error(at, report),
+        }
+    }
+
+    // Runs `f` one nested level deeper, e.g a recursive parser parsing the inside of parentheses.
+    // Past `ParseConfig::max_depth` levels, the parse is aborted with a `DepthExceeded` error. With
+    // the `stacker` feature, the stack grows on demand rather than overflowing: nesting is then
+    // only limited by `max_depth` and memory.
+    pub fn nest<O>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<O>) -> ParseResult<O> {
+        if self.depth >= self.config.max_depth {
+            let (end, found) = self.peek();
+            let custom = CustomError::new(format!(
+                "nesting exceeds the maximum depth of {}",
+                self.config.max_depth
+            ))
+            .with_code(ErrorCode::DepthExceeded);
+            self.set_error(
+                self.offset.into(),
+                ErrorReport::custom(self.offset.into()..end.into(), found, custom),
+            );
+            return Err(ParseError::Fatal(None));
+        }
+
+        self.depth += 1;
+        #[cfg(feature = "stacker")]
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || f(self));
+        #[cfg(not(feature = "stacker"))]
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    #[inline]
+    pub fn is_partial(&self) -> bool {
+        self.config.partial
+    }
+
+    // The error to fail with once the token under the cursor didn't match. Running out of a
+    // partial input isn't a mismatch though, `needed` more tokens are requested instead.
+    pub fn mismatch(&self, needed: Option<usize>) -> ParseError {
+        self.mismatch_at(self.offset, needed)
+    }
+
+    pub(crate) fn mismatch_at(&self, offset: I::Offset, needed: Option<usize>) -> ParseError {
+        if self.config.partial
+            && self.input.next(offset).1.is_none()
+            && self.input.encoding_error(offset).is_none()
+        {
+            ParseError::Incomplete { needed }
+        } else {
+            ParseError::SyntaxError
+        }
+    }
+
+    #[inline]
+    pub(crate) fn furthest_error(&self) -> Option<&(usize, ErrorReport<I::Token>)> {
+        self.error.as_ref()
+    }
+
+    // Offset of the furthest failure recorded so far.
+    #[inline]
+    pub fn error_offset(&self) -> Option<usize> {
+        self.error.as_ref().map(|(at, _)| *at)
+    }
+
+    // Invalid sequence under the cursor, if the input decodes text.
+    #[inline]
+    pub fn encoding_error(&self) -> Option<EncodingError> {
+        self.input.encoding_error(self.offset)
+    }
+
+    // Records a failure on an invalid sequence of the input. It's reported as is, whatever was
+    // expected there.
+    pub fn record_encoding_error(&mut self, error: EncodingError) {
+        let custom = CustomError::new(error.to_string())
+            .with_code(ErrorCode::InvalidEncoding)
+            .with_cause(error.clone());
+        self.record_error(ErrorReport::custom(error.span(), None, custom));
+    }
+
+    // Records a failure on the token under the cursor.
+    pub fn record_expected(&mut self, expected: impl Into<ExpectedSet<I::Token>>) {
+        self.record_expected_at(self.offset, expected);
+    }
+
+    // Records a failure on the token at `offset`, for primitives looking ahead of the cursor.
+    pub(crate) fn record_expected_at(
+        &mut self,
+        offset: I::Offset,
+        expected: impl Into<ExpectedSet<I::Token>>,
+    ) {
+        let (end, found) = self.input.next(offset);
+        if let Some(error) = found
+            .is_none()
+            .then(|| self.input.encoding_error(offset))
+            .flatten()
+        {
+            return self.record_encoding_error(error);
+        }
+        self.record_error(ErrorReport::new(offset.into()..end.into(), expected, found));
+    }
+
+    // Records a failure on the input left over when the end of input was expected. The report
+    // spans and previews at most `TRAILING_PREVIEW_LEN` tokens of it.
+    pub fn record_trailing_input(&mut self) {
+        let start = self.offset;
+        let mut end = start;
+        let mut preview = Vec::new();
+        let truncated = loop {
+            match self.input.next(end) {
+                (_, Some(_)) if preview.len() == TRAILING_PREVIEW_LEN => break true,
+                (next, Some(token)) => {
+                    preview.push(token);
+                    end = next;
+                }
+                (_, None) => break false,
+            }
+        };
+
+        let found = preview.first().copied();
+        self.record_error(
+            ErrorReport::new(start.into()..end.into(), Expected::EndOfInput, found)
+                .with_trailing(Trailing::new(preview, truncated)),
+        );
+    }
+
+    // Attaches a secondary label to the furthest failure, provided it happened after `span` and
+    // was recorded since the error generation was `generation`.
+    pub fn label_error(
+        &mut self,
+        generation: usize,
+        span: Range<usize>,
+        message: impl Into<String>,
+    ) {
+        if let Some((at, report)) = &mut self.error {
+            if self.error_generation > generation && *at >= span.end && !self.config.zero_alloc {
+                report.add_label(span, message);
+            }
+        }
+    }
+
+    // Replaces what the furthest failure expected by `label`, provided the failure happened at or
+    // after `since` and was recorded since the error generation was `generation`. Failures raised
+    // by user code are left untouched as their message is already meaningful.
+    pub fn relabel_error(&mut self, generation: usize, since: usize, label: &'static str) {
+        if let Some((at, report)) = &mut self.error {
+            if self.error_generation > generation && *at >= since && report.message().is_none() {
+                report.set_expected(Expected::Label(label));
+            }
+        }
+    }
+
+    // Adds a "while parsing `name`" breadcrumb to the furthest failure, provided it happened at or
+    // after `since` and was recorded since the error generation was `generation`: a failure that
+    // went further in an earlier attempt isn't part of `name`.
+    pub fn push_error_context(&mut self, generation: usize, since: usize, name: &'static str) {
+        if let Some((at, report)) = &mut self.error {
+            if self.error_generation > generation && *at >= since && !self.config.zero_alloc {
+                report.push_context(name, since, self.config.max_context_depth);
+            }
+        }
+    }
+
+    // Records a failure raised by user code over `span`. The failure happened once all of `span`
+    // was parsed, so it takes precedence over the failures recorded while parsing it.
+    pub fn record_custom(&mut self, span: Range<usize>, custom: CustomError) {
+        let found = self.peek_token();
+        self.record_error_at(span.end, ErrorReport::custom(span, found, custom));
+    }
+
+    // Records a failure returned by user code over `span`. The payload of custom errors is moved
+    // into the recorded report, what's left to propagate is returned.
+    pub fn record_parse_error(&mut self, span: Range<usize>, error: ParseError) -> ParseError {
+        match error {
+            ParseError::Custom(custom) => {
+                self.record_custom(span, custom);
+                ParseError::SyntaxError
+            }
+            ParseError::Fatal(Some(custom)) => {
+                self.record_custom(span, custom);
+                ParseError::Fatal(None)
+            }
+            error => {
+                let found = self.peek_token();
+                self.record_error(
+                    ErrorReport::new(span, ExpectedSet::new(), found).with_code(ErrorCode::Custom),
+                );
+                error
+            }
+        }
+    }
+
+    // Turns the error that made the whole parse fail into a report. Custom errors reaching the top,
+    // fatal or not, are reported as is, otherwise the furthest failure recorded so far is reported, or, if
+    // nothing was recorded, a failure on the token under the cursor.
+    pub fn take_error(&mut self, error: ParseError) -> ErrorReport<I::Token> {
+        let report = if let ParseError::Custom(custom) | ParseError::Fatal(Some(custom)) = error {
+            let (end, found) = self.peek();
+            ErrorReport::custom(self.offset.into()..end.into(), found, custom)
+        } else {
+            self.error
+                .take()
+                .map(|(_, report)| report)
+                .unwrap_or_else(|| {
+                    let (end, found) = self.peek();
+                    ErrorReport::new(self.offset.into()..end.into(), ExpectedSet::new(), found)
+                })
+        };
+
+        match self.input.source_id() {
+            Some(source_id) => report.with_source_id(source_id.clone()),
+            None => report,
+        }
+    }
+
+    // Keeps an error the parser recovered from. Once more errors than allowed by
+    // `ParseConfig::max_errors` are kept, a summary error is recorded instead and the parse is
+    // aborted with a fatal error.
+    pub fn recover_error(&mut self, report: ErrorReport<I::Token>) -> Result<(), ParseError> {
+        // The failure is accounted for, leftovers of the attempts made while recovering are
+        // irrelevant.
+        self.error = None;
+
+        match self.config.max_errors {
+            Some(max_errors) if self.errors.len() >= max_errors => {
+                let summary = CustomError::new(format!(
+                    "aborting after {} errors, the input has too many errors",
+                    self.errors.len()
+                ))
+                .with_code(ErrorCode::TooManyErrors);
+                let found = report.found().copied();
+                self.set_error(
+                    self.offset.into(),
+                    ErrorReport::custom(report.span(), found, summary),
+                );
+                Err(ParseError::Fatal(None))
+            }
+            _ => {
+                self.errors.push(report);
+                Ok(())
+            }
+        }
+    }
+
+    // Errors recovered from, along with those the input recovered from itself before the cursor,
+    // in the order of the input.
+    pub fn take_errors(&mut self) -> Vec<ErrorReport<I::Token>> {
+        let mut errors = std::mem::take(&mut self.errors);
+        let emitted = self.input.take_errors(self.offset);
+        if !emitted.is_empty() {
+            errors.extend(emitted);
+            errors.sort_by_key(|error| error.span().start);
+        }
+        errors
+    }
+
+    #[inline]
+    pub fn emitter(&mut self) -> Emitter<'_> {
+        Emitter::new(&mut self.diagnostics)
+    }
+
+    // Same as `take_errors` for the diagnostics emitted.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        let emitted = self.input.take_diagnostics(self.offset);
+        if !emitted.is_empty() {
+            diagnostics.extend(emitted);
+            diagnostics.sort_by_key(|diagnostic| diagnostic.span().start);
+        }
+        diagnostics
+    }
+}
+
+impl<'input, 'parse, I> InputRef<'input, 'parse, I>
+where
+    I: ByteInput<'input>,
+{
+    // Run of bytes `scan` counts under the cursor, see `ByteInput::scan_bytes`.
+    #[inline]
+    pub(crate) fn scan_bytes(&self, scan: impl FnOnce(&[u8]) -> usize) -> (I::Offset, usize) {
+        self.input.scan_bytes(self.offset, scan)
+    }
+}
+
+impl<'input, 'parse, I> InputRef<'input, 'parse, I>
+where
+    I: SliceInput<'input>,
+{
+    #[inline(always)]
+    pub fn slice(&self, start: Offset<I::Offset>, end: Offset<I::Offset>) -> I::Slice {
+        self.input.slice(self.untag(start), self.untag(end))
+    }
+
+    // Input parsed so far, from the start.
+    #[inline]
+    pub fn consumed(&self) -> I::Slice {
+        self.input.slice(self.input.start(), self.offset)
+    }
+
+    // Input left to parse, see `SliceInput::slice_to_end`.
+    #[inline]
+    pub fn remaining(&self) -> I::Slice {
+        self.input.slice_to_end(self.offset)
+    }
+
+    // Input parsed since `checkpoint` was saved.
+    #[inline]
+    pub fn slice_from(&self, checkpoint: &Checkpoint<I::Offset>) -> I::Slice {
+        #[cfg(debug_assertions)]
+        self.check(checkpoint);
+        self.input.slice(checkpoint.offset, self.offset)
     }
 }
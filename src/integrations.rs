@@ -0,0 +1,294 @@
+// Conversions from `ErrorReport` into the diagnostic types of popular error reporting crates, and
+// inputs and containers backed by other crates. Each integration lives behind its own feature
+// flag.
+#[cfg(any(
+    feature = "ariadne",
+    feature = "codespan-reporting",
+    feature = "logos",
+    feature = "miette"
+))]
+use crate::error::ErrorReport;
+#[cfg(feature = "bumpalo")]
+use crate::{input::Input, InputRef, ParseResult, Parser, RepeatedRange};
+#[cfg(any(feature = "bumpalo", feature = "compact_str"))]
+use crate::{AtLeast, AtMost, Exactly};
+#[cfg(any(
+    feature = "ariadne",
+    feature = "codespan-reporting",
+    feature = "miette"
+))]
+use std::fmt;
+#[cfg(feature = "bumpalo")]
+use std::marker::PhantomData;
+#[cfg(feature = "logos")]
+use std::ops::Range;
+#[cfg(feature = "mmap")]
+use std::{fs::File, io, path::Path};
+
+#[cfg(feature = "ariadne")]
+impl<T> ErrorReport<T>
+where
+    T: fmt::Debug,
+{
+    // Builds an `ariadne` report. Spans are byte offsets into the source, print the report with
+    // `report.print(ariadne::Source::from(source))`.
+    pub fn to_ariadne(&self) -> ::ariadne::Report<'static, std::ops::Range<usize>> {
+        use ::ariadne::{Config, IndexType, Label, Report, ReportKind};
+
+        let mut label = Label::new(self.span());
+        if let Some(expected) = self.expected_message() {
+            label = label.with_message(expected);
+        }
+
+        Report::build(ReportKind::Error, self.span())
+            .with_code(self.code())
+            .with_config(Config::default().with_index_type(IndexType::Byte))
+            .with_message(self.headline())
+            .with_label(label)
+            .with_labels(
+                self.secondary_labels()
+                    .iter()
+                    .map(|(span, message)| Label::new(span.clone()).with_message(message)),
+            )
+            .finish()
+    }
+}
+
+#[cfg(feature = "codespan-reporting")]
+impl<T> ErrorReport<T>
+where
+    T: fmt::Debug,
+{
+    // Builds a `codespan-reporting` diagnostic pointing into the file identified by `file_id`.
+    pub fn to_diagnostic<FileId>(
+        &self,
+        file_id: FileId,
+    ) -> ::codespan_reporting::diagnostic::Diagnostic<FileId>
+    where
+        FileId: Copy,
+    {
+        use ::codespan_reporting::diagnostic::{Diagnostic, Label};
+
+        let mut label = Label::primary(file_id, self.span());
+        if let Some(expected) = self.expected_message() {
+            label = label.with_message(expected);
+        }
+
+        Diagnostic::error()
+            .with_code(self.code().to_string())
+            .with_message(self.headline())
+            .with_labels(vec![label])
+            .with_labels_iter(self.secondary_labels().iter().map(|(span, message)| {
+                Label::secondary(file_id, span.clone()).with_message(message)
+            }))
+    }
+}
+
+// The source code isn't stored in the report, attach it with
+// `miette::Report::new(report).with_source_code(source)`.
+#[cfg(feature = "miette")]
+impl<T> ::miette::Diagnostic for ErrorReport<T>
+where
+    T: fmt::Debug,
+{
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.code()))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.expected_message()
+            .map(|expected| Box::new(expected) as Box<dyn fmt::Display>)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = ::miette::LabeledSpan> + '_>> {
+        let primary =
+            ::miette::LabeledSpan::new_primary_with_span(Some(self.headline()), self.span());
+        let secondary = self.secondary_labels().iter().map(|(span, message)| {
+            ::miette::LabeledSpan::new_with_span(Some(message.clone()), span.clone())
+        });
+        Some(Box::new(std::iter::once(primary).chain(secondary)))
+    }
+}
+
+// Tokens produced by a `logos` lexer, ready to be parsed with `input`. Spans are kept so that the
+// errors of the parser point at the source.
+#[cfg(feature = "logos")]
+#[derive(Debug)]
+pub struct LogosInput<T> {
+    tokens: Vec<(T, Range<usize>)>,
+}
+
+#[cfg(feature = "logos")]
+impl<T> LogosInput<T> {
+    // Runs the lexer to completion. Fails on the first input the lexer rejects.
+    pub fn new<'source>(mut lexer: ::logos::Lexer<'source, T>) -> Result<Self, ErrorReport<char>>
+    where
+        T: ::logos::Logos<'source, Source = str>,
+    {
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next() {
+            match token {
+                Ok(token) => tokens.push((token, lexer.span())),
+                Err(_) => {
+                    let span = lexer.span();
+                    let found = lexer.source()[span.start..].chars().next();
+                    return Err(ErrorReport::new(
+                        span,
+                        crate::Expected::Label("a token"),
+                        found,
+                    ));
+                }
+            }
+        }
+        Ok(Self { tokens })
+    }
+
+    #[inline]
+    pub fn tokens(&self) -> &[(T, Range<usize>)] {
+        &self.tokens
+    }
+
+    #[inline]
+    pub fn input(&self) -> crate::SpannedInput<'_, T> {
+        crate::SpannedInput::new(&self.tokens)
+    }
+}
+
+// File mapped in memory, parsed as bytes without being read first: the OS loads its pages as the
+// parser gets to them. The path of the file is attached to the errors.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MmapInput {
+    map: ::memmap2::Mmap,
+    source_id: crate::SourceId,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapInput {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // SAFETY: the map is only ever read through shared slices. As with any memory map, the
+        // file must not be truncated while it is mapped, which the caller is responsible for.
+        let map = unsafe { ::memmap2::Mmap::map(&file)? };
+        Ok(Self {
+            map,
+            source_id: crate::SourceId::new(path.to_string_lossy()),
+        })
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    #[inline]
+    pub fn input(&self) -> crate::Sourced<&[u8]> {
+        crate::Sourced::new(self.as_bytes(), self.source_id.clone())
+    }
+}
+
+// Short repetitions, e.g argument lists, collected without allocating.
+#[cfg(feature = "smallvec")]
+impl<A> crate::Container<A::Item> for ::smallvec::SmallVec<A>
+where
+    A: ::smallvec::Array,
+{
+    fn with_capacity_hint(hint: usize) -> Self {
+        ::smallvec::SmallVec::with_capacity(hint)
+    }
+
+    fn push(&mut self, item: A::Item) {
+        self.push(item);
+    }
+}
+
+// Identifiers and other short text collected without allocating, up to 24 bytes.
+#[cfg(feature = "compact_str")]
+impl crate::Container<char> for ::compact_str::CompactString {
+    fn with_capacity_hint(hint: usize) -> Self {
+        ::compact_str::CompactString::with_capacity(hint)
+    }
+
+    fn push(&mut self, item: char) {
+        self.push(item);
+    }
+}
+
+#[cfg(feature = "compact_str")]
+impl<'a> crate::Container<&'a str> for ::compact_str::CompactString {
+    fn push(&mut self, item: &'a str) {
+        self.push_str(item);
+    }
+}
+
+#[cfg(feature = "compact_str")]
+macro_rules! impl_collect_compact {
+    ($($builder:ident),+) => {
+        $(
+            impl<I, P, OP> $builder<I, P, OP> {
+                pub fn collect_compact(
+                    self,
+                ) -> crate::Collect<I, P, OP, ::compact_str::CompactString>
+                where
+                    ::compact_str::CompactString: crate::Container<OP>,
+                {
+                    self.collect()
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "compact_str")]
+impl_collect_compact!(AtLeast, AtMost, Exactly);
+
+// Same as `Collect` but the outputs are collected in a vector allocated in a `bumpalo` arena, e.g
+// the children of the nodes of a large syntax tree.
+#[cfg(feature = "bumpalo")]
+#[derive(Clone, Copy)]
+pub struct CollectIn<'bump, I, P, OP> {
+    parser: P,
+    range: RepeatedRange,
+    bump: &'bump ::bumpalo::Bump,
+    phantom: PhantomData<(I, OP)>,
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'input, 'bump, I, P, OP> Parser<'input, I, ::bumpalo::collections::Vec<'bump, OP>>
+    for CollectIn<'bump, I, P, OP>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    fn go(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+    ) -> ParseResult<::bumpalo::collections::Vec<'bump, OP>> {
+        let mut ret =
+            ::bumpalo::collections::Vec::with_capacity_in(self.range.capacity_hint(), self.bump);
+        crate::repeat(&self.parser, self.range, input_ref, |out| ret.push(out))?;
+        Ok(ret)
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+macro_rules! impl_collect_in {
+    ($($builder:ident),+) => {
+        $(
+            impl<I, P, OP> $builder<I, P, OP> {
+                pub fn collect_in(self, bump: &::bumpalo::Bump) -> CollectIn<'_, I, P, OP> {
+                    CollectIn {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        bump,
+                        phantom: PhantomData,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "bumpalo")]
+impl_collect_in!(AtLeast, AtMost, Exactly);
@@ -0,0 +1,62 @@
+// Interning of the text parsed, see `Parser::interned`. Identifiers repeated all over a large
+// source map to the same small `Symbol` rather than each getting its own `String`.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Handle to a string of an `Interner`. Equal symbols of an interner stand for equal strings, but
+// symbols are ordered by when their string was first interned, not by the strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: RefCell<Strings>,
+}
+
+#[derive(Debug, Default)]
+struct Strings {
+    symbols: HashMap<Rc<str>, Symbol>,
+    by_symbol: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&self, string: &str) -> Symbol {
+        let mut strings = self.strings.borrow_mut();
+        if let Some(symbol) = strings.symbols.get(string) {
+            return *symbol;
+        }
+
+        let index = u32::try_from(strings.by_symbol.len()).expect("too many strings interned");
+        let symbol = Symbol(index);
+        let string: Rc<str> = Rc::from(string);
+        strings.by_symbol.push(string.clone());
+        strings.symbols.insert(string, symbol);
+        symbol
+    }
+
+    // Panics if `symbol` comes from another interner.
+    pub fn resolve(&self, symbol: Symbol) -> Rc<str> {
+        self.strings.borrow().by_symbol[symbol.index()].clone()
+    }
+
+    // Number of distinct strings interned.
+    pub fn len(&self) -> usize {
+        self.strings.borrow().by_symbol.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,93 @@
+// First stage of a two stage lexer + parser pipeline. The rules of the lexer are parsers over the
+// source text: token rules produce tokens, skip rules match what lies between them, e.g white
+// space and comments. The tokens produced are fed to the parser stage with `SpannedInput`, so that
+// its errors point at the source.
+use crate::error::{ErrorReport, Expected};
+use crate::input::InputRef;
+use crate::{ParseError, Parser};
+use std::ops::Range;
+
+enum Rule<'input, T> {
+    Token(Box<dyn Parser<'input, &'input str, T> + 'input>),
+    Skip(Box<dyn Parser<'input, &'input str, ()> + 'input>),
+}
+
+pub struct Lexer<'input, T> {
+    rules: Vec<Rule<'input, T>>,
+}
+
+impl<'input, T> Default for Lexer<'input, T> {
+    fn default() -> Self {
+        Self { rules: Vec::new() }
+    }
+}
+
+impl<'input, T> Lexer<'input, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Rules are tried in the order they were added, the first one matching wins. e.g keywords
+    // must be added before identifiers.
+    pub fn token<P>(mut self, rule: P) -> Self
+    where
+        P: Parser<'input, &'input str, T> + 'input,
+    {
+        self.rules.push(Rule::Token(Box::new(rule)));
+        self
+    }
+
+    pub fn skip<P, O>(mut self, rule: P) -> Self
+    where
+        P: Parser<'input, &'input str, O> + 'input,
+        O: 'input,
+    {
+        self.rules.push(Rule::Skip(Box::new(rule.map(|_| ()))));
+        self
+    }
+
+    // Splits `source` into tokens, each along with its span. Fails on the first character no rule
+    // matches.
+    pub fn lex(&self, source: &'input str) -> Result<Vec<(T, Range<usize>)>, ErrorReport<char>> {
+        let mut input_ref = InputRef::new(&source);
+        let mut tokens = Vec::new();
+
+        'source: while input_ref.peek_token().is_some() {
+            let start = input_ref.offset();
+            for rule in self.rules.iter() {
+                let checkpoint = input_ref.save();
+                let result = match rule {
+                    Rule::Token(parser) => parser.go(&mut input_ref).map(Some),
+                    Rule::Skip(parser) => parser.go(&mut input_ref).map(|_| None),
+                };
+
+                match result {
+                    // Rules matching nothing would never let the lexer move forward.
+                    Ok(_) if input_ref.offset() == start => {}
+                    Ok(token) => {
+                        input_ref.commit(checkpoint);
+                        let span = start.into()..input_ref.offset().into();
+                        tokens.extend(token.map(|token| (token, span)));
+                        continue 'source;
+                    }
+                    Err(error @ (ParseError::Custom(_) | ParseError::Fatal(Some(_)))) => {
+                        return Err(input_ref.take_error(error))
+                    }
+                    Err(_) => {}
+                }
+                if let Err(error) = input_ref.restore(checkpoint) {
+                    return Err(input_ref.take_error(error));
+                }
+            }
+
+            // The failures of the rules aren't relevant, none of them applies here.
+            let (end, found) = input_ref.peek();
+            return Err(ErrorReport::new(
+                start.into()..end.into(),
+                Expected::Label("a token"),
+                found,
+            ));
+        }
+        Ok(tokens)
+    }
+}
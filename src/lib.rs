@@ -1,30 +1,513 @@
+mod bits;
+mod bytes;
+mod chunked;
+mod error;
 mod input;
+#[cfg(any(
+    feature = "ariadne",
+    feature = "bumpalo",
+    feature = "codespan-reporting",
+    feature = "compact_str",
+    feature = "logos",
+    feature = "miette",
+    feature = "mmap",
+    feature = "smallvec"
+))]
+mod integrations;
+mod intern;
+mod lexer;
+mod located;
+mod optimize;
+#[cfg(feature = "rayon")]
+mod parallel;
 mod sequence;
-
-use input::{Input, InputRef};
-use sequence::{Container, OrderedSequence};
+mod shared;
+mod source;
+mod spanned;
+mod stream;
+pub mod text;
+mod trie;
+mod utf16;
+mod utf8;
+mod vm;
+
+pub use bits::{bits, BitInput, BitOffset, BitSlice, Bits};
+pub use bytes::{take_until, take_while_in, ByteInput, ByteSet, TakeUntil, TakeWhileIn};
+pub use chunked::{ChunkedInput, ChunkedSlice};
+pub use error::{
+    CustomError, Diagnostic, Emitter, EncodingError, ErrorCode, ErrorReport, Expected, ExpectedSet,
+    Position, ReportDisplay, Severity, Trailing,
+};
+use input::Scratch;
+pub use input::{
+    ByValue, Checkpoint, Input, InputMark, InputRef, MapTokens, Mark, Offset, ParseConfig,
+    ParseProfile, ParseStats, RuleProfile, SkipTrivia, SliceInput, SourceId, Sourced,
+};
+#[cfg(feature = "bumpalo")]
+pub use integrations::CollectIn;
+#[cfg(feature = "logos")]
+pub use integrations::LogosInput;
+#[cfg(feature = "mmap")]
+pub use integrations::MmapInput;
+pub use intern::{Interner, Symbol};
+pub use lexer::Lexer;
+pub use located::{LocatedInput, LocatedSlice};
+pub use optimize::{ExactThen, Optimize, TakeWhile};
+#[cfg(feature = "rayon")]
+pub use parallel::{
+    parse_records_parallel, Delimited, LengthPrefixed, RecordInput, RecordSplitter,
+};
+pub use sequence::{
+    Container, Count, DuplicateKeys, FromParsedIter, MapContainer, OrderedSequence,
+};
+pub use shared::SharedSlice;
+pub use source::SourceText;
+pub use spanned::{SpannedInput, SpannedOffset, SpannedSlice};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
 use std::marker::PhantomData;
+use std::ops::{Range, RangeInclusive};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+#[cfg(feature = "async")]
+pub use stream::AsyncReaderInput;
+pub use stream::{
+    BufferSlice, IterInput, ParseState, ReaderChunk, ReaderInput, Status, StreamError,
+};
+use trie::Trie;
+pub use utf16::{Utf16Input, Utf16Slice};
+pub use utf8::Utf8Input;
+pub use vm::{Compile, Compiler, Program};
 
 #[derive(Debug, PartialEq)]
-enum ParseError {
+pub enum ParseError {
     SyntaxError,
+    // Failure that must not be recovered from: alternatives aren't tried and repetitions don't
+    // stop gracefully, the error is propagated right away. See `Parser::cut`, which keeps the
+    // payload of the custom failures it turns into fatal ones.
+    Fatal(Option<CustomError>),
+    // Failure raised by user code, see `ParseError::custom`.
+    Custom(CustomError),
+    // The input ended before the parser could tell whether it matches, only raised on partial
+    // inputs (see `ParseConfig::partial`). `needed` is the number of tokens missing, if known.
+    // Like fatal failures, it is propagated right away: the alternatives can't be ruled in or out
+    // until more input is available.
+    Incomplete { needed: Option<usize> },
+}
+
+// Whether an error allows the parser to backtrack and try something else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorSeverity {
+    Recoverable,
+    Fatal,
+}
+
+impl ParseError {
+    pub fn custom(message: impl Into<String>) -> Self {
+        ParseError::Custom(CustomError::new(message))
+    }
+
+    // Custom failure caused by an external error, the message of the failure is the one of its
+    // cause. e.g `.try_map(|digits: &str| digits.parse::<u8>().map_err(ParseError::caused_by))`
+    pub fn caused_by<E>(cause: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        ParseError::Custom(CustomError::new(cause.to_string()).with_cause(cause))
+    }
+
+    pub fn severity(&self) -> ErrorSeverity {
+        match self {
+            ParseError::SyntaxError | ParseError::Custom(_) => ErrorSeverity::Recoverable,
+            ParseError::Fatal(_) | ParseError::Incomplete { .. } => ErrorSeverity::Fatal,
+        }
+    }
+
+    #[inline]
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, ParseError::Incomplete { .. })
+    }
+
+    #[inline]
+    pub fn is_fatal(&self) -> bool {
+        self.severity() == ErrorSeverity::Fatal
+    }
+}
+
+pub type ParseResult<O> = Result<O, ParseError>;
+
+// Outcome of `Parser::parse_partial`: the output, if the parse didn't fail, along with the errors
+// encountered.
+#[derive(Debug, PartialEq)]
+pub struct ParseOutput<O, T> {
+    output: Option<O>,
+    errors: Vec<ErrorReport<T>>,
+}
+
+impl<O, T> ParseOutput<O, T> {
+    #[inline]
+    pub fn output(&self) -> Option<&O> {
+        self.output.as_ref()
+    }
+
+    #[inline]
+    pub fn errors(&self) -> &[ErrorReport<T>] {
+        &self.errors
+    }
+
+    #[inline]
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn into_output_errors(self) -> (Option<O>, Vec<ErrorReport<T>>) {
+        (self.output, self.errors)
+    }
+
+    // The output, only if no error was encountered.
+    pub fn into_result(self) -> Result<O, Vec<ErrorReport<T>>> {
+        match self.output {
+            Some(out) if self.errors.is_empty() => Ok(out),
+            _ => Err(self.errors),
+        }
+    }
+
+    // Outcome of a parse that ended with `result`: the errors recovered from, followed by the one
+    // that made the parse fail if any.
+    fn from_parse<'input, I>(
+        input_ref: &mut InputRef<'input, '_, I>,
+        result: ParseResult<O>,
+    ) -> Self
+    where
+        I: Input<'input, Token = T>,
+    {
+        let mut errors = input_ref.take_errors();
+        let output = match result {
+            Ok(out) => Some(out),
+            Err(error) => {
+                errors.push(input_ref.take_error(error));
+                None
+            }
+        };
+        ParseOutput { output, errors }
+    }
+
+    // The output, or the first error encountered as `Parser::parse` reports it.
+    fn into_first_error(self) -> Result<O, ErrorReport<T>> {
+        self.into_result()
+            .map_err(|errors| errors.into_iter().next().unwrap())
+    }
+}
+
+// Iterator over the matches of a parser, one after the other, see `Parser::parse_iter`.
+pub struct ParseIter<'p, 'input, I, P, O>
+where
+    I: Input<'input>,
+{
+    parser: &'p P,
+    input: I,
+    offset: I::Offset,
+    done: bool,
+    phantom: PhantomData<O>,
+}
+
+impl<'input, I, P, O> Iterator for ParseIter<'_, 'input, I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+{
+    type Item = Result<O, ErrorReport<I::Token>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut input_ref = InputRef::new(&self.input);
+        input_ref.move_to(self.offset);
+        if input_ref.peek_token().is_none() && input_ref.encoding_error().is_none() {
+            self.done = true;
+            return None;
+        }
+
+        match self.parser.go(&mut input_ref) {
+            Ok(out) => {
+                // A match of nothing would be found over and over again.
+                self.done = input_ref.raw_offset() == self.offset;
+                self.offset = input_ref.raw_offset();
+                Some(Ok(out))
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(input_ref.take_error(error)))
+            }
+        }
+    }
+}
+
+impl<'input, I, P, O> std::iter::FusedIterator for ParseIter<'_, 'input, I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+{
+}
+
+// Iterator over the results of a parser on each of many inputs, see `Parser::parse_all`.
+pub struct ParseAll<'p, 'input, I, P, O, It>
+where
+    I: Input<'input>,
+{
+    parser: &'p P,
+    inputs: It,
+    scratch: Scratch<I::Offset>,
+    phantom: PhantomData<O>,
 }
 
-type ParseResult<O> = Result<O, ParseError>;
+impl<'input, I, P, O, It> Iterator for ParseAll<'_, 'input, I, P, O, It>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    It: Iterator<Item = I>,
+{
+    type Item = Result<O, ErrorReport<I::Token>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let input = self.inputs.next()?;
+        let scratch = std::mem::take(&mut self.scratch);
+        let mut input_ref = InputRef::with_scratch(&input, ParseConfig::default(), scratch);
+        let result = self.parser.go(&mut input_ref);
+        let result = ParseOutput::from_parse(&mut input_ref, result).into_first_error();
+        self.scratch = input_ref.into_scratch();
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inputs.size_hint()
+    }
+}
 
-trait Parser<'input, I, O>
+pub trait Parser<'input, I, O>
 where
     I: Input<'input>,
 {
-    fn parse(&self, input: I) -> ParseResult<O> {
+    // Runs the parser on the whole input. On failure, the furthest error encountered while parsing
+    // is reported.
+    fn parse(&self, input: I) -> Result<O, ErrorReport<I::Token>> {
+        self.parse_partial(input, ParseConfig::default())
+            .into_first_error()
+    }
+
+    // Same as `parse` but only tells whether the input matches, e.g to validate a huge document
+    // against a grammar: the outputs aren't built, mappers aren't called and containers aren't
+    // filled (so the items they would reject, see `Container::try_push`, aren't caught).
+    fn check(&self, input: I) -> Result<(), ErrorReport<I::Token>> {
+        let mut input_ref = InputRef::new(&input);
+        self.go_check(&mut input_ref)
+            .map_err(|error| input_ref.take_error(error))
+    }
+
+    // Same as `parse` but the input left once the parser is done is returned along with the
+    // output, e.g to hand the body following a header over to another parser.
+    fn parse_prefix(&self, input: I) -> Result<(O, I::Slice), ErrorReport<I::Token>>
+    where
+        I: SliceInput<'input>,
+    {
+        let mut input_ref = InputRef::new(&input);
+        match self.go(&mut input_ref) {
+            Ok(out) => Ok((out, input_ref.remaining())),
+            Err(error) => Err(input_ref.take_error(error)),
+        }
+    }
+
+    // Same as `parse` but where the parser stopped is returned along with the output, so that
+    // parsing can be resumed from there with `parse_from`, e.g parsing the body of a message
+    // once its headers are dealt with. Unlike with `parse_prefix`, offsets and spans remain
+    // relative to the whole input.
+    fn parse_marked(&self, input: I) -> Result<(O, InputMark<'input, I>), ErrorReport<I::Token>> {
+        let mut input_ref = InputRef::new(&input);
+        match self.go(&mut input_ref) {
+            Ok(out) => Ok((out, input_ref.mark())),
+            Err(error) => Err(input_ref.take_error(error)),
+        }
+    }
+
+    // Same as `parse` but starts at `mark`, which must come from the same input, see
+    // `InputRef::resume`.
+    fn parse_from(
+        &self,
+        input: I,
+        mark: &InputMark<'input, I>,
+    ) -> Result<O, ErrorReport<I::Token>> {
+        let mut input_ref = InputRef::new(&input);
+        input_ref
+            .resume(mark)
+            .and_then(|_| self.go(&mut input_ref))
+            .map_err(|error| input_ref.take_error(error))
+    }
+
+    // Runs the parser over and over again, lazily, until the input is exhausted, e.g to pull the
+    // records of a log one at a time and stop early. A failure is the last item.
+    fn parse_iter(&self, input: I) -> ParseIter<'_, 'input, I, Self, O>
+    where
+        Self: Sized,
+    {
+        ParseIter {
+            parser: self,
+            offset: input.start(),
+            input,
+            done: false,
+            phantom: PhantomData,
+        }
+    }
+
+    // Same as `parse` on each of `inputs`, lazily, e.g millions of log lines or CSV fields. The
+    // buffers the parses need are allocated once and reused from one input to the next. The
+    // results cached by the rules (see `Parser::rule`) aren't: each input is parsed afresh.
+    fn parse_all<It>(&self, inputs: It) -> ParseAll<'_, 'input, I, Self, O, It::IntoIter>
+    where
+        It: IntoIterator<Item = I>,
+        Self: Sized,
+    {
+        ParseAll {
+            parser: self,
+            inputs: inputs.into_iter(),
+            scratch: Scratch::default(),
+            phantom: PhantomData,
+        }
+    }
+
+    // Same as `parse_all` on each line of `text`, without their line terminators.
+    fn parse_lines(
+        &self,
+        text: &'input str,
+    ) -> ParseAll<'_, 'input, I, Self, O, std::str::Lines<'input>>
+    where
+        std::str::Lines<'input>: Iterator<Item = I>,
+        Self: Sized,
+    {
+        self.parse_all(text.lines())
+    }
+
+    // Same as `parse` but panics on failure with the rendered errors (see `ErrorReport::display`),
+    // meant for tests and quick scripts where `unwrap` would only show the raw report.
+    #[track_caller]
+    fn parse_or_report(&self, input: I) -> O
+    where
+        I: Copy + AsRef<[u8]>,
+        I::Token: fmt::Debug,
+    {
+        match self.parse_with_config(input, ParseConfig::default()) {
+            Ok(out) => out,
+            Err(errors) => {
+                let source = String::from_utf8_lossy(input.as_ref());
+                let rendered = errors
+                    .iter()
+                    .map(|error| error.display(&source).to_string())
+                    .collect::<Vec<_>>();
+                panic!("parse failed:\n{}", rendered.join("\n\n"))
+            }
+        }
+    }
+
+    // Same as `parse`, but the warnings and notes emitted while parsing (see `validate`) are
+    // returned as well, whether the parse succeeded or not.
+    fn parse_with_diagnostics(
+        &self,
+        input: I,
+    ) -> (Result<O, ErrorReport<I::Token>>, Vec<Diagnostic>) {
         let mut input_ref = InputRef::new(&input);
-        self.go(&mut input_ref)
+        let result = self
+            .go(&mut input_ref)
+            .map_err(|error| input_ref.take_error(error));
+        (result, input_ref.take_diagnostics())
+    }
+
+    // Same as `parse`, but how the input was consumed is returned as well, e.g to find out how
+    // much a grammar backtracks.
+    fn parse_with_stats(&self, input: I) -> (Result<O, ErrorReport<I::Token>>, ParseStats) {
+        let mut input_ref = InputRef::new(&input);
+        let result = self
+            .go(&mut input_ref)
+            .map_err(|error| input_ref.take_error(error));
+        (result, input_ref.stats())
+    }
+
+    // Same as `parse_with_stats`, but the time the parse took is measured and the rewinds are
+    // counted for each `labelled` parser as well, e.g to find out which rules of a grammar
+    // backtrack the most. Keeping the counters slows the parse down a little.
+    fn parse_with_profile(&self, input: I) -> (Result<O, ErrorReport<I::Token>>, ParseProfile) {
+        self.parse_with_profile_config(input, ParseConfig::default())
+    }
+
+    // Same as `parse_with_profile` but parses with `config`, e.g to profile a grammar with packrat
+    // parsing enabled.
+    fn parse_with_profile_config(
+        &self,
+        input: I,
+        config: ParseConfig,
+    ) -> (Result<O, ErrorReport<I::Token>>, ParseProfile) {
+        let mut input_ref = InputRef::with_config(&input, config);
+        input_ref.profile();
+        let start = Instant::now();
+        let result = self.go(&mut input_ref);
+        let profile = input_ref.take_profile(start.elapsed());
+        (result.map_err(|error| input_ref.take_error(error)), profile)
+    }
+
+    // Same as `parse` but every error recovered from (see `recover_with`) is reported, in the
+    // order they were encountered, followed by the error that made the parse fail if any.
+    fn parse_with_config(
+        &self,
+        input: I,
+        config: ParseConfig,
+    ) -> Result<O, Vec<ErrorReport<I::Token>>> {
+        self.parse_partial(input, config).into_result()
+    }
+
+    // Same as `parse_with_config` but the output is kept along with the errors: a parse that
+    // recovered from its errors still produces a best effort output, e.g a syntax tree with holes
+    // for an IDE.
+    fn parse_partial(&self, input: I, config: ParseConfig) -> ParseOutput<O, I::Token> {
+        let mut input_ref = InputRef::with_config(&input, config);
+        let result = self.go(&mut input_ref);
+        ParseOutput::from_parse(&mut input_ref, result)
     }
 
-    // Helper function
-    // All the logic for parsing resides in this method.
     fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O>;
 
+    // Same as `go` but for the outcome only, see `check`. Combinators override it to skip building
+    // their output, e.g `map` doesn't call its mapper and `collect` doesn't fill its container.
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.go(input_ref).map(|_| ())
+    }
+
+    // Tells from the token under the cursor alone, without running the parser, that it fails,
+    // e.g `exact("while")` on anything but a 'w'. The failure is then recorded as `go` would,
+    // and `or`, `choice`, `or_not` and repetitions skip the attempt rather than saving the input,
+    // running the parser and rewinding. Parsers that can't tell (the default) return `false`.
+    fn rules_out(&self, _input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        false
+    }
+
+    // Moves past up to `at_most` matches of the parser in one go, for the repetitions whose
+    // outputs aren't needed (see `to_slice`) of parsers matching single tokens, e.g
+    // `any().filter(..)`. Only the outputs `predicate` accepts are matched. Returns the number of
+    // matches moved past, parsers that can't scan (the default) don't move. The attempt that
+    // ends the run is left to `go`, so that its failure is recorded as usual.
+    fn go_scan(
+        &self,
+        _input_ref: &mut InputRef<'input, '_, I>,
+        _at_most: usize,
+        _predicate: &dyn Fn(&O) -> bool,
+    ) -> usize {
+        0
+    }
+
     // `map` operator, works the same way as the map function on iterators (Functors
     // generally).
     fn map<U, F>(self, mapper: F) -> Map<I, Self, O, F, U>
@@ -39,6 +522,63 @@ where
         }
     }
 
+    // `try_map` operator, works like `map` but the mapper can fail, e.g when converting the
+    // parsed input to a number that doesn't fit in the target type.
+    fn try_map<U, F>(self, mapper: F) -> TryMap<I, Self, O, F, U>
+    where
+        F: Fn(O) -> Result<U, ParseError>,
+        Self: Sized,
+    {
+        TryMap {
+            mapper,
+            parser: self,
+            phantom: PhantomData,
+        }
+    }
+
+    // `map_err_with_span` operator replaces the failure of the parser by the one returned by
+    // `mapper`, which also receives the span of the input attempted before failing, e.g to report
+    // an "unterminated string" covering the whole string.
+    fn map_err_with_span<F>(self, mapper: F) -> MapErrWithSpan<I, Self, O, F>
+    where
+        F: Fn(ParseError, I::Span) -> ParseError,
+        Self: Sized,
+    {
+        MapErrWithSpan {
+            mapper,
+            parser: self,
+            phantom: PhantomData,
+        }
+    }
+
+    // `on_err` operator calls `callback` whenever the parser fails, before the failure is
+    // propagated, e.g to log or count the failures of a parser in production.
+    fn on_err<F>(self, callback: F) -> OnErr<I, Self, O, F>
+    where
+        F: for<'parse> Fn(&ParseError, &InputRef<'input, 'parse, I>),
+        Self: Sized,
+    {
+        OnErr {
+            callback,
+            parser: self,
+            phantom: PhantomData,
+        }
+    }
+
+    // `validate` operator, works like `map` but the mapper also receives the span of the parsed
+    // input and an `Emitter` to report warnings and notes that don't make the parse fail.
+    fn validate<U, F>(self, validator: F) -> Validate<I, Self, O, F, U>
+    where
+        F: Fn(O, I::Span, &mut Emitter<'_>) -> U,
+        Self: Sized,
+    {
+        Validate {
+            validator,
+            parser: self,
+            phantom: PhantomData,
+        }
+    }
+
     // `right_bind` operator, you can think of it as the right bind operator in haskell (>>). It helps
     // binding multiple parsers together while only keeping the results of the second parser.
     fn right_bind<P2, OP2>(self, second_parser: P2) -> RightBind<I, Self, O, P2, OP2>
@@ -67,6 +607,27 @@ where
         })
     }
 
+    // `delimited_by` operator parses the input between an opening and a closing delimiter, only
+    // keeping the output of the delimited parser. When the closing delimiter is missing, the
+    // error points back at the opening one.
+    fn delimited_by<P1, OP1, P2, OP2>(
+        self,
+        open: P1,
+        close: P2,
+    ) -> DelimitedBy<I, Self, O, P1, OP1, P2, OP2>
+    where
+        P1: Parser<'input, I, OP1>,
+        P2: Parser<'input, I, OP2>,
+        Self: Sized,
+    {
+        DelimitedBy {
+            parser: self,
+            open,
+            close,
+            phantom: PhantomData,
+        }
+    }
+
     // `and` operator allows you to run two parsers and return the output of both in a
     // container.
     fn and<P2, OP2>(self, second_parser: P2) -> And<I, Self, O, P2, OP2>
@@ -95,6 +656,21 @@ where
         }
     }
 
+    // `separated_by` operator parses a possibly empty list of items separated by `separator`, e.g
+    // the arguments of a call.
+    fn separated_by<P2, OP2>(self, separator: P2) -> SeparatedBy<I, Self, O, P2, OP2>
+    where
+        P2: Parser<'input, I, OP2>,
+        Self: Sized,
+    {
+        SeparatedBy {
+            parser: self,
+            separator,
+            allow_trailing: false,
+            phantom: PhantomData,
+        }
+    }
+
     fn filter<F>(self, filter_func: F) -> Filter<I, Self, O, F>
     where
         Self: Sized,
@@ -106,6 +682,21 @@ where
         }
     }
 
+    // `guard` operator works like `filter` but a rejected output is reported with `message`, e.g
+    // `port.guard(|port| *port <= 65535, "port number must be <= 65535")`.
+    fn guard<F>(self, predicate: F, message: impl Into<String>) -> Guard<I, Self, O, F>
+    where
+        F: Fn(&O) -> bool,
+        Self: Sized,
+    {
+        Guard {
+            parser: self,
+            predicate,
+            message: message.into(),
+            phantom: PhantomData,
+        }
+    }
+
     fn padded<P2, OP2>(self, padded_by: P2) -> Padded<I, Self, O, P2, OP2>
     where
         Self: Sized,
@@ -126,932 +717,6099 @@ where
             second_parser,
         }
     }
-}
-
-#[derive(Clone, Copy)]
-struct Or<P1, P2> {
-    first_parser: P1,
-    second_parser: P2,
-}
 
-impl<'input, I, P1, P2, OP> Parser<'input, I, OP> for Or<P1, P2>
-where
-    I: Input<'input>,
-    P1: Parser<'input, I, OP>,
-    P2: Parser<'input, I, OP>,
-{
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP> {
-        let prev_state = input_ref.offset();
-        if let Ok(out) = self.first_parser.go(input_ref) {
-            Ok(out)
-        } else {
-            input_ref.rewind(prev_state);
-            self.second_parser.go(input_ref)
+    // `expect` operator replaces what the parser reports as expected on failure by a human level
+    // description, e.g `version.expect("a version number like 1.2.3")` instead of a list of
+    // characters.
+    fn expect(self, description: &'static str) -> Expect<I, Self, O>
+    where
+        Self: Sized,
+    {
+        Expect {
+            parser: self,
+            description,
+            phantom: PhantomData,
         }
     }
-}
-
-#[derive(Clone, Copy)]
-struct Padded<I, P1, OP1, P2, OP2> {
-    parser: P1,
-    padded_by: P2,
-    phantom: PhantomData<(I, OP1, OP2)>,
-}
-
-impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, OP1> for Padded<I, P1, OP1, P2, OP2>
-where
-    I: Input<'input>,
-    P1: Parser<'input, I, OP1>,
-    P2: Parser<'input, I, OP2>,
-{
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP1> {
-        _ = self.padded_by.go(input_ref);
-
-        let out = self.parser.go(input_ref)?;
-
-        _ = self.padded_by.go(input_ref);
 
-        Ok(out)
+    // `or_error` operator replaces the failure of the parser by the one built by `error` when it
+    // failed without making any progress, e.g when none of the alternatives of
+    // `let_statement.or(if_statement).or(expression)` matched their first token,
+    // `.or_error(|| ParseError::custom("expected a statement"))` is reported instead of what the
+    // last alternative expected.
+    fn or_error<F>(self, error: F) -> OrError<I, Self, O, F>
+    where
+        F: Fn() -> ParseError,
+        Self: Sized,
+    {
+        OrError {
+            parser: self,
+            error,
+            phantom: PhantomData,
+        }
     }
-}
 
-#[derive(Clone, Copy)]
-struct Filter<I, P, O, F> {
-    parser: P,
-    filter_func: F,
-    phantom: PhantomData<(I, O)>,
-}
-
-impl<'input, I, P, O, F> Parser<'input, I, O> for Filter<I, P, O, F>
-where
-    I: Input<'input>,
-    P: Parser<'input, I, O>,
-    F: Fn(&O) -> bool,
-{
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
-        let prev_state = input_ref.offset();
-        self.parser.go(input_ref).and_then(|out| {
-            if (self.filter_func)(&out) {
-                Ok(out)
-            } else {
-                input_ref.rewind(prev_state);
-                Err(ParseError::SyntaxError)
-            }
-        })
+    // `interned` operator maps the text parsed to its `Symbol` in `interner`, e.g identifiers
+    // collected all over a large source.
+    fn interned(self, interner: &Interner) -> Interned<'_, I, Self, O>
+    where
+        O: AsRef<str>,
+        Self: Sized,
+    {
+        Interned {
+            parser: self,
+            interner,
+            phantom: PhantomData,
+        }
     }
-}
-
-// This is a bit too awkward. Maybe put all the entities related to a specific parser into a
-// module.
-#[derive(Debug, Clone, Copy)]
-enum RepeatedRange {
-    AtLeast(usize),
-    Between(usize, usize),
-    Exactly(usize),
-}
 
-impl RepeatedRange {
-    #[inline]
-    fn start(&self) -> usize {
-        match self {
-            &RepeatedRange::AtLeast(start) => start,
-            &RepeatedRange::Between(start, _end) => start,
-            &RepeatedRange::Exactly(count) => count,
+    // `rule` operator makes the parser a rule of the grammar, whose results are cached by offset
+    // when parsing in packrat mode (see `ParseConfig::packrat`), e.g the nonterminals shared by
+    // alternatives backtracking over the same input. The output is cloned out of the cache. The
+    // runs that emitted diagnostics or recovered from errors aren't cached.
+    fn rule(self) -> Rule<'input, I, Self, O>
+    where
+        Self: Sized,
+    {
+        Rule {
+            parser: self,
+            memo: Rc::new(Memo {
+                generation: Cell::new(0),
+                entries: RefCell::new(BTreeMap::new()),
+            }),
         }
     }
 
-    #[inline]
-    fn end(&self) -> Option<usize> {
-        match self {
-            &RepeatedRange::AtLeast(_start) => None,
-            &RepeatedRange::Between(_start, end) => Some(end),
-            &RepeatedRange::Exactly(count) => Some(count),
+    // `labelled` operator names the parser, errors bubbling up through it are annotated with
+    // "in `name`" breadcrumbs, e.g "found 'x', expected '\"', in string literal, in object value".
+    fn labelled(self, name: &'static str) -> Labelled<I, Self, O>
+    where
+        Self: Sized,
+    {
+        Labelled {
+            parser: self,
+            name,
+            phantom: PhantomData,
         }
     }
-}
-
-#[derive(Clone, Copy)]
-struct Repeated<I, P, OP> {
-    parser: P,
-    range: RepeatedRange,
-    phantom: PhantomData<(I, OP)>,
-}
 
-#[derive(Clone, Copy)]
-struct AtLeast<I, P, OP>(Repeated<I, P, OP>);
+    // `nested` operator runs the parser one nested level deeper, see `InputRef::nest`. Meant for
+    // the recursive parts of a grammar, e.g the inside of parentheses. The recursion entry points,
+    // i.e `boxed`, `into_grammar`, `rule` and `left_recursive` parsers, count their own level.
+    fn nested(self) -> Nested<I, Self, O>
+    where
+        Self: Sized,
+    {
+        Nested {
+            parser: self,
+            phantom: PhantomData,
+        }
+    }
 
-#[derive(Clone, Copy)]
-struct Exactly<I, P, OP>(Repeated<I, P, OP>);
+    // `boxed` operator hides the type of the parser behind a pointer, calling it through dynamic
+    // dispatch. Each combinator nested in another is a type of its own, so the types of large
+    // grammars, and the code generated for them, grow huge: boxing the rules of the grammar, e.g
+    // `statement.boxed()`, keeps build times and binary size down for a small cost at runtime.
+    fn boxed(self) -> Boxed<'input, I, O>
+    where
+        Self: Sized + 'input,
+    {
+        Boxed {
+            parser: Rc::new(self),
+        }
+    }
 
-#[derive(Clone, Copy)]
-struct AtMost<I, P, OP>(Repeated<I, P, OP>);
+    // `into_grammar` operator wraps the parser in a `Grammar` shared across threads. The parser
+    // must be `Send` and `Sync`, which rules aren't (see `rule`): they share their cache.
+    fn into_grammar(self) -> Grammar<'input, I, O>
+    where
+        Self: Send + Sync + Sized + 'input,
+    {
+        Grammar {
+            parser: Arc::new(self),
+        }
+    }
 
-impl<I, P, OP> Repeated<I, P, OP> {
-    fn at_least(self, at_least: usize) -> AtLeast<I, P, OP> {
-        AtLeast(Repeated {
-            range: RepeatedRange::AtLeast(at_least),
-            parser: self.parser,
+    // `left_recursive` operator lets the parser call itself first thing, e.g the body of
+    // `expr := expr '-' number | number`, built in `go` by a recursive parser. The body is parsed
+    // over and over, the calls to itself at the same offset getting the previous match, for as long
+    // as the match grows. Left recursive parsers are told apart by `name`. The output is cloned.
+    fn left_recursive(self, name: &'static str) -> LeftRecursive<I, Self, O>
+    where
+        Self: Sized,
+    {
+        LeftRecursive {
+            parser: self,
+            name,
             phantom: PhantomData,
-        })
+        }
     }
 
-    fn exactly(self, count: usize) -> Exactly<I, P, OP> {
-        Exactly(Repeated {
-            range: RepeatedRange::Exactly(count),
-            parser: self.parser,
-            phantom: PhantomData,
-        })
+    // `compile` operator turns the grammar into a `Program` run by a small virtual machine, e.g to
+    // validate millions of small inputs against it. The program outputs the slice of input
+    // matched, the outputs of the parsers it was compiled from aren't built.
+    fn compile(&self) -> Program<'input, I>
+    where
+        Self: Compile<I::Token> + Sized,
+    {
+        Program::new(self)
     }
-}
 
-impl<I, P, OP> AtLeast<I, P, OP> {
-    fn at_most(self, at_most: usize) -> AtMost<I, P, OP> {
-        let at_least = self.0.range.start();
+    // `optimize` operator rewrites the grammar into an equivalent one that parses faster, e.g a
+    // chain of `or` into a single `choice` (see `Optimize` for the patterns rewritten). It is only
+    // available for grammars made of the parsers implementing `Optimize`.
+    fn optimize(self) -> <Self as Optimize<'input, I, O>>::Optimized
+    where
+        Self: Optimize<'input, I, O> + Sized,
+    {
+        self.rewrite()
+    }
 
-        AtMost(Repeated {
-            range: RepeatedRange::Between(at_least, at_most),
-            parser: self.0.parser,
+    // `or_not` operator makes the parser optional: on a recoverable failure, the input is rewound
+    // and `None` is returned.
+    fn or_not(self) -> OrNot<I, Self, O>
+    where
+        Self: Sized,
+    {
+        OrNot {
+            parser: self,
             phantom: PhantomData,
-        })
+        }
     }
 
-    fn collect<C: Container>(self) -> Collect<I, P, OP, C> {
-        Collect {
-            parser: self.0.parser,
-            range: self.0.range,
+    // `recover_with` operator recovers from the failures of the parser: the error is kept aside,
+    // the input is rewound and `fallback` is run in place of the parser, e.g to skip the input up
+    // to the next statement and produce a placeholder. This allows reporting several errors in one
+    // go, see `parse_with_config`.
+    fn recover_with<P2>(self, fallback: P2) -> RecoverWith<I, Self, O, P2>
+    where
+        P2: Parser<'input, I, O>,
+        Self: Sized,
+    {
+        RecoverWith {
+            parser: self,
+            fallback,
             phantom: PhantomData,
         }
     }
-}
 
-impl<I, P, OP> AtMost<I, P, OP> {
-    fn collect<C: Container>(self) -> Collect<I, P, OP, C> {
-        Collect {
-            parser: self.0.parser,
-            range: self.0.range,
+    // `recover_to` operator works like `recover_with` but the input is skipped with `skip`, whose
+    // output is discarded, and the parser produces the placeholder built by `placeholder` from the
+    // span that was skipped, e.g `expr.recover_to(skip_to_semicolon, Expr::Error)`. Later passes
+    // can then tell where the holes of the output are.
+    fn recover_to<P2, O2, F>(self, skip: P2, placeholder: F) -> RecoverTo<I, Self, O, P2, O2, F>
+    where
+        P2: Parser<'input, I, O2>,
+        F: Fn(I::Span) -> O,
+        Self: Sized,
+    {
+        RecoverTo {
+            parser: self,
+            skip,
+            placeholder,
             phantom: PhantomData,
         }
     }
-}
 
-impl<I, P, OP> Exactly<I, P, OP> {
-    fn collect<C: Container>(self) -> Collect<I, P, OP, C> {
-        Collect {
-            parser: self.0.parser,
-            range: self.0.range,
+    // `cut` operator turns the recoverable failures of the parser into fatal ones, committing to
+    // the current alternative. e.g `exact("let").right_bind(binding.cut())` reports the error
+    // inside of `binding` instead of backtracking and trying other statements once `let` was
+    // matched.
+    fn cut(self) -> Cut<I, Self, O>
+    where
+        Self: Sized,
+    {
+        Cut {
+            parser: self,
             phantom: PhantomData,
         }
     }
 }
 
 #[derive(Clone, Copy)]
-struct Collect<I, P, OP, C> {
+pub struct Expect<I, P, O> {
     parser: P,
-    range: RepeatedRange,
-    phantom: PhantomData<(I, OP, C)>,
+    description: &'static str,
+    phantom: PhantomData<(I, O)>,
 }
 
-impl<'input, I, P, OP, C> Parser<'input, I, C> for Collect<I, P, OP, C>
+impl<'input, I, P, O> Parser<'input, I, O> for Expect<I, P, O>
 where
     I: Input<'input>,
-    P: Parser<'input, I, OP>,
-    C: Container<Item = OP>,
+    P: Parser<'input, I, O>,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<C> {
-        let at_least = self.range.start();
-        let at_most = self.range.end();
-
-        let mut ret = C::default();
-        for _ in 0..at_least {
-            ret.push(self.parser.go(input_ref)?);
-        }
-
-        for out in (at_least..at_most.unwrap_or(usize::MAX))
-            .map(|_| self.parser.go(input_ref))
-            .take_while(|x| x.is_ok())
-        {
-            ret.push(out.unwrap());
-        }
-        Ok(ret)
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let (start, generation) = (input_ref.offset(), input_ref.error_generation());
+        self.parser
+            .go(input_ref)
+            .inspect_err(|_| input_ref.relabel_error(generation, start.into(), self.description))
     }
 }
 
 #[derive(Clone, Copy)]
-struct Bind<I, P1, OP1, P2, OP2> {
-    // First parser to run. The result of this parser will be discarded.
-    first_parser: P1,
-    // Second parser to run. The result of this parser will be returned.
-    second_parser: P2,
-    phantom: PhantomData<(I, OP1, OP2)>,
+pub struct OrError<I, P, O, F> {
+    parser: P,
+    error: F,
+    phantom: PhantomData<(I, O)>,
 }
 
-// `and` operator allows you to run two parsers and return the output of both in a
-// container.
-#[derive(Clone, Copy)]
-struct And<I, P1, OP1, P2, OP2>(Bind<I, P1, OP1, P2, OP2>);
+impl<'input, I, P, O, F> Parser<'input, I, O> for OrError<I, P, O, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    F: Fn() -> ParseError,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let checkpoint = input_ref.save();
+        let start = checkpoint.offset();
+        let generation = input_ref.error_generation();
+        match self.parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(error) => {
+                // Failures past the start mean an alternative got somewhere, its error is more
+                // accurate than the fallback. Failures of earlier attempts don't count.
+                if input_ref.error_generation() > generation
+                    && input_ref.error_offset().is_some_and(|at| at > start.into())
+                {
+                    return Err(error);
+                }
 
-// `left_bind` operator, similar to (<<) in haskell
-#[derive(Clone, Copy)]
-struct LeftBind<I, P1, OP1, P2, OP2>(Bind<I, P1, OP1, P2, OP2>);
+                input_ref.restore(checkpoint)?;
+                let (end, _) = input_ref.peek();
+                Err(input_ref.record_parse_error(start.into()..end.into(), (self.error)()))
+            }
+        }
+    }
+}
 
-// `right_bind` operator, similar to (>>) in haskell
 #[derive(Clone, Copy)]
-struct RightBind<I, P1, OP1, P2, OP2>(Bind<I, P1, OP1, P2, OP2>);
+pub struct Interned<'a, I, P, O> {
+    parser: P,
+    interner: &'a Interner,
+    phantom: PhantomData<(I, O)>,
+}
 
-impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, (OP1, OP2)> for And<I, P1, OP1, P2, OP2>
+impl<'input, I, P, O> Parser<'input, I, Symbol> for Interned<'_, I, P, O>
 where
     I: Input<'input>,
-    P1: Parser<'input, I, OP1>,
-    P2: Parser<'input, I, OP2>,
+    P: Parser<'input, I, O>,
+    O: AsRef<str>,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<(OP1, OP2)> {
-        Ok((
-            self.0.first_parser.go(input_ref)?,
-            self.0.second_parser.go(input_ref)?,
-        ))
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<Symbol> {
+        let out = self.parser.go(input_ref)?;
+        Ok(self.interner.intern(out.as_ref()))
     }
 }
 
-impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, OP1> for LeftBind<I, P1, OP1, P2, OP2>
-where
-    I: Input<'input>,
-    P1: Parser<'input, I, OP1>,
-    P2: Parser<'input, I, OP2>,
-{
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP1> {
-        let ret = self.0.first_parser.go(input_ref)?;
-        self.0.second_parser.go(input_ref)?;
-        Ok(ret)
+// Parser behind a pointer, see `Parser::boxed`. Copies are cheap and share the parser.
+pub struct Boxed<'input, I, O> {
+    parser: Rc<dyn Parser<'input, I, O> + 'input>,
+}
+
+impl<I, O> Clone for Boxed<'_, I, O> {
+    fn clone(&self) -> Self {
+        Boxed {
+            parser: Rc::clone(&self.parser),
+        }
     }
 }
 
-impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, OP2> for RightBind<I, P1, OP1, P2, OP2>
+impl<'input, I, O> Parser<'input, I, O> for Boxed<'input, I, O>
 where
     I: Input<'input>,
-    P1: Parser<'input, I, OP1>,
-    P2: Parser<'input, I, OP2>,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP2> {
-        self.0.first_parser.go(input_ref)?;
-        self.0.second_parser.go(input_ref)
+    // Boxed parsers are what recursive grammars are made of, so each call is one nested level
+    // deeper, see `InputRef::nest`.
+    #[inline]
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        input_ref.nest(|input_ref| self.parser.go(input_ref))
+    }
+
+    #[inline]
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        input_ref.nest(|input_ref| self.parser.go_check(input_ref))
+    }
+
+    #[inline]
+    fn go_scan(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        at_most: usize,
+        predicate: &dyn Fn(&O) -> bool,
+    ) -> usize {
+        self.parser.go_scan(input_ref, at_most, predicate)
+    }
+
+    #[inline]
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.parser.rules_out(input_ref)
     }
 }
 
-// `map` operator, works the same way as the map function on iterators (Functors
-// generally).
-#[derive(Clone, Copy)]
-struct Map<I, P, OP, F, U> {
-    // function mapping the output of the parser to the output desired.
-    mapper: F,
-    // parser we are mapping
-    parser: P,
-    phantom: PhantomData<(I, U, OP)>,
+// Grammar built once and shared, see `Parser::into_grammar`. Copies are cheap and can be sent to
+// other threads, e.g a grammar kept in a `static` `OnceLock` serving the requests of a server.
+// The lifetime of the inputs is part of the type though: a grammar in a `static` is a
+// `Grammar<'static, ..>`, it only parses inputs that own their source (e.g `Arc<str>`) or borrow a
+// `'static` one, not a `&str` borrowed from a request. Build one per input lifetime otherwise.
+pub struct Grammar<'input, I, O> {
+    parser: Arc<dyn Parser<'input, I, O> + Send + Sync + 'input>,
 }
 
-impl<'input, I, P, OP, F, U> Parser<'input, I, U> for Map<I, P, OP, F, U>
+impl<I, O> Clone for Grammar<'_, I, O> {
+    fn clone(&self) -> Self {
+        Grammar {
+            parser: Arc::clone(&self.parser),
+        }
+    }
+}
+
+impl<'input, I, O> Parser<'input, I, O> for Grammar<'input, I, O>
 where
     I: Input<'input>,
-    P: Parser<'input, I, OP>,
-    F: Fn(OP) -> U,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<U> {
-        let out = self.parser.go(input_ref)?;
-        Ok((self.mapper)(out))
+    // Boxed parsers are what recursive grammars are made of, so each call is one nested level
+    // deeper, see `InputRef::nest`.
+    #[inline]
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        input_ref.nest(|input_ref| self.parser.go(input_ref))
+    }
+
+    #[inline]
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        input_ref.nest(|input_ref| self.parser.go_check(input_ref))
+    }
+
+    #[inline]
+    fn go_scan(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        at_most: usize,
+        predicate: &dyn Fn(&O) -> bool,
+    ) -> usize {
+        self.parser.go_scan(input_ref, at_most, predicate)
+    }
+
+    #[inline]
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.parser.rules_out(input_ref)
     }
 }
 
-// `Exact` combinator matches an exact sequence of tokens.
-// Returns an error if there is a mismatch.
-#[derive(Clone, Copy)]
-struct Exact<I, T> {
-    seq: T,
-    phantom: PhantomData<I>,
+// Rule of a grammar, see `Parser::rule`. Its copies share the results cached, so that a rule used
+// in several places of the grammar is parsed once at a given offset.
+pub struct Rule<'input, I: Input<'input>, P, O> {
+    parser: P,
+    memo: Rc<Memo<I::Offset, I::Token, O>>,
+}
+
+// Results of a rule by offset, for the parse with the `generation` it belongs to.
+struct Memo<Off, T, O> {
+    generation: Cell<u64>,
+    entries: RefCell<BTreeMap<usize, MemoEntry<Off, T, O>>>,
+}
+
+struct MemoEntry<Off, T, O> {
+    // `None` if the rule failed.
+    out: Option<O>,
+    end: Off,
+    tokens: usize,
+    // Furthest failure recorded while parsing the rule, recorded again when the result is reused.
+    error: Option<(usize, ErrorReport<T>)>,
 }
 
-fn exact<'input, I, T>(seq: T) -> Exact<I, T>
+impl<'input, I, P, O> Clone for Rule<'input, I, P, O>
 where
     I: Input<'input>,
-    T: OrderedSequence<Token = I::Token>,
+    P: Clone,
 {
-    Exact {
-        seq,
-        phantom: PhantomData,
+    fn clone(&self) -> Self {
+        Rule {
+            parser: self.parser.clone(),
+            memo: Rc::clone(&self.memo),
+        }
     }
 }
 
-impl<'input, I, T> Parser<'input, I, I::Slice> for Exact<I, T>
+impl<'input, I, P, O> Parser<'input, I, O> for Rule<'input, I, P, O>
 where
     I: Input<'input>,
-    T: OrderedSequence<Token = I::Token>,
+    P: Parser<'input, I, O>,
+    O: Clone,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
-        let start = input_ref.offset();
+    // Rules call each other recursively, so each call is one nested level deeper.
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        input_ref.nest(|input_ref| self.memoized(input_ref))
+    }
+}
 
-        if let Some(_token) = self.seq.iterator().find_map(|seq_token| {
-            if Some(seq_token) == input_ref.peek_token() {
-                input_ref.next_token();
-                None
-            } else {
-                Some(())
+impl<'input, I, P, O> Rule<'input, I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    O: Clone,
+{
+    fn memoized(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let Some(capacity) = input_ref.packrat() else {
+            return self.parser.go(input_ref);
+        };
+
+        let start = input_ref.raw_offset();
+        {
+            let mut entries = self.memo.entries.borrow_mut();
+            if self.memo.generation.get() != input_ref.generation() {
+                self.memo.generation.set(input_ref.generation());
+                entries.clear();
+            }
+            if let Some(entry) = entries.get(&start.into()) {
+                input_ref.count_memo_hit();
+                if let Some((at, report)) = &entry.error {
+                    let report = report.try_clone().expect("reports cached can be copied");
+                    input_ref.record_error_at(*at, report);
+                }
+                return match &entry.out {
+                    Some(out) => {
+                        input_ref.advance(entry.end, entry.tokens);
+                        Ok(out.clone())
+                    }
+                    None => Err(ParseError::SyntaxError),
+                };
+            }
+        }
+
+        input_ref.count_memo_miss();
+        let checkpoint = input_ref.save();
+        let tokens = input_ref.stats().tokens();
+        let result = self.parser.go(input_ref);
+        let cached = match &result {
+            Ok(out) => Some(Some(out.clone())),
+            Err(ParseError::SyntaxError) => Some(None),
+            // More input may change the outcome, and an aborted parse is over anyway.
+            Err(_) => None,
+        };
+        // The furthest failure is recorded again along with the result, unless it comes from user
+        // code: the run isn't cached then.
+        let error = match input_ref.furthest_error() {
+            Some((at, report)) if *at >= start.into() => {
+                report.try_clone().map(|report| Some((*at, report)))
+            }
+            _ => Some(None),
+        };
+        let cached = cached.filter(|_| !input_ref.emitted_since(&checkpoint));
+        if let Some((out, error)) = cached.zip(error) {
+            let entry = MemoEntry {
+                out,
+                end: input_ref.raw_offset(),
+                tokens: input_ref.stats().tokens() - tokens,
+                error,
+            };
+            let mut entries = self.memo.entries.borrow_mut();
+            // Parsing goes forward, the results furthest behind are the least likely to be
+            // looked up again.
+            if entries.len() >= capacity {
+                entries.pop_first();
+            }
+            if capacity > 0 {
+                entries.insert(start.into(), entry);
             }
-        }) {
-            Err(ParseError::SyntaxError)
-        } else {
-            Ok(input_ref.slice(start, input_ref.offset()))
         }
+        if result.is_ok() {
+            input_ref.commit(checkpoint);
+        }
+        result
     }
 }
 
-// `End` combinator matches the EOI (end of input).
-// Returns an error if the input is not yet fully consumed.
 #[derive(Clone, Copy)]
-struct End<I> {
-    phantom: PhantomData<I>,
+pub struct Labelled<I, P, O> {
+    parser: P,
+    name: &'static str,
+    phantom: PhantomData<(I, O)>,
 }
 
-fn end<'input, I>() -> End<I>
+impl<'input, I, P, O> Parser<'input, I, O> for Labelled<I, P, O>
 where
     I: Input<'input>,
+    P: Parser<'input, I, O>,
 {
-    End {
-        phantom: PhantomData,
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let (start, generation) = (input_ref.offset(), input_ref.error_generation());
+        input_ref.enter_rule(self.name);
+        let result = self.parser.go(input_ref);
+        input_ref.exit_rule();
+        result.inspect_err(|_| input_ref.push_error_context(generation, start.into(), self.name))
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        let (start, generation) = (input_ref.offset(), input_ref.error_generation());
+        input_ref.enter_rule(self.name);
+        let result = self.parser.go_check(input_ref);
+        input_ref.exit_rule();
+        result.inspect_err(|_| input_ref.push_error_context(generation, start.into(), self.name))
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        let (start, generation) = (input_ref.offset(), input_ref.error_generation());
+        let ruled_out = self.parser.rules_out(input_ref);
+        if ruled_out {
+            input_ref.push_error_context(generation, start.into(), self.name);
+        }
+        ruled_out
     }
 }
 
-impl<'input, I> Parser<'input, I, ()> for End<I>
+#[derive(Clone, Copy)]
+pub struct Nested<I, P, O> {
+    parser: P,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O> Parser<'input, I, O> for Nested<I, P, O>
 where
     I: Input<'input>,
+    P: Parser<'input, I, O>,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
-        if input_ref.peek_token().is_some() {
-            Err(ParseError::SyntaxError)
-        } else {
-            Ok(())
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        input_ref.nest(|input_ref| self.parser.go(input_ref))
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        input_ref.nest(|input_ref| self.parser.go_check(input_ref))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct LeftRecursive<I, P, O> {
+    parser: P,
+    name: &'static str,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O> Parser<'input, I, O> for LeftRecursive<I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    O: Clone + 'static,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let start = input_ref.raw_offset();
+        // The rule calling itself gets the match grown so far.
+        if let Some(seed) = input_ref.seed::<O>(self.name, start) {
+            return match seed {
+                Some((out, end, tokens)) => {
+                    input_ref.advance(end, tokens);
+                    Ok(out)
+                }
+                None => Err(ParseError::SyntaxError),
+            };
         }
+
+        input_ref.push_seed(self.name, start);
+        let result = input_ref.nest(|input_ref| self.grow(input_ref));
+        input_ref.pop_seed();
+        result
+    }
+}
+
+impl<'input, I, P, O> LeftRecursive<I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    O: Clone + 'static,
+{
+    fn grow(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        // Match the longest one grew from.
+        let mut previous = None;
+        let mut longest: Option<(O, I::Offset, usize)> = None;
+        loop {
+            let checkpoint = input_ref.save();
+            let tokens = input_ref.stats().tokens();
+            let result = self.parser.go(input_ref);
+            let end = input_ref.raw_offset();
+            match result {
+                Ok(out)
+                    if longest
+                        .as_ref()
+                        .is_none_or(|(_, at, _)| end.into() > (*at).into()) =>
+                {
+                    let grown = (out, end, input_ref.stats().tokens() - tokens);
+                    input_ref.restore(checkpoint)?;
+                    input_ref.grow_seed(Some(grown.clone()));
+                    previous = longest.replace(grown);
+                }
+                Err(error) if error.is_fatal() => return Err(error),
+                Err(error) if longest.is_none() => {
+                    input_ref.restore(checkpoint)?;
+                    return Err(error);
+                }
+                _ => {
+                    input_ref.restore(checkpoint)?;
+                    break;
+                }
+            }
+        }
+
+        // The longest match is parsed once more to keep what it emitted, e.g the errors recovered
+        // from along the way.
+        input_ref.grow_seed(previous);
+        self.parser.go(input_ref)
     }
 }
 
-// `Any` combinator matches any token except the EOI (end of input).
-// Returns an error if the input was totally consumed (i.e empty).
 #[derive(Clone, Copy)]
-struct Any<I> {
-    phantom: PhantomData<I>,
+pub struct OrNot<I, P, O> {
+    parser: P,
+    phantom: PhantomData<(I, O)>,
 }
 
-fn any<'input, I>() -> Any<I>
+impl<'input, I, P, O> Parser<'input, I, Option<O>> for OrNot<I, P, O>
 where
     I: Input<'input>,
+    P: Parser<'input, I, O>,
 {
-    Any {
-        phantom: PhantomData,
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<Option<O>> {
+        if self.parser.rules_out(input_ref) {
+            return Ok(None);
+        }
+        let checkpoint = input_ref.save();
+        match self.parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(Some(out))
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(_) => {
+                input_ref.restore(checkpoint)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        if self.parser.rules_out(input_ref) {
+            return Ok(());
+        }
+        let checkpoint = input_ref.save();
+        match self.parser.go_check(input_ref) {
+            Ok(()) => {
+                input_ref.commit(checkpoint);
+                Ok(())
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(_) => input_ref.restore(checkpoint),
+        }
     }
 }
 
-impl<'input, I> Parser<'input, I, I::Token> for Any<I>
+#[derive(Clone, Copy)]
+pub struct RecoverWith<I, P, O, P2> {
+    parser: P,
+    fallback: P2,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O, P2> Parser<'input, I, O> for RecoverWith<I, P, O, P2>
 where
     I: Input<'input>,
+    P: Parser<'input, I, O>,
+    P2: Parser<'input, I, O>,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Token> {
-        if input_ref.peek_token().is_some() {
-            Ok(input_ref.next_token().unwrap())
-        } else {
-            Err(ParseError::SyntaxError)
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let checkpoint = input_ref.save();
+        match self.parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(error) => {
+                let report = input_ref.take_error(error);
+                input_ref.restore(checkpoint)?;
+                match self.fallback.go(input_ref) {
+                    Ok(out) => {
+                        input_ref.recover_error(report)?;
+                        Ok(out)
+                    }
+                    Err(error) => {
+                        input_ref.record_error(report);
+                        Err(error)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RecoverTo<I, P, O, P2, O2, F> {
+    parser: P,
+    skip: P2,
+    placeholder: F,
+    phantom: PhantomData<(I, O, O2)>,
+}
+
+impl<'input, I, P, O, P2, O2, F> Parser<'input, I, O> for RecoverTo<I, P, O, P2, O2, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    P2: Parser<'input, I, O2>,
+    F: Fn(I::Span) -> O,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let checkpoint = input_ref.save();
+        let prev_state = checkpoint.offset();
+        match self.parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(error) => {
+                let report = input_ref.take_error(error);
+                input_ref.restore(checkpoint)?;
+                match self.skip.go(input_ref) {
+                    Ok(_) => {
+                        input_ref.recover_error(report)?;
+                        Ok((self.placeholder)(
+                            input_ref.span(prev_state, input_ref.offset()),
+                        ))
+                    }
+                    Err(error) => {
+                        input_ref.record_error(report);
+                        Err(error)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Cut<I, P, O> {
+    parser: P,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O> Parser<'input, I, O> for Cut<I, P, O>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        self.parser.go(input_ref).map_err(|error| match error {
+            ParseError::SyntaxError => ParseError::Fatal(None),
+            ParseError::Custom(custom) => ParseError::Fatal(Some(custom)),
+            ParseError::Fatal(_) | ParseError::Incomplete { .. } => error,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Or<P1, P2> {
+    first_parser: P1,
+    second_parser: P2,
+}
+
+impl<'input, I, P1, P2, OP> Parser<'input, I, OP> for Or<P1, P2>
+where
+    I: Input<'input>,
+    P1: Parser<'input, I, OP>,
+    P2: Parser<'input, I, OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP> {
+        if self.first_parser.rules_out(input_ref) {
+            return self.second_parser.go(input_ref);
+        }
+        let checkpoint = input_ref.save();
+        match self.first_parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(_) => {
+                input_ref.restore(checkpoint)?;
+                self.second_parser.go(input_ref)
+            }
+        }
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        if self.first_parser.rules_out(input_ref) {
+            return self.second_parser.go_check(input_ref);
+        }
+        let checkpoint = input_ref.save();
+        match self.first_parser.go_check(input_ref) {
+            Ok(()) => {
+                input_ref.commit(checkpoint);
+                Ok(())
+            }
+            Err(error) if error.is_fatal() => Err(error),
+            Err(_) => {
+                input_ref.restore(checkpoint)?;
+                self.second_parser.go_check(input_ref)
+            }
         }
     }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.first_parser.rules_out(input_ref) && self.second_parser.rules_out(input_ref)
+    }
+}
+
+// `choice` primitive, tries the parsers of a tuple in turn until one matches, e.g
+// `choice((a, b, c))` rather than `a.or(b).or(c)` for long lists of alternatives. `optimize`
+// turns such chains of `or` into a choice.
+#[derive(Clone, Copy)]
+pub struct Choice<T>(T);
+
+pub const fn choice<T>(parsers: T) -> Choice<T> {
+    Choice(parsers)
+}
+
+macro_rules! impl_choice {
+    ($($parser:ident $idx:tt),+; $last:ident $last_idx:tt) => {
+        impl<'input, I, OP, $($parser,)+ $last> Parser<'input, I, OP> for Choice<($($parser,)+ $last,)>
+        where
+            I: Input<'input>,
+            $($parser: Parser<'input, I, OP>,)+
+            $last: Parser<'input, I, OP>,
+        {
+            fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP> {
+                $(
+                    if !self.0.$idx.rules_out(input_ref) {
+                        let checkpoint = input_ref.save();
+                        match self.0.$idx.go(input_ref) {
+                            Ok(out) => {
+                                input_ref.commit(checkpoint);
+                                return Ok(out);
+                            }
+                            Err(error) if error.is_fatal() => return Err(error),
+                            Err(_) => input_ref.restore(checkpoint)?,
+                        }
+                    }
+                )+
+                self.0.$last_idx.go(input_ref)
+            }
+
+            fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+                $(
+                    if !self.0.$idx.rules_out(input_ref) {
+                        let checkpoint = input_ref.save();
+                        match self.0.$idx.go_check(input_ref) {
+                            Ok(()) => {
+                                input_ref.commit(checkpoint);
+                                return Ok(());
+                            }
+                            Err(error) if error.is_fatal() => return Err(error),
+                            Err(_) => input_ref.restore(checkpoint)?,
+                        }
+                    }
+                )+
+                self.0.$last_idx.go_check(input_ref)
+            }
+
+            fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+                $(self.0.$idx.rules_out(input_ref) &&)+ self.0.$last_idx.rules_out(input_ref)
+            }
+        }
+    };
 }
 
-// `OneOf` primitive, matches one of the sequence passed in as a parameter
+impl_choice!(P1 0; P2 1);
+impl_choice!(P1 0, P2 1; P3 2);
+impl_choice!(P1 0, P2 1, P3 2; P4 3);
+impl_choice!(P1 0, P2 1, P3 2, P4 3; P5 4);
+impl_choice!(P1 0, P2 1, P3 2, P4 3, P5 4; P6 5);
+impl_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5; P7 6);
+impl_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5, P7 6; P8 7);
+
+// `dispatch` primitive, picks the alternatives to try by the token under the cursor rather than
+// trying each in turn: each branch is registered along with the tokens it can start with, e.g
+// `dispatch().on(['"'], string).on(['t', 'f'], boolean)`. Branches sharing a first token are tried
+// in the order they were added. Chain with `or` to fall back on a branch starting with anything.
 #[derive(Clone)]
-struct OneOf<I, S> {
-    container: Vec<S>,
+pub struct Dispatch<I, T, B> {
+    branches: B,
+    count: usize,
+    table: HashMap<T, Vec<usize>>,
+    // First tokens in the order they were added, for the error report.
+    tokens: Vec<T>,
     phantom: PhantomData<I>,
 }
 
-fn one_of<'input, I, S>(container: Vec<S>) -> OneOf<I, S>
+pub fn dispatch<'input, I>() -> Dispatch<I, I::Token, ()>
 where
     I: Input<'input>,
-    S: OrderedSequence<Token = I::Token>,
+    I::Token: Hash,
 {
-    OneOf {
-        container,
+    Dispatch {
+        branches: (),
+        count: 0,
+        table: HashMap::new(),
+        tokens: Vec::new(),
         phantom: PhantomData,
     }
 }
 
-impl<'input, I, S> Parser<'input, I, I::Slice> for OneOf<I, S>
+impl<I, T, B> Dispatch<I, T, B>
 where
-    I: Input<'input>,
-    S: OrderedSequence<Token = I::Token>,
-    I::Token: std::fmt::Display + std::fmt::Debug,
-    I::Slice: std::fmt::Display,
+    T: Copy + Eq + Hash,
 {
-    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
-        let start_offset = input_ref.offset();
-
-        for seq in self.container.iter() {
-            if let Some(_) = seq.iterator().find_map(|seq_token| {
-                if Some(seq_token) == input_ref.peek_token() {
-                    input_ref.next_token();
-                    None
-                } else {
-                    Some(())
-                }
-            }) {
-                input_ref.rewind(start_offset);
-            } else {
-                return Ok(input_ref.slice(start_offset, input_ref.offset()));
+    pub fn on<P>(
+        mut self,
+        first: impl IntoIterator<Item = T>,
+        parser: P,
+    ) -> Dispatch<I, T, (B, P)> {
+        for token in first {
+            let branches = self.table.entry(token).or_default();
+            if branches.is_empty() {
+                self.tokens.push(token);
+            }
+            if branches.last() != Some(&self.count) {
+                branches.push(self.count);
             }
         }
+        Dispatch {
+            branches: (self.branches, parser),
+            count: self.count + 1,
+            table: self.table,
+            tokens: self.tokens,
+            phantom: PhantomData,
+        }
+    }
+}
+
+// Branches of a `dispatch`, nested as `(((), P1), P2)...` in the order they were added.
+pub trait Branches<'input, I, O>
+where
+    I: Input<'input>,
+{
+    // Runs the branch `idx` out of `count`.
+    fn go_branch(
+        &self,
+        idx: usize,
+        count: usize,
+        input_ref: &mut InputRef<'input, '_, I>,
+    ) -> ParseResult<O>;
+}
+
+impl<'input, I, O> Branches<'input, I, O> for ()
+where
+    I: Input<'input>,
+{
+    fn go_branch(&self, _: usize, _: usize, _: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
         Err(ParseError::SyntaxError)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<'input, I, O, B, P> Branches<'input, I, O> for (B, P)
+where
+    I: Input<'input>,
+    B: Branches<'input, I, O>,
+    P: Parser<'input, I, O>,
+{
+    #[inline]
+    fn go_branch(
+        &self,
+        idx: usize,
+        count: usize,
+        input_ref: &mut InputRef<'input, '_, I>,
+    ) -> ParseResult<O> {
+        if idx + 1 == count {
+            self.1.go(input_ref)
+        } else {
+            self.0.go_branch(idx, count - 1, input_ref)
+        }
+    }
+}
 
-    macro_rules! input_ref {
-        ($e: expr) => {
-            InputRef::new(&$e)
+impl<'input, I, B, O> Parser<'input, I, O> for Dispatch<I, I::Token, B>
+where
+    I: Input<'input>,
+    I::Token: Hash,
+    B: Branches<'input, I, O>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let branches = match input_ref
+            .peek_token()
+            .and_then(|token| self.table.get(&token))
+        {
+            Some(branches) => branches,
+            None => {
+                let expected = self.tokens.iter().map(|&token| Expected::Token(token));
+                input_ref.record_expected(expected.collect::<ExpectedSet<_>>());
+                return Err(input_ref.mismatch(Some(1)));
+            }
         };
+
+        let (last, rest) = branches
+            .split_last()
+            .expect("tokens map to a branch at least");
+        for &idx in rest {
+            let checkpoint = input_ref.save();
+            match self.branches.go_branch(idx, self.count, input_ref) {
+                Ok(out) => {
+                    input_ref.commit(checkpoint);
+                    return Ok(out);
+                }
+                Err(error) if error.is_fatal() => return Err(error),
+                Err(_) => input_ref.restore(checkpoint)?,
+            }
+        }
+        self.branches.go_branch(*last, self.count, input_ref)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Padded<I, P1, OP1, P2, OP2> {
+    parser: P1,
+    padded_by: P2,
+    phantom: PhantomData<(I, OP1, OP2)>,
+}
+
+impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, OP1> for Padded<I, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP1> {
+        _ = self.padded_by.go(input_ref);
+
+        let out = self.parser.go(input_ref)?;
+
+        _ = self.padded_by.go(input_ref);
+
+        Ok(out)
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        _ = self.padded_by.go_check(input_ref);
+        self.parser.go_check(input_ref)?;
+        _ = self.padded_by.go_check(input_ref);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Filter<I, P, O, F> {
+    parser: P,
+    filter_func: F,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O, F> Parser<'input, I, O> for Filter<I, P, O, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    F: Fn(&O) -> bool,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let checkpoint = input_ref.save();
+        let prev_state = checkpoint.offset();
+        self.parser.go(input_ref).and_then(|out| {
+            if (self.filter_func)(&out) {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            } else {
+                let end = input_ref.offset();
+                input_ref.restore(checkpoint)?;
+                let found = input_ref.peek_token();
+                input_ref.record_error(
+                    ErrorReport::new(prev_state.into()..end.into(), ExpectedSet::new(), found)
+                        .with_code(ErrorCode::RejectedToken),
+                );
+                Err(ParseError::SyntaxError)
+            }
+        })
+    }
+
+    fn go_scan(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        at_most: usize,
+        predicate: &dyn Fn(&O) -> bool,
+    ) -> usize {
+        let predicate = |out: &O| (self.filter_func)(out) && predicate(out);
+        self.parser.go_scan(input_ref, at_most, &predicate)
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.parser.rules_out(input_ref)
+    }
+}
+
+#[derive(Clone)]
+pub struct Guard<I, P, O, F> {
+    parser: P,
+    predicate: F,
+    message: String,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O, F> Parser<'input, I, O> for Guard<I, P, O, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    F: Fn(&O) -> bool,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let checkpoint = input_ref.save();
+        let prev_state = checkpoint.offset();
+        self.parser.go(input_ref).and_then(|out| {
+            if (self.predicate)(&out) {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            } else {
+                let end = input_ref.offset();
+                input_ref.restore(checkpoint)?;
+                input_ref.record_custom(
+                    prev_state.into()..end.into(),
+                    CustomError::new(self.message.as_str()).with_code(ErrorCode::RejectedToken),
+                );
+                Err(ParseError::SyntaxError)
+            }
+        })
+    }
+}
+
+// This is a bit too awkward. Maybe put all the entities related to a specific parser into a
+// module.
+#[derive(Debug, Clone, Copy)]
+enum RepeatedRange {
+    AtLeast(usize),
+    Between(usize, usize),
+    Exactly(usize),
+}
+
+impl RepeatedRange {
+    #[inline]
+    fn start(&self) -> usize {
+        match *self {
+            RepeatedRange::AtLeast(start) => start,
+            RepeatedRange::Between(start, _end) => start,
+            RepeatedRange::Exactly(count) => count,
+        }
+    }
+
+    #[inline]
+    fn end(&self) -> Option<usize> {
+        match *self {
+            RepeatedRange::AtLeast(_start) => None,
+            RepeatedRange::Between(_start, end) => Some(end),
+            RepeatedRange::Exactly(count) => Some(count),
+        }
+    }
+
+    // Number of outputs to make room for upfront. The bounds may be way off, e.g
+    // `at_most(usize::MAX)` used as a safety net, or an `exactly(n)` count read from a length
+    // prefix the input lies about, so only a limited amount of them is trusted. Containers grow
+    // past it as items are parsed.
+    #[inline]
+    fn capacity_hint(&self) -> usize {
+        const TRUSTED_UPPER_BOUND: usize = 64;
+        let hint = match *self {
+            RepeatedRange::AtLeast(start) => start,
+            RepeatedRange::Between(_, end) => end,
+            RepeatedRange::Exactly(count) => count,
+        };
+        hint.min(TRUSTED_UPPER_BOUND)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Repeated<I, P, OP> {
+    parser: P,
+    range: RepeatedRange,
+    phantom: PhantomData<(I, OP)>,
+}
+
+#[derive(Clone, Copy)]
+pub struct AtLeast<I, P, OP>(Repeated<I, P, OP>);
+
+#[derive(Clone, Copy)]
+pub struct Exactly<I, P, OP>(Repeated<I, P, OP>);
+
+#[derive(Clone, Copy)]
+pub struct AtMost<I, P, OP>(Repeated<I, P, OP>);
+
+impl<I, P, OP> Repeated<I, P, OP> {
+    pub fn at_least(self, at_least: usize) -> AtLeast<I, P, OP> {
+        AtLeast(Repeated {
+            range: RepeatedRange::AtLeast(at_least),
+            parser: self.parser,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn exactly(self, count: usize) -> Exactly<I, P, OP> {
+        Exactly(Repeated {
+            range: RepeatedRange::Exactly(count),
+            parser: self.parser,
+            phantom: PhantomData,
+        })
+    }
+
+    // Same as `exactly` but the count is known at compile time, so that the outputs can be
+    // collected in an array, e.g the fields of a fixed-size record.
+    pub fn exactly_n<const N: usize>(self) -> ExactlyN<I, P, OP, N> {
+        ExactlyN(Repeated {
+            range: RepeatedRange::Exactly(N),
+            parser: self.parser,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ExactlyN<I, P, OP, const N: usize>(Repeated<I, P, OP>);
+
+impl<I, P, OP, const N: usize> ExactlyN<I, P, OP, N> {
+    pub fn collect_array(self) -> CollectArray<I, P, OP, N> {
+        CollectArray(self.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CollectArray<I, P, OP, const N: usize>(Repeated<I, P, OP>);
+
+impl<'input, I, P, OP, const N: usize> Parser<'input, I, [OP; N]> for CollectArray<I, P, OP, N>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<[OP; N]> {
+        // Filled in place rather than collected in a vector, so that nothing is allocated.
+        let mut ret = [const { None }; N];
+        let mut slots = ret.iter_mut();
+        repeat(&self.0.parser, self.0.range, input_ref, |out| {
+            *slots.next().expect("exactly N outputs are parsed") = Some(out)
+        })?;
+        Ok(ret.map(|out| out.expect("exactly N outputs are parsed")))
+    }
+}
+
+// Terminal methods shared by the range builders, turning the repetition into a parser.
+macro_rules! impl_repeated_terminals {
+    ($($builder:ident),+) => {
+        $(
+            impl<I, P, T> $builder<I, P, Result<T, ParseError>> {
+                pub fn try_collect<C: Container<T>>(self) -> TryCollect<I, P, T, C> {
+                    TryCollect {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        phantom: PhantomData,
+                    }
+                }
+            }
+
+            impl<I, P, K, V> $builder<I, P, (K, V)> {
+                pub fn collect_map<C: MapContainer<K, V>>(
+                    self,
+                    duplicates: DuplicateKeys,
+                ) -> CollectMap<I, P, K, V, C> {
+                    CollectMap {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        duplicates,
+                        phantom: PhantomData,
+                    }
+                }
+            }
+
+            impl<I, P, OP> $builder<I, P, OP> {
+                pub fn collect<C: Container<OP>>(self) -> Collect<I, P, OP, C> {
+                    Collect {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        phantom: PhantomData,
+                    }
+                }
+
+                pub fn collect_into<C: Container<OP>>(
+                    self,
+                    buffer: &RefCell<C>,
+                ) -> CollectInto<'_, I, P, OP, C> {
+                    CollectInto {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        buffer,
+                        phantom: PhantomData,
+                    }
+                }
+
+                pub fn fold_collect<A, F>(self, init: A, fold: F) -> Fold<I, P, OP, A, F>
+                where
+                    A: Clone,
+                    F: Fn(A, OP) -> A,
+                {
+                    Fold {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        init,
+                        fold,
+                        phantom: PhantomData,
+                    }
+                }
+
+                pub fn collect_parsed<C>(self) -> CollectParsed<I, P, OP, C> {
+                    CollectParsed {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        phantom: PhantomData,
+                    }
+                }
+
+                pub fn to_slice(self) -> ToSlice<I, P, OP> {
+                    ToSlice(Repeated {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        phantom: PhantomData,
+                    })
+                }
+
+                pub fn last(self) -> Last<I, P, OP> {
+                    Last(Repeated {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        phantom: PhantomData,
+                    })
+                }
+
+                pub fn for_each<F>(self, callback: F) -> ForEach<I, P, OP, F>
+                where
+                    F: Fn(OP),
+                {
+                    ForEach {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        callback,
+                        phantom: PhantomData,
+                    }
+                }
+
+                pub fn write_into<W, F, E>(self, writer: &RefCell<W>, write: F) -> WriteInto<'_, I, P, OP, W, F>
+                where
+                    F: Fn(&mut W, OP) -> Result<(), E>,
+                {
+                    WriteInto {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        writer,
+                        write,
+                        phantom: PhantomData,
+                    }
+                }
+
+                pub fn max_by<F>(self, compare: F) -> MaxBy<I, P, OP, F>
+                where
+                    F: Fn(&OP, &OP) -> Ordering,
+                {
+                    MaxBy {
+                        parser: self.0.parser,
+                        range: self.0.range,
+                        compare,
+                        phantom: PhantomData,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_repeated_terminals!(AtLeast, AtMost, Exactly);
+
+impl<I, P, OP> AtLeast<I, P, OP> {
+    pub fn at_most(self, at_most: usize) -> AtMost<I, P, OP> {
+        let at_least = self.0.range.start();
+
+        AtMost(Repeated {
+            range: RepeatedRange::Between(at_least, at_most),
+            parser: self.0.parser,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Collect<I, P, OP, C> {
+    parser: P,
+    range: RepeatedRange,
+    phantom: PhantomData<(I, OP, C)>,
+}
+
+impl<'input, I, P, OP, C> Parser<'input, I, C> for Collect<I, P, OP, C>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    C: Container<OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<C> {
+        let mut ret = C::with_capacity_hint(self.range.capacity_hint());
+        try_repeat(&self.parser, self.range, input_ref, |out| {
+            ret.try_push(out).map_err(ParseError::Custom)
+        })?;
+        Ok(ret)
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        skip_repeat(&Checking::new(&self.parser), self.range, input_ref)
+    }
+}
+
+// Parser of `()` running `parser` in check mode, see `Parser::go_check`.
+struct Checking<'p, P, OP> {
+    parser: &'p P,
+    phantom: PhantomData<OP>,
+}
+
+impl<'p, P, OP> Checking<'p, P, OP> {
+    fn new(parser: &'p P) -> Self {
+        Checking {
+            parser,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I, P, OP> Parser<'input, I, ()> for Checking<'_, P, OP>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    #[inline]
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.parser.go_check(input_ref)
+    }
+
+    #[inline]
+    fn go_scan(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        at_most: usize,
+        predicate: &dyn Fn(&()) -> bool,
+    ) -> usize {
+        self.parser.go_scan(input_ref, at_most, &|_| predicate(&()))
+    }
+
+    #[inline]
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.parser.rules_out(input_ref)
+    }
+}
+
+// Runs `parser` as many times as `range` allows, handing the outputs over to `push`.
+fn repeat<'input, I, P, OP>(
+    parser: &P,
+    range: RepeatedRange,
+    input_ref: &mut InputRef<'input, '_, I>,
+    mut push: impl FnMut(OP),
+) -> ParseResult<()>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    try_repeat(parser, range, input_ref, |out| {
+        push(out);
+        Ok(())
+    })
+}
+
+// Same as `repeat` for a parser whose outputs aren't needed: the runs it matches are moved past in
+// one go when it can scan them, see `Parser::go_scan`.
+fn skip_repeat<'input, I, P>(
+    parser: &P,
+    range: RepeatedRange,
+    input_ref: &mut InputRef<'input, '_, I>,
+) -> ParseResult<()>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, ()>,
+{
+    let scanned = parser.go_scan(input_ref, range.end().unwrap_or(usize::MAX), &|_| true);
+    let range = match range {
+        RepeatedRange::AtLeast(start) => RepeatedRange::AtLeast(start.saturating_sub(scanned)),
+        RepeatedRange::Between(start, end) => {
+            RepeatedRange::Between(start.saturating_sub(scanned), end - scanned)
+        }
+        RepeatedRange::Exactly(count) => RepeatedRange::Exactly(count - scanned),
+    };
+    repeat(parser, range, input_ref, |()| {})
+}
+
+// Same as `repeat` but `push` can reject an output, which makes the repetition fail right away
+// with the error recorded over the output's input.
+fn try_repeat<'input, I, P, OP>(
+    parser: &P,
+    range: RepeatedRange,
+    input_ref: &mut InputRef<'input, '_, I>,
+    mut push: impl FnMut(OP) -> Result<(), ParseError>,
+) -> ParseResult<()>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    let at_least = range.start();
+    let at_most = range.end();
+
+    let mut count = 0;
+    loop {
+        if count >= at_least
+            && (at_most.is_some_and(|at_most| count >= at_most) || parser.rules_out(input_ref))
+        {
+            return Ok(());
+        }
+
+        let checkpoint = input_ref.save();
+        let start = checkpoint.offset();
+        let out = match parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                out
+            }
+            Err(error) if count < at_least || error.is_fatal() => return Err(error),
+            // The input the failed attempt consumed isn't part of the repetition.
+            Err(_) => return input_ref.restore(checkpoint),
+        };
+        if let Err(error) = push(out) {
+            let end = input_ref.offset();
+            return Err(input_ref.record_parse_error(start.into()..end.into(), error));
+        }
+        count += 1;
+    }
+}
+
+// Same as `Collect` but the outputs are results, the first error makes the repetition fail, e.g
+// items converted with `map` to numbers that may overflow.
+#[derive(Clone, Copy)]
+pub struct TryCollect<I, P, T, C> {
+    parser: P,
+    range: RepeatedRange,
+    phantom: PhantomData<(I, T, C)>,
+}
+
+impl<'input, I, P, T, C> Parser<'input, I, C> for TryCollect<I, P, T, C>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, Result<T, ParseError>>,
+    C: Container<T>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<C> {
+        let mut ret = C::with_capacity_hint(self.range.capacity_hint());
+        try_repeat(&self.parser, self.range, input_ref, |out| {
+            ret.try_push(out?).map_err(ParseError::Custom)
+        })?;
+        Ok(ret)
+    }
+}
+
+// Same as `Collect` but the outputs are appended to a buffer provided by the caller, so that it
+// can be reused from a parse to the next, e.g a line of a large file to the next. The outputs are
+// pushed as they are parsed and never taken back: those parsed before a failure are left in the
+// buffer, and so are those of a repetition that succeeded but is rolled back later on, e.g by an
+// enclosing `or` trying something else. Clear the buffer before each attempt if that matters.
+#[derive(Clone, Copy)]
+pub struct CollectInto<'a, I, P, OP, C> {
+    parser: P,
+    range: RepeatedRange,
+    buffer: &'a RefCell<C>,
+    phantom: PhantomData<(I, OP)>,
+}
+
+impl<'input, I, P, OP, C> Parser<'input, I, ()> for CollectInto<'_, I, P, OP, C>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    C: Container<OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        try_repeat(&self.parser, self.range, input_ref, |out| {
+            self.buffer
+                .borrow_mut()
+                .try_push(out)
+                .map_err(ParseError::Custom)
+        })
+    }
+}
+
+// Reduces the outputs of a repetition as they are parsed, e.g digits into a number, rather than
+// collecting them first. Each parse starts from a clone of `init`.
+#[derive(Clone, Copy)]
+pub struct Fold<I, P, OP, A, F> {
+    parser: P,
+    range: RepeatedRange,
+    init: A,
+    fold: F,
+    phantom: PhantomData<(I, OP)>,
+}
+
+impl<'input, I, P, OP, A, F> Parser<'input, I, A> for Fold<I, P, OP, A, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    A: Clone,
+    F: Fn(A, OP) -> A,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<A> {
+        let mut acc = Some(self.init.clone());
+        repeat(&self.parser, self.range, input_ref, |out| {
+            acc = acc.take().map(|acc| (self.fold)(acc, out));
+        })?;
+        Ok(acc.expect("the accumulator is put back after each output"))
+    }
+}
+
+// Hands the outputs of a repetition over to `callback` as they are parsed, e.g the records of a
+// log too large to be collected.
+#[derive(Clone, Copy)]
+pub struct ForEach<I, P, OP, F> {
+    parser: P,
+    range: RepeatedRange,
+    callback: F,
+    phantom: PhantomData<(I, OP)>,
+}
+
+impl<'input, I, P, OP, F> Parser<'input, I, ()> for ForEach<I, P, OP, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    F: Fn(OP),
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        repeat(&self.parser, self.range, input_ref, &self.callback)
+    }
+}
+
+// Writes the outputs of a repetition to `writer` with `write` as they are parsed, e.g a huge
+// document normalized on the fly into a file (`io::Write`) or a string (`fmt::Write`), so that
+// the outputs are never held all at once. A failure to write makes the repetition fail right
+// away, with the error as the cause. Outputs written before a failure are left in the writer, and
+// so are those of a repetition that succeeded but is rolled back later on, e.g by an enclosing
+// `or` or `or_not` trying something else: writes can't be taken back. The writer is only borrowed
+// while an output is written, so that the parser repeated may write to it too, e.g nested lists
+// re-emitted in place.
+#[derive(Clone, Copy)]
+pub struct WriteInto<'a, I, P, OP, W, F> {
+    parser: P,
+    range: RepeatedRange,
+    writer: &'a RefCell<W>,
+    write: F,
+    phantom: PhantomData<(I, OP)>,
+}
+
+impl<'input, I, P, OP, W, F, E> Parser<'input, I, ()> for WriteInto<'_, I, P, OP, W, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    F: Fn(&mut W, OP) -> Result<(), E>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        try_repeat(&self.parser, self.range, input_ref, |out| {
+            (self.write)(&mut self.writer.borrow_mut(), out).map_err(ParseError::caused_by)
+        })
+    }
+}
+
+// Same as `Collect` but the outputs are handed over, with their spans, to `FromParsedIter`, which
+// pulls them one at a time and may reject them. If it stops short of the minimum number of items,
+// the rest of them are parsed all the same.
+#[derive(Clone, Copy)]
+pub struct CollectParsed<I, P, OP, C> {
+    parser: P,
+    range: RepeatedRange,
+    phantom: PhantomData<(I, OP, C)>,
+}
+
+impl<'input, I, P, OP, C> Parser<'input, I, C> for CollectParsed<I, P, OP, C>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    C: FromParsedIter<OP, I::Span>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<C> {
+        let start = input_ref.offset().into();
+        let mut items = ParsedItems {
+            parser: &self.parser,
+            range: self.range,
+            input_ref,
+            count: 0,
+            done: false,
+            last: start..start,
+            error: None,
+            phantom: PhantomData,
+        };
+        let result = C::from_parsed_iter(&mut items);
+        // The aggregate may stop taking items early, the repetition is still short of none.
+        while items.count < self.range.start() && items.next().is_some() {}
+
+        let (last, error) = (items.last, items.error);
+        if let Some(error) = error {
+            return Err(error);
+        }
+        result.map_err(|custom| {
+            input_ref.record_custom(last, custom);
+            ParseError::SyntaxError
+        })
+    }
+}
+
+// Outputs of a repetition parsed as they are pulled, see `CollectParsed`.
+struct ParsedItems<'a, 'input, 'parse, I, P, OP>
+where
+    I: Input<'input>,
+{
+    parser: &'a P,
+    range: RepeatedRange,
+    input_ref: &'a mut InputRef<'input, 'parse, I>,
+    count: usize,
+    done: bool,
+    // Span of the last output pulled.
+    last: Range<usize>,
+    // Failure that makes the whole repetition fail.
+    error: Option<ParseError>,
+    phantom: PhantomData<OP>,
+}
+
+impl<'input, I, P, OP> Iterator for ParsedItems<'_, 'input, '_, I, P, OP>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    type Item = (OP, I::Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.range.end().is_some_and(|end| self.count >= end) {
+            return None;
+        }
+
+        let checkpoint = self.input_ref.save();
+        let start = checkpoint.offset();
+        match self.parser.go(self.input_ref) {
+            Ok(out) => {
+                self.input_ref.commit(checkpoint);
+                let end = self.input_ref.offset();
+                self.count += 1;
+                self.last = start.into()..end.into();
+                Some((out, self.input_ref.span(start, end)))
+            }
+            Err(error) => {
+                self.done = true;
+                if self.count < self.range.start() || error.is_fatal() {
+                    self.error = Some(error);
+                } else if let Err(error) = self.input_ref.restore(checkpoint) {
+                    self.error = Some(error);
+                }
+                None
+            }
+        }
+    }
+}
+
+// Outputs the slice of input the repetition matched rather than collecting the outputs, e.g an
+// identifier as a `&str` without allocating a `String`.
+#[derive(Clone, Copy)]
+pub struct ToSlice<I, P, OP>(Repeated<I, P, OP>);
+
+impl<'input, I, P, OP> Parser<'input, I, I::Slice> for ToSlice<I, P, OP>
+where
+    I: SliceInput<'input>,
+    P: Parser<'input, I, OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let start = input_ref.offset();
+        skip_repeat(&Checking::new(&self.0.parser), self.0.range, input_ref)?;
+        Ok(input_ref.slice(start, input_ref.offset()))
+    }
+}
+
+// Keeps the last output of a repetition only, if any.
+#[derive(Clone, Copy)]
+pub struct Last<I, P, OP>(Repeated<I, P, OP>);
+
+impl<'input, I, P, OP> Parser<'input, I, Option<OP>> for Last<I, P, OP>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<Option<OP>> {
+        let mut last = None;
+        repeat(&self.0.parser, self.0.range, input_ref, |out| {
+            last = Some(out)
+        })?;
+        Ok(last)
+    }
+}
+
+// Keeps the greatest output of a repetition according to `compare`, if any. Of several equally
+// great outputs, the last one is kept.
+#[derive(Clone, Copy)]
+pub struct MaxBy<I, P, OP, F> {
+    parser: P,
+    range: RepeatedRange,
+    compare: F,
+    phantom: PhantomData<(I, OP)>,
+}
+
+impl<'input, I, P, OP, F> Parser<'input, I, Option<OP>> for MaxBy<I, P, OP, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    F: Fn(&OP, &OP) -> Ordering,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<Option<OP>> {
+        let mut max = None;
+        repeat(&self.parser, self.range, input_ref, |out| match &max {
+            Some(max) if (self.compare)(max, &out) == Ordering::Greater => {}
+            _ => max = Some(out),
+        })?;
+        Ok(max)
+    }
+}
+
+// Same as `Collect` but for key-value pairs, what happens to duplicate keys is up to `duplicates`.
+#[derive(Clone, Copy)]
+pub struct CollectMap<I, P, K, V, C> {
+    parser: P,
+    range: RepeatedRange,
+    duplicates: DuplicateKeys,
+    phantom: PhantomData<(I, K, V, C)>,
+}
+
+impl<'input, I, P, K, V, C> Parser<'input, I, C> for CollectMap<I, P, K, V, C>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, (K, V)>,
+    C: MapContainer<K, V>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<C> {
+        let mut ret = C::with_capacity_hint(self.range.capacity_hint());
+        try_repeat(&self.parser, self.range, input_ref, |(key, value)| {
+            match self.duplicates {
+                DuplicateKeys::KeepFirst if ret.contains_key(&key) => {}
+                DuplicateKeys::Fail if ret.contains_key(&key) => {
+                    return Err(ParseError::Custom(
+                        CustomError::new("duplicate key").with_code(ErrorCode::DuplicateKey),
+                    ));
+                }
+                _ => ret.try_push((key, value)).map_err(ParseError::Custom)?,
+            }
+            Ok(())
+        })?;
+        Ok(ret)
+    }
+}
+
+// Builder of a list of items separated by `separator`, see `Parser::separated_by`.
+#[derive(Clone, Copy)]
+pub struct SeparatedBy<I, P, OP, P2, OP2> {
+    parser: P,
+    separator: P2,
+    allow_trailing: bool,
+    phantom: PhantomData<(I, OP, OP2)>,
+}
+
+impl<I, P, OP, P2, OP2> SeparatedBy<I, P, OP, P2, OP2> {
+    // Accepts a separator after the last item, e.g `[1, 2, 3,]`.
+    pub fn allow_trailing(mut self) -> Self {
+        self.allow_trailing = true;
+        self
+    }
+
+    pub fn collect<C: Container<OP>>(self) -> CollectSeparated<I, P, OP, P2, OP2, C> {
+        CollectSeparated {
+            list: self,
+            phantom: PhantomData,
+        }
+    }
+
+    // Collects the separators along with the items, e.g for a formatter that must not drop the
+    // commas of a list.
+    pub fn collect_with_separators(self) -> CollectWithSeparators<I, P, OP, P2, OP2> {
+        CollectWithSeparators(self)
+    }
+}
+
+impl<'input, I, P, OP, P2, OP2> SeparatedBy<I, P, OP, P2, OP2>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn separated(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        mut push_item: impl FnMut(OP) -> Result<(), ParseError>,
+        mut push_separator: impl FnMut(OP2, I::Span),
+    ) -> ParseResult<()> {
+        // Items rejected by `push_item` make the list fail over their span.
+        let mut push = |input_ref: &mut InputRef<'input, '_, I>, start: Offset<I::Offset>, out| {
+            push_item(out).map_err(|error| {
+                let end = input_ref.offset();
+                input_ref.record_parse_error(start.into()..end.into(), error)
+            })
+        };
+
+        let checkpoint = input_ref.save();
+        let start = checkpoint.offset();
+        match self.parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                push(input_ref, start, out)?;
+            }
+            Err(error) if error.is_fatal() => return Err(error),
+            Err(_) => return input_ref.restore(checkpoint),
+        }
+
+        loop {
+            let checkpoint = input_ref.save();
+            let start = checkpoint.offset();
+            let separator = match self.separator.go(input_ref) {
+                Ok(separator) => separator,
+                Err(error) if error.is_fatal() => return Err(error),
+                Err(_) => return input_ref.restore(checkpoint),
+            };
+            let span = input_ref.span(start, input_ref.offset());
+
+            let item_checkpoint = input_ref.save();
+            let item_start = item_checkpoint.offset();
+            match self.parser.go(input_ref) {
+                Ok(out) => {
+                    input_ref.commit(item_checkpoint);
+                    input_ref.commit(checkpoint);
+                    push_separator(separator, span);
+                    push(input_ref, item_start, out)?;
+                }
+                Err(error) if error.is_fatal() => return Err(error),
+                Err(_) if self.allow_trailing => {
+                    input_ref.restore(item_checkpoint)?;
+                    input_ref.commit(checkpoint);
+                    push_separator(separator, span);
+                    return Ok(());
+                }
+                Err(_) => {
+                    input_ref.restore(item_checkpoint)?;
+                    return input_ref.restore(checkpoint);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CollectSeparated<I, P, OP, P2, OP2, C> {
+    list: SeparatedBy<I, P, OP, P2, OP2>,
+    phantom: PhantomData<C>,
+}
+
+impl<'input, I, P, OP, P2, OP2, C> Parser<'input, I, C> for CollectSeparated<I, P, OP, P2, OP2, C>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    P2: Parser<'input, I, OP2>,
+    C: Container<OP>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<C> {
+        let mut ret = C::default();
+        self.list.separated(
+            input_ref,
+            |out| ret.try_push(out).map_err(ParseError::Custom),
+            |_, _| {},
+        )?;
+        Ok(ret)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CollectWithSeparators<I, P, OP, P2, OP2>(SeparatedBy<I, P, OP, P2, OP2>);
+
+impl<'input, I, P, OP, P2, OP2> Parser<'input, I, Separated<OP, OP2, I::Span>>
+    for CollectWithSeparators<I, P, OP, P2, OP2>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+    ) -> ParseResult<Separated<OP, OP2, I::Span>> {
+        let mut items = Vec::new();
+        let mut separators = Vec::new();
+        self.0.separated(
+            input_ref,
+            |out| {
+                items.push(out);
+                Ok(())
+            },
+            |separator, span| separators.push((separator, span)),
+        )?;
+        Ok(Separated { items, separators })
+    }
+}
+
+// Items of a list along with the separators between them and their spans. There is one separator
+// less than items, or as many if the list has a trailing separator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Separated<O, S, Sp> {
+    items: Vec<O>,
+    separators: Vec<(S, Sp)>,
+}
+
+impl<O, S, Sp> Separated<O, S, Sp> {
+    #[inline]
+    pub fn items(&self) -> &[O] {
+        &self.items
+    }
+
+    #[inline]
+    pub fn separators(&self) -> &[(S, Sp)] {
+        &self.separators
+    }
+
+    #[inline]
+    pub fn has_trailing(&self) -> bool {
+        !self.items.is_empty() && self.items.len() == self.separators.len()
+    }
+
+    pub fn into_parts(self) -> (Vec<O>, Vec<(S, Sp)>) {
+        (self.items, self.separators)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Bind<I, P1, OP1, P2, OP2> {
+    // First parser to run. The result of this parser will be discarded.
+    first_parser: P1,
+    // Second parser to run. The result of this parser will be returned.
+    second_parser: P2,
+    phantom: PhantomData<(I, OP1, OP2)>,
+}
+
+// `and` operator allows you to run two parsers and return the output of both in a
+// container.
+#[derive(Clone, Copy)]
+pub struct And<I, P1, OP1, P2, OP2>(Bind<I, P1, OP1, P2, OP2>);
+
+// `left_bind` operator, similar to (<<) in haskell
+#[derive(Clone, Copy)]
+pub struct LeftBind<I, P1, OP1, P2, OP2>(Bind<I, P1, OP1, P2, OP2>);
+
+// `right_bind` operator, similar to (>>) in haskell
+#[derive(Clone, Copy)]
+pub struct RightBind<I, P1, OP1, P2, OP2>(Bind<I, P1, OP1, P2, OP2>);
+
+impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, (OP1, OP2)> for And<I, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<(OP1, OP2)> {
+        Ok((
+            self.0.first_parser.go(input_ref)?,
+            self.0.second_parser.go(input_ref)?,
+        ))
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.0.go_check(input_ref)
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.0.first_parser.rules_out(input_ref)
+    }
+}
+
+impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, OP1> for LeftBind<I, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP1> {
+        let ret = self.0.first_parser.go(input_ref)?;
+        self.0.second_parser.go(input_ref)?;
+        Ok(ret)
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.0.go_check(input_ref)
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.0.first_parser.rules_out(input_ref)
+    }
+}
+
+impl<'input, I, P1, OP1, P2, OP2> Parser<'input, I, OP2> for RightBind<I, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<OP2> {
+        self.0.first_parser.go(input_ref)?;
+        self.0.second_parser.go(input_ref)
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.0.go_check(input_ref)
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.0.first_parser.rules_out(input_ref)
+    }
+}
+
+impl<'input, I, P1, OP1, P2, OP2> Bind<I, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.first_parser.go_check(input_ref)?;
+        self.second_parser.go_check(input_ref)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct DelimitedBy<I, P, O, P1, OP1, P2, OP2> {
+    parser: P,
+    open: P1,
+    close: P2,
+    phantom: PhantomData<(I, O, OP1, OP2)>,
+}
+
+impl<'input, I, P, O, P1, OP1, P2, OP2> Parser<'input, I, O>
+    for DelimitedBy<I, P, O, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, OP2>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let (start, generation) = (input_ref.offset(), input_ref.error_generation());
+        self.open.go(input_ref)?;
+        let open_span = start.into()..input_ref.offset().into();
+
+        let out = self.parser.go(input_ref)?;
+        self.close
+            .go(input_ref)
+            .inspect_err(|_| input_ref.label_error(generation, open_span, "unclosed delimiter"))?;
+        Ok(out)
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        let (start, generation) = (input_ref.offset(), input_ref.error_generation());
+        self.open.go_check(input_ref)?;
+        let open_span = start.into()..input_ref.offset().into();
+
+        self.parser.go_check(input_ref)?;
+        self.close
+            .go_check(input_ref)
+            .inspect_err(|_| input_ref.label_error(generation, open_span, "unclosed delimiter"))
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.open.rules_out(input_ref)
+    }
+}
+
+// `map` operator, works the same way as the map function on iterators (Functors
+// generally).
+#[derive(Clone, Copy)]
+pub struct Map<I, P, OP, F, U> {
+    // function mapping the output of the parser to the output desired.
+    mapper: F,
+    // parser we are mapping
+    parser: P,
+    phantom: PhantomData<(I, U, OP)>,
+}
+
+impl<'input, I, P, OP, F, U> Parser<'input, I, U> for Map<I, P, OP, F, U>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    F: Fn(OP) -> U,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<U> {
+        let out = self.parser.go(input_ref)?;
+        Ok((self.mapper)(out))
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.parser.go_check(input_ref)
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.parser.rules_out(input_ref)
+    }
+}
+
+// `try_map` operator, see `Parser::try_map`.
+#[derive(Clone, Copy)]
+pub struct TryMap<I, P, OP, F, U> {
+    mapper: F,
+    parser: P,
+    phantom: PhantomData<(I, U, OP)>,
+}
+
+impl<'input, I, P, OP, F, U> Parser<'input, I, U> for TryMap<I, P, OP, F, U>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    F: Fn(OP) -> Result<U, ParseError>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<U> {
+        let checkpoint = input_ref.save();
+        let start = checkpoint.offset();
+        let out = self.parser.go(input_ref)?;
+        match (self.mapper)(out) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            }
+            Err(error) => {
+                let end = input_ref.offset();
+                input_ref.restore(checkpoint)?;
+                Err(input_ref.record_parse_error(start.into()..end.into(), error))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MapErrWithSpan<I, P, O, F> {
+    mapper: F,
+    parser: P,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O, F> Parser<'input, I, O> for MapErrWithSpan<I, P, O, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    F: Fn(ParseError, I::Span) -> ParseError,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        let (checkpoint, generation) = (input_ref.save(), input_ref.error_generation());
+        let start = checkpoint.offset();
+        match self.parser.go(input_ref) {
+            Ok(out) => {
+                input_ref.commit(checkpoint);
+                Ok(out)
+            }
+            Err(error) => {
+                // The parser may have looked further than where it stopped, the report covers
+                // what it looked at. An error recorded before it ran isn't its own.
+                let mut end = input_ref.offset();
+                if input_ref.error_generation() > generation {
+                    if let Some(at) = input_ref.error_offset() {
+                        end = input_ref.seek(end, at);
+                    }
+                }
+                let error = (self.mapper)(error, input_ref.span(start, end));
+                input_ref.restore(checkpoint)?;
+                Err(input_ref.record_parse_error(start.into()..end.into(), error))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct OnErr<I, P, O, F> {
+    callback: F,
+    parser: P,
+    phantom: PhantomData<(I, O)>,
+}
+
+impl<'input, I, P, O, F> Parser<'input, I, O> for OnErr<I, P, O, F>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, O>,
+    F: for<'parse> Fn(&ParseError, &InputRef<'input, 'parse, I>),
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<O> {
+        self.parser
+            .go(input_ref)
+            .inspect_err(|error| (self.callback)(error, input_ref))
+    }
+}
+
+// `validate` operator, see `Parser::validate`.
+#[derive(Clone, Copy)]
+pub struct Validate<I, P, OP, F, U> {
+    validator: F,
+    parser: P,
+    phantom: PhantomData<(I, U, OP)>,
+}
+
+impl<'input, I, P, OP, F, U> Parser<'input, I, U> for Validate<I, P, OP, F, U>
+where
+    I: Input<'input>,
+    P: Parser<'input, I, OP>,
+    F: Fn(OP, I::Span, &mut Emitter<'_>) -> U,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<U> {
+        let start = input_ref.offset();
+        let out = self.parser.go(input_ref)?;
+        let span = input_ref.span(start, input_ref.offset());
+        Ok((self.validator)(out, span, &mut input_ref.emitter()))
+    }
+}
+
+// `Exact` combinator matches an exact sequence of tokens.
+// Returns an error if there is a mismatch.
+#[derive(Clone, Copy)]
+pub struct Exact<I, T> {
+    seq: T,
+    phantom: PhantomData<I>,
+}
+
+pub const fn exact<'input, I, T>(seq: T) -> Exact<I, T>
+where
+    I: Input<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    Exact {
+        seq,
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I, T> Exact<I, T>
+where
+    I: Input<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    fn matches(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        if input_ref.match_sequence(&self.seq) {
+            return Ok(());
+        }
+
+        // Inputs storing their tokens in a slice tell at once how much of the sequence matched.
+        if let Some(tokens) = self.seq.as_tokens() {
+            if let Some((end, matched)) = input_ref.match_prefix(tokens) {
+                input_ref.advance(end, matched);
+                input_ref.record_expected(Expected::Token(tokens[matched]));
+                return Err(input_ref.mismatch(Some(tokens.len() - matched)));
+            }
+        }
+
+        // Walks the sequence to find out where the input differs from it.
+        let mut tokens = self.seq.iterator();
+        if let Some(token) = tokens.find(|&seq_token| {
+            if Some(seq_token) == input_ref.peek_token() {
+                input_ref.next_token();
+                false
+            } else {
+                true
+            }
+        }) {
+            input_ref.record_expected(Expected::Token(token));
+            Err(input_ref.mismatch(Some(1 + tokens.count())))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        match (self.seq.iterator().next(), input_ref.peek_token()) {
+            (Some(first), Some(token)) if first != token => {
+                input_ref.record_expected(Expected::Token(first));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'input, I, T> Parser<'input, I, I::Slice> for Exact<I, T>
+where
+    I: SliceInput<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let start = input_ref.offset();
+        self.matches(input_ref)?;
+        Ok(input_ref.slice(start, input_ref.offset()))
+    }
+
+    #[inline]
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        Exact::rules_out(self, input_ref)
+    }
+}
+
+// `Tag` primitive, works like `exact` but outputs nothing, so that it works on inputs that can't
+// slice (see `SliceInput`).
+#[derive(Clone, Copy)]
+pub struct Tag<I, T>(Exact<I, T>);
+
+pub const fn tag<'input, I, T>(seq: T) -> Tag<I, T>
+where
+    I: Input<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    Tag(exact(seq))
+}
+
+impl<'input, I, T> Parser<'input, I, ()> for Tag<I, T>
+where
+    I: Input<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.0.matches(input_ref)
+    }
+
+    #[inline]
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.0.rules_out(input_ref)
+    }
+}
+
+// `End` combinator matches the EOI (end of input).
+// Returns an error if the input is not yet fully consumed.
+#[derive(Clone, Copy)]
+pub struct End<I> {
+    phantom: PhantomData<I>,
+}
+
+pub const fn end<'input, I>() -> End<I>
+where
+    I: Input<'input>,
+{
+    End {
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I> Parser<'input, I, ()> for End<I>
+where
+    I: Input<'input>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        if input_ref.peek_token().is_some() {
+            input_ref.record_trailing_input();
+            Err(ParseError::SyntaxError)
+        } else if let Some(error) = input_ref.encoding_error() {
+            input_ref.record_encoding_error(error);
+            Err(ParseError::SyntaxError)
+        } else if input_ref.is_partial() {
+            Err(ParseError::Incomplete { needed: None })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// `Any` combinator matches any token except the EOI (end of input).
+// Returns an error if the input was totally consumed (i.e empty).
+#[derive(Clone, Copy)]
+pub struct Any<I> {
+    phantom: PhantomData<I>,
+}
+
+pub const fn any<'input, I>() -> Any<I>
+where
+    I: Input<'input>,
+{
+    Any {
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I> Parser<'input, I, I::Token> for Any<I>
+where
+    I: Input<'input>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Token> {
+        match input_ref.peek() {
+            (next_offset, Some(token)) => {
+                input_ref.advance(next_offset.get(), 1);
+                Ok(token)
+            }
+            (_, None) => {
+                input_ref.record_expected(Expected::Any);
+                Err(input_ref.mismatch(Some(1)))
+            }
+        }
+    }
+
+    fn go_scan(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        at_most: usize,
+        predicate: &dyn Fn(&I::Token) -> bool,
+    ) -> usize {
+        let (mut offset, mut count) = (input_ref.raw_offset(), 0);
+        while count < at_most {
+            match input_ref.peek_at(offset) {
+                (next_offset, Some(token)) if predicate(&token) => offset = next_offset,
+                _ => break,
+            }
+            count += 1;
+        }
+        input_ref.advance(offset, count);
+        count
+    }
+}
+
+// `TokenRange` primitive, matches a token within a range, e.g `range('a'..='z')`.
+#[derive(Clone, Copy)]
+pub struct TokenRange<I, T> {
+    start: T,
+    end: T,
+    phantom: PhantomData<I>,
+}
+
+pub const fn range<'input, I>(tokens: RangeInclusive<I::Token>) -> TokenRange<I, I::Token>
+where
+    I: Input<'input>,
+    I::Token: PartialOrd,
+{
+    TokenRange {
+        start: *tokens.start(),
+        end: *tokens.end(),
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I> TokenRange<I, I::Token>
+where
+    I: Input<'input>,
+    I::Token: PartialOrd,
+{
+    #[inline]
+    fn contains(&self, token: &I::Token) -> bool {
+        self.start <= *token && *token <= self.end
+    }
+}
+
+impl<'input, I> Parser<'input, I, I::Token> for TokenRange<I, I::Token>
+where
+    I: Input<'input>,
+    I::Token: PartialOrd,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Token> {
+        match input_ref.peek() {
+            (next_offset, Some(token)) if self.contains(&token) => {
+                input_ref.advance(next_offset.get(), 1);
+                Ok(token)
+            }
+            _ => {
+                input_ref.record_expected(Expected::Range(self.start, self.end));
+                Err(input_ref.mismatch(Some(1)))
+            }
+        }
+    }
+
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        match input_ref.peek_token() {
+            Some(token) if !self.contains(&token) => {
+                input_ref.record_expected(Expected::Range(self.start, self.end));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn go_scan(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        at_most: usize,
+        predicate: &dyn Fn(&I::Token) -> bool,
+    ) -> usize {
+        any().go_scan(input_ref, at_most, &|token| {
+            self.contains(token) && predicate(token)
+        })
+    }
+}
+
+// `OneOf` primitive, matches one of the sequence passed in as a parameter. The first one in the
+// list that matches is picked. The sequences are arranged in a trie when built, so that a single
+// pass over the input finds the match however many there are.
+#[derive(Clone)]
+pub struct OneOf<I, S: OrderedSequence> {
+    trie: Trie<S::Token>,
+    phantom: PhantomData<(I, S)>,
+}
+
+pub fn one_of<'input, I, S>(container: Vec<S>) -> OneOf<I, S>
+where
+    I: Input<'input>,
+    S: OrderedSequence<Token = I::Token>,
+{
+    OneOf {
+        trie: Trie::new(&container),
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I, S> OneOf<I, S>
+where
+    I: Input<'input>,
+    S: OrderedSequence<Token = I::Token>,
+{
+    // Index of the sequence that matched.
+    #[inline]
+    fn matches(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<usize> {
+        self.trie.matches(input_ref)
+    }
+}
+
+impl<'input, I, S> Parser<'input, I, I::Slice> for OneOf<I, S>
+where
+    I: SliceInput<'input>,
+    S: OrderedSequence<Token = I::Token>,
+    I::Token: std::fmt::Display + std::fmt::Debug,
+    I::Slice: std::fmt::Display,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let start_offset = input_ref.offset();
+        self.matches(input_ref)?;
+        Ok(input_ref.slice(start_offset, input_ref.offset()))
+    }
+}
+
+// `OneOfIndex` primitive, works like `one_of` but outputs the index of the sequence that matched,
+// so that it works on inputs that can't slice (see `SliceInput`).
+pub struct OneOfIndex<I, S: OrderedSequence>(OneOf<I, S>);
+
+impl<I, S> Clone for OneOfIndex<I, S>
+where
+    S: OrderedSequence,
+    OneOf<I, S>: Clone,
+{
+    fn clone(&self) -> Self {
+        OneOfIndex(self.0.clone())
+    }
+}
+
+pub fn one_of_index<'input, I, S>(container: Vec<S>) -> OneOfIndex<I, S>
+where
+    I: Input<'input>,
+    S: OrderedSequence<Token = I::Token>,
+{
+    OneOfIndex(one_of(container))
+}
+
+impl<'input, I, S> Parser<'input, I, usize> for OneOfIndex<I, S>
+where
+    I: Input<'input>,
+    S: OrderedSequence<Token = I::Token>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<usize> {
+        self.0.matches(input_ref)
+    }
+}
+
+// `Escaped` primitive, parses text made of runs of `normal` tokens interleaved with `escape`
+// sequences, each standing for the character it outputs, e.g the content of a JSON string. The
+// text is borrowed from the input unless it contains escape sequences. `normal` must consume
+// input when it succeeds.
+#[derive(Clone, Copy)]
+pub struct Escaped<I, P1, OP1, P2> {
+    normal: P1,
+    escape: P2,
+    phantom: PhantomData<(I, OP1)>,
+}
+
+pub fn escaped<'input, I, P1, OP1, P2>(normal: P1, escape: P2) -> Escaped<I, P1, OP1, P2>
+where
+    I: SliceInput<'input, Slice = &'input str>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, char>,
+{
+    Escaped {
+        normal,
+        escape,
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I, P1, OP1, P2> Parser<'input, I, Cow<'input, str>> for Escaped<I, P1, OP1, P2>
+where
+    I: SliceInput<'input, Slice = &'input str>,
+    P1: Parser<'input, I, OP1>,
+    P2: Parser<'input, I, char>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<Cow<'input, str>> {
+        let mut text = Cow::Borrowed("");
+        // Start of the run of normal tokens being parsed.
+        let mut run = input_ref.offset();
+        loop {
+            let checkpoint = input_ref.save();
+            match self.normal.go(input_ref) {
+                Ok(_) => {
+                    input_ref.commit(checkpoint);
+                    continue;
+                }
+                Err(error) if error.is_fatal() => return Err(error),
+                Err(_) => input_ref.restore(checkpoint)?,
+            }
+
+            let escape_start = input_ref.offset();
+            let checkpoint = input_ref.save();
+            match self.escape.go(input_ref) {
+                Ok(c) => {
+                    input_ref.commit(checkpoint);
+                    text.push(input_ref.slice(run, escape_start));
+                    text.push(c);
+                    run = input_ref.offset();
+                }
+                Err(error) if error.is_fatal() => return Err(error),
+                Err(_) => {
+                    input_ref.restore(checkpoint)?;
+                    break;
+                }
+            }
+        }
+        text.push(input_ref.slice(run, input_ref.offset()));
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! input_ref {
+        ($e: expr) => {
+            InputRef::new(&$e)
+        };
+    }
+
+    // Sanity check `exact` combinator
+    // Success case
+    #[test]
+    fn test_exact_simple_string_ok() {
+        let mut input_ref = input_ref!("hello world");
+
+        let parser = exact("hello");
+
+        assert_eq!(parser.go(&mut input_ref), Ok("hello"));
+        assert_eq!(input_ref.offset(), "hello".len());
+
+        let parser = exact(" world");
+        assert_eq!(parser.go(&mut input_ref), Ok(" world"));
+        assert_eq!(input_ref.offset(), "hello world".len());
+    }
+
+    // Sanity check for `exact` combinator
+    // error case
+    #[test]
+    fn test_exact_simple_string_err() {
+        let mut input_ref = input_ref!(b"hello world" as &[u8]);
+
+        let parser = exact(b"hellqasd" as &[u8]);
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), "hell".len());
+    }
+
+    // Sanity check for `end` combinator
+    // Success case.
+    #[test]
+    fn test_end_ok() {
+        let mut input_ref = input_ref!("");
+        let parser = end();
+
+        assert_eq!(parser.go(&mut input_ref), Ok(()));
+        assert_eq!(input_ref.offset(), 0);
+    }
+
+    // Sanity check for `end` combinator
+    // Error case.
+    #[test]
+    fn test_end_err() {
+        let mut input_ref = input_ref!("characters left in the input");
+        let parser = end();
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), 0);
+    }
+
+    // Sanity check for `any` combinator
+    // Success case
+    #[test]
+    fn test_any_ok() {
+        let mut input_ref = input_ref!("any");
+        let parser = any();
+
+        assert_eq!(parser.go(&mut input_ref), Ok('a'));
+        assert_eq!(input_ref.offset(), "a".len());
+
+        assert_eq!(parser.go(&mut input_ref), Ok('n'));
+        assert_eq!(input_ref.offset(), "ab".len());
+
+        assert_eq!(parser.go(&mut input_ref), Ok('y'));
+        assert_eq!(input_ref.offset(), "any".len());
+    }
+
+    // Sanity check for `any` combinator
+    // Success case
+    #[test]
+    fn test_any_err() {
+        let mut input_ref = input_ref!("");
+        let parser = any();
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), 0);
+    }
+
+    // Sanity check for `map` operator
+    #[test]
+    fn test_map() {
+        let mut input_ref = input_ref!("123");
+        let parser = exact("123").map(|string: &str| string.parse::<u32>().ok());
+
+        assert_eq!(parser.go(&mut input_ref), Ok(Some(123)));
+        assert_eq!(input_ref.offset(), "123".len());
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+    }
+
+    // Sanity check for `bind` operator
+    #[test]
+    fn test_bind() {
+        let mut input_ref = input_ref!("123456");
+        let parser = exact("123").right_bind(exact("456")).left_bind(end());
+
+        assert_eq!(parser.go(&mut input_ref), Ok("456"));
+        assert_eq!(input_ref.offset(), "123456".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("123")
+            .right_bind(exact("456"))
+            .map(|string: &str| string.parse::<u32>().ok());
+
+        assert_eq!(parser.go(&mut input_ref), Ok(Some(456)));
+        assert_eq!(input_ref.offset(), "123456".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("123")
+            .left_bind(exact("456"))
+            .map(|string: &str| string.parse::<u32>().ok());
+
+        assert_eq!(parser.go(&mut input_ref), Ok(Some(123)));
+        assert_eq!(input_ref.offset(), "123456".len());
+    }
+
+    #[test]
+    fn test_bind_with_separated_value() {
+        let mut input_ref = input_ref!("123-456");
+        let parser = exact("123")
+            .left_bind(exact("-"))
+            .right_bind(exact("456"))
+            .map(|string: &str| string.parse::<u32>().ok());
+
+        assert_eq!(parser.go(&mut input_ref), Ok(Some(456)));
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("123")
+            .left_bind(exact("-"))
+            .left_bind(exact("456"))
+            .map(|string: &str| string.parse::<u32>().ok());
+
+        assert_eq!(parser.go(&mut input_ref), Ok(Some(123)));
+        assert_eq!(input_ref.offset(), "123-456".len());
+    }
+
+    // Sanity check for `And` operator
+    #[test]
+    fn test_and() {
+        let mut input_ref = input_ref!("https://");
+        let https = exact("https");
+        let slashes = exact("//");
+
+        let parser = https.left_bind(exact(":")).and(slashes);
+
+        assert_eq!(parser.go(&mut input_ref), Ok(("https", "//")));
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("https")
+            .left_bind(exact("er"))
+            .right_bind(exact("//"));
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), "https".len());
+    }
+
+    // Sanity check for `Repeated` operator
+    #[test]
+    fn test_repeated() {
+        let mut input_ref = input_ref!("hhhhhhoooooo");
+
+        let parser = exact('h')
+            .repeated()
+            .at_least(3)
+            .at_most(4)
+            .collect::<Vec<_>>();
+
+        assert_eq!(parser.go(&mut input_ref), Ok(vec!["h"; 4]));
+        assert_eq!(input_ref.offset(), 4);
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact('h').repeated().at_least(1).collect::<Vec<_>>();
+        assert_eq!(parser.go(&mut input_ref), Ok(vec!["h"; 6]));
+        assert_eq!(input_ref.offset(), 6);
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact('h')
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .and(exact('o').repeated().at_least(1).collect::<Vec<_>>())
+            .left_bind(end());
+
+        assert_eq!(parser.go(&mut input_ref), Ok((vec!["h"; 6], vec!["o"; 6])));
+    }
+
+    #[test]
+    fn test_repeated_err() {
+        let mut input_ref = input_ref!("hhhhhooooo");
+        let parser = exact('h')
+            .repeated()
+            .at_least(100)
+            .at_most(400)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            parser.go(&mut input_ref),
+            Err::<Vec<_>, _>(ParseError::SyntaxError)
+        );
+    }
+
+    #[test]
+    fn test_filter_ok() {
+        let mut input_ref = input_ref!("132letters");
+
+        let digits = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>();
+
+        let letters = any()
+            .filter(|c: &char| c.is_ascii_alphabetic())
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>();
+
+        assert_eq!(digits.go(&mut input_ref), Ok(vec!['1', '3', '2']));
+        assert_eq!(input_ref.offset(), "132".len());
+        assert_eq!(
+            letters.go(&mut input_ref),
+            Ok(vec!['l', 'e', 't', 't', 'e', 'r', 's'])
+        );
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = digits.left_bind::<_, Vec<_>>(letters).left_bind(end());
+        assert_eq!(parser.go(&mut input_ref), Ok(vec!['1', '3', '2']));
+    }
+
+    #[test]
+    fn test_floating_point_number() {
+        let mut input_ref = input_ref!("-123.234");
+
+        let digit_seq = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>();
+
+        let parser = exact('-')
+            .right_bind(digit_seq.clone())
+            .left_bind(exact('.'))
+            .and(digit_seq);
+
+        assert_eq!(
+            parser.go(&mut input_ref),
+            Ok((vec!['1', '2', '3'], vec!['2', '3', '4']))
+        );
+        assert_eq!(input_ref.offset(), "-123.234".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let digit_seq = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .exactly(3)
+            .collect::<String>();
+
+        let parser = exact('-')
+            .right_bind(digit_seq.clone())
+            .left_bind(exact('.'))
+            .and(digit_seq);
+
+        assert_eq!(
+            parser.go(&mut input_ref),
+            Ok((String::from("123"), String::from("234")))
+        );
+        assert_eq!(input_ref.offset(), "-123.234".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let digit_seq = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(2)
+            .at_most(3)
+            .collect::<String>();
+
+        let parser = exact('-')
+            .right_bind(digit_seq.clone())
+            .left_bind(exact('.'))
+            .and(digit_seq);
+
+        assert_eq!(
+            parser.go(&mut input_ref),
+            Ok((String::from("123"), String::from("234")))
+        );
+        assert_eq!(input_ref.offset(), "-123.234".len());
+    }
+
+    #[test]
+    fn identifier() {
+        use std::collections::HashSet;
+
+        let mut input_ref = input_ref!("ident_ifier");
+        let parser = any()
+            .filter(|c: &char| c.is_ascii())
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+
+        assert_eq!(parser.go(&mut input_ref), Ok(String::from("ident_ifier")));
+        assert_eq!(input_ref.offset(), "ident_ifier".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = any()
+            .filter(|c: &char| c.is_ascii())
+            .repeated()
+            .at_least(1)
+            .collect::<HashSet<_>>();
+
+        assert_eq!(
+            parser.go(&mut input_ref),
+            Ok(HashSet::from([
+                'i', 'd', 'e', 'n', 't', '_', 'i', 'f', 'i', 'e', 'r'
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_one_of() {
+        let mut input_ref = input_ref!("12345");
+
+        let parser = one_of(vec!['1', '2', '3']);
+
+        assert_eq!(parser.go(&mut input_ref), Ok("1"));
+        assert_eq!(input_ref.offset(), 1);
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = one_of(vec!["124", "1235", "122", "12345"]).left_bind(end());
+
+        assert_eq!(parser.go(&mut input_ref), Ok("12345"));
+        assert_eq!(input_ref.offset(), "12345".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = one_of(vec!["124", "1235", "122"]);
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), input_ref.start());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = one_of(vec!["124", "1235", "122"]);
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), input_ref.start());
+    }
+
+    #[test]
+    fn test_padded_by() {
+        let mut input_ref = input_ref!(r#" { "key1": "value1", "key2": "value2", } "#);
+        assert_eq!(input_ref.offset(), 0);
+
+        let white_space = any()
+            .filter(|c: &char| c == &' ')
+            .repeated()
+            .at_least(0)
+            .collect::<String>();
+
+        let left_brace = exact('{').padded(white_space.clone());
+        let right_brace = exact('}').padded(white_space.clone());
+        let column = exact(':').padded(white_space.clone());
+        let comma = exact(',').padded(white_space.clone());
+
+        let string = exact('"')
+            .right_bind(
+                any()
+                    .filter(|c: &char| c.is_ascii_alphanumeric())
+                    .repeated()
+                    .at_least(1)
+                    .collect::<String>(),
+            )
+            .left_bind(exact('"'));
+
+        let kvp = string.clone().left_bind(column).and(string);
+
+        let json_file = left_brace
+            .clone()
+            .right_bind(kvp.clone())
+            .left_bind(comma.clone())
+            .and(kvp.clone())
+            .left_bind(comma)
+            .left_bind(right_brace);
+
+        let kvp1 = (String::from("key1"), String::from("value1"));
+        let kvp2 = (String::from("key2"), String::from("value2"));
+
+        assert_eq!(json_file.go(&mut input_ref), Ok((kvp1, kvp2)));
+    }
+
+    #[test]
+    fn test_string() {
+        let mut input_ref = input_ref!(r#"    "       string"   "#);
+        let white_space = any()
+            .filter(|c: &char| c == &' ')
+            .repeated()
+            .at_least(0)
+            .collect::<String>();
+
+        let string = exact('"').padded(white_space).right_bind(
+            any()
+                .filter(|c: &char| c.is_ascii_alphabetic())
+                .repeated()
+                .at_least(1)
+                .collect::<String>(),
+        );
+
+        assert_eq!(string.go(&mut input_ref), Ok(String::from("string")));
+    }
+
+    #[test]
+    fn test_or() {
+        let mut input_ref = input_ref!("http://localhost");
+
+        let parser = exact("https").or(exact("http"));
+        assert_eq!(parser.go(&mut input_ref), Ok("http"));
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("http::").or(exact("httppp")).or(exact("htttt"));
+
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+    }
+
+    #[test]
+    fn test_long_string() {
+        const SIZE: usize = 1_000_000;
+        let long_string = vec!['c'; SIZE]
+            .into_iter()
+            .chain(vec!['d'; SIZE])
+            .collect::<String>();
+
+        let long_string_as_str = long_string.as_str();
+        let mut input_ref = input_ref!(long_string_as_str);
+
+        let bounded_parser = |at_least, at_most| {
+            any()
+                .filter(|c: &char| c == &'c')
+                .repeated()
+                .at_least(at_least)
+                .at_most(at_most)
+                .collect::<String>()
+                .and(
+                    any()
+                        .filter(|c: &char| c == &'d')
+                        .repeated()
+                        .at_least(at_least)
+                        .at_most(at_most)
+                        .collect::<String>(),
+                )
+        };
+
+        let string = |c, size| vec![c; size].into_iter().collect::<String>();
+
+        let parser = bounded_parser(1, SIZE);
+        assert_eq!(
+            parser.go(&mut input_ref),
+            Ok((string('c', SIZE), string('d', SIZE)))
+        );
+
+        assert_eq!(input_ref.offset(), SIZE << 1);
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = bounded_parser(SIZE + 1, SIZE + 2);
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), SIZE);
+    }
+
+    #[test]
+    fn test_error_report_display() {
+        let source = "key = value\nport = 80a\n";
+        let digits = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+        let line = exact("key = value\n")
+            .right_bind(exact("port = "))
+            .right_bind(digits)
+            .left_bind(exact('\n'));
+
+        let report = line.parse(source).unwrap_err();
+        assert_eq!(report.span(), 21..22);
+        assert_eq!(report.found(), Some(&'a'));
+        assert_eq!(report.expected(), &[Expected::Token('\n')]);
+        assert_eq!(
+            report.display(source).to_string(),
+            concat!(
+                "error[E0001]: found 'a', expected '\\n'\n",
+                " --> 2:10\n",
+                "  |\n",
+                "2 | port = 80a\n",
+                "  |          ^",
+            )
+        );
+    }
+
+    #[cfg(feature = "ariadne")]
+    #[test]
+    fn test_error_report_to_ariadne() {
+        let source = "hellq";
+        let report = exact("hello").parse(source).unwrap_err();
+
+        let mut rendered = Vec::new();
+        report
+            .to_ariadne()
+            .write(ariadne::Source::from(source), &mut rendered)
+            .unwrap();
+        let rendered = String::from_utf8(rendered).unwrap();
+
+        assert!(rendered.contains("found 'q'"));
+        assert!(rendered.contains("expected 'o'"));
+    }
+
+    #[cfg(feature = "codespan-reporting")]
+    #[test]
+    fn test_error_report_to_diagnostic() {
+        let report = exact("hello").parse("hellq").unwrap_err();
+        let diagnostic = report.to_diagnostic(());
+
+        assert_eq!(diagnostic.message, "found 'q'");
+        assert_eq!(diagnostic.labels[0].range, 4..5);
+        assert_eq!(diagnostic.labels[0].message, "expected 'o'");
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn test_error_report_miette_diagnostic() {
+        use miette::Diagnostic;
+
+        let report = exact("hello").parse("hellq").unwrap_err();
+        let label = report.labels().unwrap().next().unwrap();
+
+        assert_eq!(label.label(), Some("found 'q'"));
+        assert_eq!(label.offset(), 4);
+        assert_eq!(label.len(), 1);
+        assert_eq!(report.help().unwrap().to_string(), "expected 'o'");
+    }
+
+    #[test]
+    fn test_validate_emits_warnings() {
+        let number = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .validate(|digits, span, emitter| {
+                if digits.len() > 1 && digits.starts_with('0') {
+                    emitter.warning(span, "leading zeros are ignored");
+                }
+                digits.parse::<u32>().unwrap()
+            });
+        let parser = number.clone().left_bind(exact(',')).and(number.clone());
+
+        let (result, diagnostics) = parser.parse_with_diagnostics("007,12");
+        assert_eq!(result, Ok((7, 12)));
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                Severity::Warning,
+                0..3,
+                "leading zeros are ignored"
+            )]
+        );
+
+        // Warnings emitted by an abandoned branch are discarded.
+        let parser = number
+            .clone()
+            .left_bind(exact(';'))
+            .or(exact("00").right_bind(number));
+
+        let (result, diagnostics) = parser.parse_with_diagnostics("007");
+        assert_eq!(result, Ok(7));
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_cut() {
+        let mut input_ref = input_ref!("let 1");
+        let identifier = any()
+            .filter(|c: &char| c.is_ascii_alphabetic())
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+
+        let parser = exact("let ")
+            .right_bind(identifier.clone())
+            .or(exact("let").map(String::from));
+        assert_eq!(parser.go(&mut input_ref), Ok(String::from("let")));
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("let ")
+            .right_bind(identifier.clone().cut())
+            .or(exact("let").map(String::from));
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::Fatal(None)));
+        assert_eq!(input_ref.offset(), "let ".len());
+
+        input_ref.rewind(input_ref.start()).unwrap();
+
+        let parser = exact("let ").right_bind(identifier.cut()).or_not();
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::Fatal(None)));
+
+        // Custom failures keep their payload.
+        struct Reserved;
+
+        impl<'input> Parser<'input, &'input str, ()> for Reserved {
+            fn go(&self, _: &mut InputRef<'input, '_, &'input str>) -> ParseResult<()> {
+                Err(ParseError::custom("reserved word"))
+            }
+        }
+
+        let parser = exact("let ")
+            .right_bind(Reserved.cut())
+            .or(exact("let").map(|_| ()));
+        let report = parser.parse("let x").unwrap_err();
+        assert_eq!(report.message(), Some("reserved word"));
+        assert_eq!(report.span(), 4..5);
+    }
+
+    #[test]
+    fn test_or_not() {
+        let mut input_ref = input_ref!("-12");
+        let parser = exact('-').or_not().and(exact("12"));
+
+        assert_eq!(parser.go(&mut input_ref), Ok((Some("-"), "12")));
+
+        let mut input_ref = input_ref!("12");
+        assert_eq!(parser.go(&mut input_ref), Ok((None, "12")));
+    }
+
+    #[test]
+    fn test_repeated_fatal() {
+        let mut input_ref = input_ref!("[1][2][x]");
+        let item = exact('[')
+            .right_bind(any().filter(|c: &char| c.is_ascii_digit()).cut())
+            .left_bind(exact(']'));
+
+        let parser = item.repeated().at_least(0).collect::<Vec<_>>();
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::Fatal(None)));
+        assert_eq!(input_ref.offset(), "[1][2][".len());
+    }
+
+    #[test]
+    fn test_try_map_keeps_cause() {
+        use std::error::Error;
+
+        let byte = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .try_map(|digits| digits.parse::<u8>().map_err(ParseError::caused_by));
+        let parser = exact("ttl=").right_bind(byte).left_bind(end());
+
+        assert_eq!(parser.parse("ttl=64"), Ok(64));
+
+        let report = parser.parse("ttl=640").unwrap_err();
+        assert_eq!(report.span(), 4..7);
+        assert_eq!(
+            report.message(),
+            Some("number too large to fit in target type")
+        );
+        assert!(report
+            .source()
+            .unwrap()
+            .downcast_ref::<std::num::ParseIntError>()
+            .is_some());
+        assert_eq!(report.to_string(), "number too large to fit in target type");
+
+        let mut input_ref = input_ref!("ttl=");
+        let parser = exact("ttl=").try_map(|_| Err::<(), _>(ParseError::custom("no ttl")));
+        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(input_ref.offset(), input_ref.start());
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+
+        assert_eq!(
+            exact("abc").parse("abd").unwrap_err().code(),
+            ErrorCode::UnexpectedToken
+        );
+        assert_eq!(
+            exact("abc").parse("ab").unwrap_err().code(),
+            ErrorCode::UnexpectedEndOfInput
+        );
+        assert_eq!(
+            exact("ab")
+                .left_bind(end())
+                .parse("abc")
+                .unwrap_err()
+                .code(),
+            ErrorCode::TrailingInput
+        );
+        assert_eq!(
+            digit.parse("a").unwrap_err().code(),
+            ErrorCode::RejectedToken
+        );
+
+        let parser = digit.try_map(|_| {
+            Err::<(), _>(ParseError::Custom(
+                CustomError::new("reserved").with_code(ErrorCode::User(42)),
+            ))
+        });
+        let report = parser.parse("7").unwrap_err();
+        assert_eq!(report.code(), ErrorCode::User(42));
+        assert_eq!(report.code().to_string(), "U0042");
+        assert_eq!(ErrorCode::TrailingInput.to_string(), "E0003");
+    }
+
+    #[test]
+    fn test_delimited_by_labels_unclosed_delimiter() {
+        let source = "call(\n  1,\n  2\n";
+        let white_space = any()
+            .filter(|c: &char| c.is_ascii_whitespace())
+            .repeated()
+            .at_least(0)
+            .collect::<()>();
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let arguments = digit
+            .padded(white_space)
+            .left_bind(exact(',').or_not())
+            .repeated()
+            .at_least(0)
+            .collect::<Vec<_>>();
+        let call = exact("call").right_bind(arguments.delimited_by(exact('('), exact(')')));
+
+        assert_eq!(call.parse("call(1, 2)"), Ok(vec!['1', '2']));
+
+        let report = call.parse(source).unwrap_err();
+        assert_eq!(report.span(), source.len()..source.len());
+        assert_eq!(
+            report.secondary_labels(),
+            &[(4..5, String::from("unclosed delimiter"))]
+        );
+        assert_eq!(
+            report.display(source).to_string(),
+            concat!(
+                "error[E0002]: found end of input, expected one of any token, ',', ')'\n",
+                " --> 4:1\n",
+                "  |\n",
+                "4 | \n",
+                "  | ^\n",
+                "  |\n",
+                "1 | call(\n",
+                "  |     - unclosed delimiter",
+            )
+        );
+    }
+
+    #[test]
+    fn test_recover_with_max_errors() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let skip_statement = any()
+            .filter(|c: &char| c != &';')
+            .repeated()
+            .at_least(0)
+            .collect::<()>()
+            .map(|_| '?');
+        let statement = digit
+            .left_bind(exact(';'))
+            .recover_with(skip_statement.left_bind(exact(';')));
+        let parser = statement
+            .repeated()
+            .at_least(0)
+            .collect::<String>()
+            .left_bind(end());
+
+        assert_eq!(parser.parse("1;2;3;"), Ok(String::from("123")));
+
+        let errors = parser
+            .parse_with_config("1;x;2;yy;3;", ParseConfig::default())
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].span(), 2..3);
+        assert_eq!(errors[1].span(), 6..7);
+
+        let errors = parser
+            .parse_with_config("a;b;c;d;", ParseConfig::default().max_errors(2))
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[1].span(), 2..3);
+        assert_eq!(errors[2].code(), ErrorCode::TooManyErrors);
+        assert_eq!(errors[2].span(), 4..5);
+    }
+
+    #[test]
+    fn test_expect() {
+        let number = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+        let version = number
+            .clone()
+            .left_bind(exact('.'))
+            .and(number.clone())
+            .left_bind(exact('.'))
+            .and(number)
+            .expect("a version number like 1.2.3");
+        let parser = exact("v").right_bind(version).left_bind(end());
+
+        let report = parser.parse("v1.x.3").unwrap_err();
+        assert_eq!(report.span(), 3..4);
+        assert_eq!(
+            report.expected(),
+            &[Expected::Label("a version number like 1.2.3")]
+        );
+        assert_eq!(
+            report.to_string(),
+            "found 'x', expected a version number like 1.2.3"
+        );
+
+        // Failures happening before the labelled parser are left untouched.
+        let report = parser.parse("w1.2.3").unwrap_err();
+        assert_eq!(report.expected(), &[Expected::Token('v')]);
+    }
+
+    #[test]
+    fn test_expected_merged_across_alternatives() {
+        let keyword = exact("let")
+            .or(exact("loop"))
+            .or(exact("if"))
+            .or(exact("let"));
+
+        let report = keyword.parse("lop").unwrap_err();
+        assert_eq!(report.span(), 2..3);
+        assert_eq!(report.expected(), &[Expected::Token('o')]);
+
+        let report = keyword.parse("x").unwrap_err();
+        assert_eq!(report.span(), 0..1);
+        assert_eq!(
+            report.expected(),
+            &[Expected::Token('l'), Expected::Token('i')]
+        );
+        assert_eq!(report.to_string(), "found 'x', expected one of 'l', 'i'");
+
+        let mut expected = ExpectedSet::from(Expected::Token('a'));
+        expected.union(ExpectedSet::from(vec![
+            Expected::Token('b'),
+            Expected::Token('a'),
+            Expected::EndOfInput,
+        ]));
+        assert_eq!(
+            expected.as_slice(),
+            &[
+                Expected::Token('a'),
+                Expected::Token('b'),
+                Expected::EndOfInput
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labelled_breadcrumbs() {
+        let escape = exact('\\')
+            .right_bind(exact('n').or(exact('"')))
+            .labelled("string escape");
+        let string = escape
+            .delimited_by(exact('"'), exact('"'))
+            .labelled("string literal");
+        let value = exact(':').right_bind(string).labelled("object value");
+
+        assert_eq!(value.parse(r#":"\n""#), Ok("n"));
+
+        let report = value.parse(r#":"\x""#).unwrap_err();
+        assert_eq!(report.span(), 3..4);
+        assert_eq!(
+            report.context(),
+            &[
+                ("string escape", 2),
+                ("string literal", 1),
+                ("object value", 0)
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "found 'x', expected one of 'n', '\"', \
+             in string escape, in string literal, in object value"
+        );
+
+        let errors = value
+            .parse_with_config(r#":"\x""#, ParseConfig::default().max_context_depth(2))
+            .unwrap_err();
+        assert_eq!(
+            errors[0].context(),
+            &[("string escape", 2), ("string literal", 1)]
+        );
+
+        // The furthest failure comes from an earlier attempt, not from within the label.
+        let prefix = exact("abw").right_bind(exact('x')).or_not();
+        let parser = prefix.right_bind(exact("ab").right_bind(exact('q').labelled("q")));
+        let report = parser.parse("abwz").unwrap_err();
+        assert_eq!((report.span(), report.context()), (3..4, &[][..]));
+    }
+
+    #[test]
+    fn test_end_reports_trailing_input() {
+        let parser = exact("ab").left_bind(end());
+
+        let report = parser.parse("abcd").unwrap_err();
+        assert_eq!(report.span(), 2..4);
+        assert_eq!(report.trailing().unwrap().preview(), &['c', 'd']);
+        assert_eq!(
+            report.to_string(),
+            "found trailing input at offset 2, expected end of input, left: 'c', 'd'"
+        );
+
+        let source = format!("ab{}", "x".repeat(100));
+        let report = parser.parse(&source).unwrap_err();
+        let trailing = report.trailing().unwrap();
+        assert_eq!(trailing.preview().len(), 16);
+        assert!(trailing.is_truncated());
+        assert!(report.to_string().ends_with("'x', ..."));
+    }
+
+    #[test]
+    fn test_or_error() {
+        let statement = exact("let ")
+            .right_bind(exact('x'))
+            .or(exact("if ").right_bind(exact('y')))
+            .or_error(|| ParseError::custom("expected a statement"));
+
+        assert_eq!(statement.parse("if y"), Ok("y"));
+
+        let report = statement.parse("while").unwrap_err();
+        assert_eq!(report.span(), 0..1);
+        assert_eq!(report.code(), ErrorCode::Custom);
+        assert_eq!(report.to_string(), "expected a statement");
+
+        // A branch made progress, its error is kept.
+        let report = statement.parse("let z").unwrap_err();
+        assert_eq!(report.span(), 4..5);
+        assert_eq!(report.expected(), &[Expected::Token('x')]);
+
+        // An earlier attempt made progress, not a branch.
+        let prefix = exact("abw").right_bind(exact('x')).or_not();
+        let parser = prefix.right_bind(exact("ab").right_bind(statement));
+        let report = parser.parse("abwhile").unwrap_err();
+        assert_eq!(report.span(), 2..3);
+        assert_eq!(report.to_string(), "expected a statement");
+    }
+
+    #[test]
+    fn test_map_err_with_span() {
+        let string = any()
+            .filter(|c: &char| *c != '"')
+            .repeated()
+            .at_least(0)
+            .collect::<String>()
+            .delimited_by(exact('"'), exact('"'))
+            .map_err_with_span(|_, span: Range<usize>| {
+                ParseError::custom(format!("unterminated string starting at {}", span.start))
+            });
+        let parser = exact("x = ").right_bind(string);
+
+        assert_eq!(parser.parse("x = \"abc\""), Ok(String::from("abc")));
+
+        let report = parser.parse("x = \"abc").unwrap_err();
+        assert_eq!(report.span(), 4..8);
+        assert_eq!(report.message(), Some("unterminated string starting at 4"));
+
+        let pair = (exact('a').and(exact('b')))
+            .or(exact('x').and(exact('y')))
+            .map_err_with_span(|_, span: Range<usize>| {
+                ParseError::custom(format!("bad pair at {span:?}"))
+            });
+        let report = pair.parse("ac").unwrap_err();
+        assert_eq!(report.span(), 0..1);
+        assert_eq!(report.message(), Some("bad pair at 0..1"));
+
+        // The error of an earlier branch, further to the right, isn't part of the span.
+        let mapped = std::cell::RefCell::new(None);
+        let keyword =
+            exact("abcd").or(exact('x').map_err_with_span(|error, span: Range<usize>| {
+                *mapped.borrow_mut() = Some(span);
+                error
+            }));
+        assert!(keyword.parse("abcz").is_err());
+        assert_eq!(*mapped.borrow(), Some(0..0));
+    }
+
+    #[test]
+    fn test_on_err() {
+        let failures = std::cell::RefCell::new(Vec::new());
+        let parser = exact("ab")
+            .on_err(|error, input_ref| {
+                failures
+                    .borrow_mut()
+                    .push((error.is_fatal(), input_ref.error_offset()))
+            })
+            .or(exact("cd"));
+
+        assert_eq!(parser.parse("ab"), Ok("ab"));
+        assert!(failures.borrow().is_empty());
+
+        assert_eq!(parser.parse("cd"), Ok("cd"));
+        assert_eq!(*failures.borrow(), [(false, Some(0))]);
+    }
+
+    #[test]
+    fn test_parse_or_report() {
+        let parser = exact("port = ").right_bind(exact("80"));
+        assert_eq!(parser.parse_or_report("port = 80"), "80");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "parse failed:\nerror[E0001]: found '1', expected '8'\n --> 1:8\n  |\n1 | port = 10\n  |        ^"
+    )]
+    fn test_parse_or_report_panics() {
+        exact("port = ")
+            .right_bind(exact("80"))
+            .parse_or_report("port = 10");
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let statement = digit
+            .left_bind(exact(';'))
+            .recover_with(any().left_bind(exact(';')).map(|_| '?'));
+        let parser = statement
+            .repeated()
+            .at_least(0)
+            .collect::<String>()
+            .left_bind(end());
+
+        let (output, errors) = parser
+            .parse_partial("1;x;3;", ParseConfig::default())
+            .into_output_errors();
+        assert_eq!(output, Some(String::from("1?3")));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span(), 2..3);
+
+        let partial = parser.parse_partial("1;2;", ParseConfig::default());
+        assert!(!partial.has_errors());
+        assert_eq!(partial.into_result(), Ok(String::from("12")));
+
+        let partial = digit.parse_partial("x", ParseConfig::default());
+        assert_eq!(partial.output(), None);
+        assert_eq!(partial.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_recover_to() {
+        #[derive(Debug, PartialEq)]
+        enum Expr {
+            Digit(char),
+            Error(Range<usize>),
+        }
+
+        let digit = any().filter(|c: &char| c.is_ascii_digit()).map(Expr::Digit);
+        let skip = any()
+            .filter(|c: &char| *c != ';')
+            .repeated()
+            .at_least(1)
+            .collect::<()>();
+        let parser = digit
+            .recover_to(skip, Expr::Error)
+            .left_bind(exact(';'))
+            .repeated()
+            .at_least(0)
+            .collect::<Vec<_>>()
+            .left_bind(end());
+
+        let (output, errors) = parser
+            .parse_partial("1;xyz;3;", ParseConfig::default())
+            .into_output_errors();
+        assert_eq!(
+            output,
+            Some(vec![Expr::Digit('1'), Expr::Error(2..5), Expr::Digit('3')])
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span(), 2..3);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let config = ParseConfig::default().partial(true);
+        let keyword = exact("let").or(exact("if"));
+
+        let mut input_ref = InputRef::with_config(&"le", config);
+        assert_eq!(
+            keyword.go(&mut input_ref),
+            Err(ParseError::Incomplete { needed: Some(1) })
+        );
+
+        // Mismatches are still reported as such.
+        let mut input_ref = InputRef::with_config(&"lo", config);
+        assert_eq!(keyword.go(&mut input_ref), Err(ParseError::SyntaxError));
+
+        let mut input_ref = InputRef::with_config(&"ab", config);
+        assert_eq!(
+            exact("ab").left_bind(end()).go(&mut input_ref),
+            Err(ParseError::Incomplete { needed: None })
+        );
+
+        let mut input_ref = InputRef::with_config(&"12", config);
+        assert_eq!(
+            one_of(vec!["1234", "12"]).go(&mut input_ref),
+            Err(ParseError::Incomplete { needed: Some(2) })
+        );
+        assert_eq!(
+            any()
+                .repeated()
+                .at_least(0)
+                .collect::<String>()
+                .go(&mut InputRef::with_config(&"ab", config)),
+            Err(ParseError::Incomplete { needed: Some(1) })
+        );
+
+        // Complete inputs are unaffected.
+        assert_eq!(
+            keyword.parse("le").unwrap_err().code(),
+            ErrorCode::UnexpectedEndOfInput
+        );
+    }
+
+    #[test]
+    fn test_error_report_position() {
+        let source = "name = \"café\"\nport = 😀80";
+        let parser = exact("name = \"café\"\nport = ").right_bind(exact("80"));
+
+        let position = parser.parse(source).unwrap_err().position(source);
+        assert_eq!(position.byte(), 22);
+        assert_eq!(position.char(), 21);
+        assert_eq!(position.line(), 1);
+        assert_eq!(position.column(), 7);
+        assert_eq!(position.utf16_column(), 7);
+        assert_eq!(position.to_string(), "2:8");
+
+        let position = Position::locate(source, 26);
+        assert_eq!(position.column(), 8);
+        assert_eq!(position.utf16_column(), 9);
+    }
+
+    #[test]
+    fn test_error_report_display_window() {
+        let source = "[\n  1,\n  2x\n]\n";
+        let parser = exact("[\n  1,\n  2").right_bind(exact(",\n"));
+
+        let report = parser.parse(source).unwrap_err();
+        assert_eq!(
+            report.display(source).context_lines(1).to_string(),
+            concat!(
+                "error[E0001]: found 'x', expected ','\n",
+                " --> 3:4\n",
+                "  |\n",
+                "2 |   1,\n",
+                "3 |   2x\n",
+                "  |    ^\n",
+                "4 | ]",
+            )
+        );
+
+        let source = format!("{{{}x}}", "\"a\":1,".repeat(20));
+        let parser =
+            exact('{').right_bind(exact("\"a\":1,").repeated().at_least(0).collect::<Vec<_>>());
+        let parser = parser.left_bind(exact('}'));
+
+        let report = parser.parse(source.as_str()).unwrap_err();
+        assert_eq!(
+            report.display(&source).max_line_width(10).to_string(),
+            concat!(
+                "error[E0001]: found 'x', expected one of '\"', '}'\n",
+                " --> 1:122\n",
+                "  |\n",
+                "1 | ...1,\"a\":1,x}\n",
+                "  |            ^",
+            )
+        );
+    }
+
+    #[test]
+    fn test_guard() {
+        let port = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .map(|digits| digits.parse::<u64>().unwrap())
+            .guard(|port| *port <= 65535, "port number must be <= 65535");
+        let parser = exact("port=").right_bind(port).left_bind(end());
+
+        assert_eq!(parser.parse("port=8080"), Ok(8080));
+
+        let report = parser.parse("port=99999").unwrap_err();
+        assert_eq!(report.span(), 5..10);
+        assert_eq!(report.code(), ErrorCode::RejectedToken);
+        assert_eq!(report.to_string(), "port number must be <= 65535");
+    }
+
+    #[test]
+    fn test_token_slice_input() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Token {
+            Let,
+            Ident,
+            Eq,
+            Number,
+        }
+
+        let tokens = [Token::Let, Token::Ident, Token::Eq, Token::Number];
+        let binding = exact(&[Token::Let, Token::Ident][..])
+            .right_bind(exact(&[Token::Eq][..]))
+            .right_bind(any())
+            .left_bind(end());
+
+        assert_eq!(binding.parse(&tokens[..]), Ok(Token::Number));
+
+        let report = binding.parse(&tokens[..3]).unwrap_err();
+        assert_eq!(report.span(), 3..3);
+        assert_eq!(report.expected(), &[Expected::Any]);
+    }
+
+    #[test]
+    fn test_iter_input() {
+        let keyword = exact("let").or(exact("lex"));
+        let parser = keyword
+            .left_bind(exact(' '))
+            .right_bind(any().repeated().at_least(1).collect::<String>());
+
+        let input = IterInput::new("lex x".chars());
+        assert_eq!(parser.parse(input), Ok(String::from("x")));
+
+        let report = parser.parse(IterInput::new("lez".chars())).unwrap_err();
+        assert_eq!(report.span(), 2..3);
+        assert_eq!(
+            report.expected(),
+            &[Expected::Token('t'), Expected::Token('x')]
+        );
+
+        let input = IterInput::new([1, 2, 3]);
+        let mut input_ref = InputRef::new(&input);
+        let slice = exact(&[1, 2][..]).go(&mut input_ref).unwrap();
+        assert_eq!(slice.range(), 0..2);
+        assert_eq!(input.tokens(slice), [1, 2]);
+    }
+
+    #[test]
+    fn test_reader_input() {
+        let digits = || {
+            any()
+                .filter(|b: &u8| b.is_ascii_digit())
+                .repeated()
+                .at_least(1)
+                .collect::<Vec<_>>()
+        };
+        let record = digits().left_bind(exact(&b";"[..]));
+
+        let mut input = ReaderInput::with_chunk_size(&b"1;23;4567;"[..], 3);
+        assert_eq!(input.parse_next(&record).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(input.parse_next(&record).unwrap(), Some(b"23".to_vec()));
+        assert_eq!(input.parse_next(&record).unwrap(), Some(b"4567".to_vec()));
+        assert_eq!(input.offset(), 10);
+        assert_eq!(input.parse_next(&record).unwrap(), None);
+
+        let mut input = ReaderInput::with_chunk_size(&b"1;2x;"[..], 2);
+        assert_eq!(input.parse_next(&record).unwrap(), Some(b"1".to_vec()));
+        match input.parse_next(&record) {
+            Err(StreamError::Parse(report)) => {
+                assert_eq!(report.span(), 3..4);
+                assert_eq!(report.found(), Some(&b'x'));
+            }
+            _ => panic!("expected a parse error"),
+        }
+
+        // The stream ends in the middle of a record.
+        let mut input = ReaderInput::with_chunk_size(&b"12"[..], 1);
+        assert_eq!(input.parse_next(&digits()).unwrap(), Some(b"12".to_vec()));
+        let mut input = ReaderInput::with_chunk_size(&b"12"[..], 1);
+        assert!(matches!(
+            input.parse_next(&record),
+            Err(StreamError::Parse(report)) if report.code() == ErrorCode::UnexpectedEndOfInput
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_reader_input() {
+        let record = any()
+            .filter(|b: &u8| b.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .left_bind(exact(&b";"[..]));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut input = AsyncReaderInput::with_chunk_size(&b"12;345;6x"[..], 2);
+            assert_eq!(
+                input.parse_next(&record).await.unwrap(),
+                Some(b"12".to_vec())
+            );
+            assert_eq!(
+                input.parse_next(&record).await.unwrap(),
+                Some(b"345".to_vec())
+            );
+            assert!(matches!(
+                input.parse_next(&record).await,
+                Err(StreamError::Parse(report)) if report.span() == (8..9)
+            ));
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_reader_limits() {
+        use std::time::Duration;
+        use tokio::io::AsyncWriteExt;
+
+        let record = any()
+            .filter(|b: &u8| *b != b'\n')
+            .repeated()
+            .at_least(0)
+            .collect::<Vec<_>>()
+            .left_bind(exact(&b"\n"[..]));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (mut peer, socket) = tokio::io::duplex(64);
+            peer.write_all(b"ping\npi").await.unwrap();
+            let mut input = AsyncReaderInput::new(socket).with_timeout(Duration::from_millis(10));
+            assert_eq!(
+                input.parse_next(&record).await.unwrap(),
+                Some(b"ping".to_vec())
+            );
+            assert!(matches!(
+                input.parse_next(&record).await,
+                Err(StreamError::Timeout)
+            ));
+
+            let mut input =
+                AsyncReaderInput::with_chunk_size(&b"pong\nlong line\n"[..], 4).max_frame_size(8);
+            assert_eq!(
+                input.parse_next(&record).await.unwrap(),
+                Some(b"pong".to_vec())
+            );
+            assert!(matches!(
+                input.parse_next(&record).await,
+                Err(StreamError::FrameTooLarge { limit: 8 })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_parse_state() {
+        let record = any()
+            .filter(|b: &u8| b.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .left_bind(exact(&b";"[..]));
+        let mut state = ParseState::new(record);
+
+        assert_eq!(state.feed(b"1"), Status::NeedMore);
+        assert_eq!(state.feed(b"2;34"), Status::Done(b"12".to_vec()));
+        assert_eq!(state.feed(b""), Status::NeedMore);
+        assert_eq!(state.feed(b";5"), Status::Done(b"34".to_vec()));
+        assert_eq!(state.offset(), 6);
+
+        match state.finish() {
+            Status::Error(report) => {
+                assert_eq!(report.span(), 7..7);
+                assert_eq!(report.code(), ErrorCode::UnexpectedEndOfInput);
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_spanned_input() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Token {
+            Let,
+            Ident,
+            Eq,
+            Number,
+        }
+
+        // let  x = = 1
+        let tokens = [
+            (Token::Let, 0..3),
+            (Token::Ident, 5..6),
+            (Token::Eq, 7..8),
+            (Token::Eq, 9..10),
+            (Token::Number, 11..12),
+        ];
+        let binding = exact(&[Token::Let, Token::Ident][..])
+            .right_bind(exact(&[Token::Eq, Token::Number][..]));
+
+        let report = binding.parse(SpannedInput::new(&tokens)).unwrap_err();
+        assert_eq!(report.span(), 9..11);
+        assert_eq!(report.expected(), &[Expected::Token(Token::Number)]);
+
+        let slice = exact(&[Token::Let, Token::Ident][..])
+            .parse(SpannedInput::new(&tokens))
+            .unwrap();
+        assert_eq!(slice.span(), 0..6);
+        assert_eq!(slice.tokens().count(), 2);
+    }
+
+    #[test]
+    fn test_lexer() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Token {
+            Let,
+            Ident,
+            Eq,
+            Number,
+        }
+
+        let letters = any()
+            .filter(|c: &char| c.is_ascii_alphabetic())
+            .repeated()
+            .at_least(1)
+            .collect::<()>();
+        let digits = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<()>();
+        let white_space = any()
+            .filter(|c: &char| c.is_ascii_whitespace())
+            .repeated()
+            .at_least(1)
+            .collect::<()>();
+        let lexer = Lexer::new()
+            .skip(white_space)
+            .token(exact("let").map(|_| Token::Let))
+            .token(letters.map(|_| Token::Ident))
+            .token(digits.map(|_| Token::Number))
+            .token(exact('=').map(|_| Token::Eq));
+
+        let tokens = lexer.lex("let x = 42").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                (Token::Let, 0..3),
+                (Token::Ident, 4..5),
+                (Token::Eq, 6..7),
+                (Token::Number, 8..10),
+            ]
+        );
+
+        let binding = exact(&[Token::Let, Token::Ident, Token::Eq][..])
+            .right_bind(exact(&[Token::Number][..]));
+        let tokens = lexer.lex("let x = y").unwrap();
+        let report = binding.parse(SpannedInput::new(&tokens)).unwrap_err();
+        assert_eq!(report.span(), 8..9);
+
+        let report = lexer.lex("let x = 4?2").unwrap_err();
+        assert_eq!(report.span(), 9..10);
+        assert_eq!(report.to_string(), "found '?', expected a token");
+    }
+
+    #[test]
+    #[cfg(feature = "logos")]
+    fn test_logos_input() {
+        use logos::Logos;
+
+        #[derive(Logos, Debug, Clone, Copy, PartialEq, Eq)]
+        #[logos(skip r"[ \t\n]+")]
+        enum Token {
+            #[token("let")]
+            Let,
+            #[regex("[a-z]+")]
+            Ident,
+            #[token("=")]
+            Eq,
+            #[regex("[0-9]+")]
+            Number,
+        }
+
+        let binding = exact(&[Token::Let, Token::Ident, Token::Eq][..])
+            .right_bind(exact(&[Token::Number][..]));
+
+        let tokens = LogosInput::new(Token::lexer("let x = 42")).unwrap();
+        assert_eq!(tokens.tokens()[3], (Token::Number, 8..10));
+        assert!(binding.parse(tokens.input()).is_ok());
+
+        let tokens = LogosInput::new(Token::lexer("let x = y")).unwrap();
+        assert_eq!(binding.parse(tokens.input()).unwrap_err().span(), 8..9);
+
+        let report = LogosInput::new(Token::lexer("let x = ?")).unwrap_err();
+        assert_eq!(report.span(), 8..9);
+        assert_eq!(report.found(), Some(&'?'));
+    }
+
+    #[test]
+    fn test_located_input() {
+        let source = "a = 1\r\nbb = 22\n";
+        let input = LocatedInput::new(source);
+        let mut input_ref = InputRef::new(&input);
+
+        let key = exact("a = 1\r\n").right_bind(exact("bb"));
+        let slice = key.go(&mut input_ref).unwrap();
+        assert_eq!(slice.as_str(), "bb");
+        assert_eq!((slice.start().line(), slice.start().column()), (1, 0));
+        assert_eq!(slice.end().to_string(), "2:3");
+
+        let position = input_ref.position();
+        assert_eq!((position.line(), position.column()), (1, 2));
+        assert_eq!(position, Position::locate(source, 9));
+
+        let report = key.right_bind(exact(" = 3")).parse(input).unwrap_err();
+        assert_eq!(report.span(), 12..13);
+    }
+
+    #[test]
+    fn test_sourced_input() {
+        let source = "port = 8o";
+        let parser = exact("port = 80");
+
+        let report = parser
+            .parse(Sourced::new(source, "config.toml"))
+            .unwrap_err();
+        assert_eq!(
+            report.source_id().map(SourceId::as_str),
+            Some("config.toml")
+        );
+        assert_eq!(
+            report.display(source).to_string(),
+            concat!(
+                "error[E0001]: found 'o', expected '0'\n",
+                " --> config.toml:1:9\n",
+                "  |\n",
+                "1 | port = 8o\n",
+                "  |         ^",
+            )
+        );
+
+        assert_eq!(
+            exact("port = 80").parse(source).unwrap_err().source_id(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_skip_trivia() {
+        let white_space = any().filter(|c: &char| c.is_ascii_whitespace());
+        let comment = exact("/*")
+            .right_bind(
+                any()
+                    .filter(|c: &char| *c != '*')
+                    .repeated()
+                    .at_least(0)
+                    .collect::<()>(),
+            )
+            .right_bind(exact("*/"));
+        let trivia = white_space.map(|_| ()).or(comment.map(|_| ()));
+        let binding = exact("let")
+            .right_bind(any())
+            .left_bind(exact('='))
+            .left_bind(exact("42"))
+            .left_bind(end());
+
+        let input = SkipTrivia::new(" let /* name */ x\n =  4 2 ", trivia);
+        assert_eq!(binding.parse(input), Ok('x'));
+
+        let report = binding
+            .parse(SkipTrivia::new("let x /* eq */ : 42", trivia))
+            .unwrap_err();
+        assert_eq!(report.span(), 15..17);
+        assert_eq!(report.found(), Some(&':'));
+
+        // What the trivia emit reaches the parse once, however often they're skipped again.
+        let tab = exact('\t').validate(|_, span, emitter| emitter.warning(span, "tab"));
+        let unterminated = exact("/*")
+            .right_bind(any().repeated().at_least(0).collect::<()>())
+            .right_bind(exact("*/").map(|_| ()).recover_with(end()));
+        let trivia = tab.map(|_| ()).or(exact(' ').map(|_| ())).or(unterminated);
+        let binding = exact("let")
+            .right_bind(any())
+            .left_bind(exact(':'))
+            .or(exact("let").right_bind(any()).left_bind(exact('=')))
+            .left_bind(exact("42"))
+            .left_bind(end());
+
+        let input = SkipTrivia::new("let\tx = 42 /* no end", trivia);
+        let (result, diagnostics) = binding.parse_with_diagnostics(input);
+        assert_eq!(result, Ok('x'));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span(), 3..4);
+
+        let input = SkipTrivia::new("let\tx = 42 /* no end", trivia);
+        let errors = binding
+            .parse_with_config(input, ParseConfig::default())
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span(), 20..20);
+    }
+
+    #[test]
+    fn test_chunked_input() {
+        let chunks = ["let na", "", "me = \"ca", "fé\";"];
+        let ident = any()
+            .filter(|c: &char| c.is_alphanumeric() || *c == 'é')
+            .repeated()
+            .at_least(1)
+            .collect::<()>();
+        let parser = exact("let ")
+            .right_bind(exact("name"))
+            .left_bind(exact(" = "))
+            .and(
+                ident
+                    .delimited_by(exact('"'), exact('"'))
+                    .left_bind(exact(';')),
+            );
+
+        let (name, _) = parser.parse(ChunkedInput::new(&chunks)).unwrap();
+        assert_eq!(name.to_cow(), "name");
+        assert_eq!(name.chunks().collect::<Vec<_>>(), ["na", "me"]);
+        assert_eq!(name.to_string(), "name");
+
+        let value = any()
+            .repeated()
+            .at_least(0)
+            .collect::<()>()
+            .left_bind(end());
+        let input = ChunkedInput::new(&chunks);
+        let mut input_ref = InputRef::new(&input);
+        value.go(&mut input_ref).unwrap();
+        assert_eq!(input_ref.offset(), 19);
+        assert!(matches!(
+            input.slice(11, 14).to_cow(),
+            Cow::Borrowed("\"ca")
+        ));
+
+        let report = parser
+            .parse(ChunkedInput::new(&["let nam", "ing"]))
+            .unwrap_err();
+        assert_eq!(report.span(), 7..8);
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mmap_input() {
+        let path = std::env::temp_dir().join(format!("parser-mmap-{}.log", std::process::id()));
+        std::fs::write(&path, b"GET /index.html\n").unwrap();
+
+        let file = MmapInput::open(&path).unwrap();
+        let request = exact(&b"GET "[..]).right_bind(exact(&b"/index.htm"[..]));
+        assert_eq!(request.parse(file.input()), Ok(&b"/index.htm"[..]));
+
+        let report = exact(&b"POST"[..]).parse(file.input()).unwrap_err();
+        assert_eq!(
+            report.source_id().map(SourceId::as_str),
+            Some(path.to_string_lossy().as_ref())
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_source_text() {
+        let path = std::env::temp_dir().join(format!("parser-source-{}.toml", std::process::id()));
+        std::fs::write(&path, "port = 8o\n").unwrap();
+
+        let source = SourceText::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let report = exact("port = 80").parse(source.input()).unwrap_err();
+        assert_eq!(report.source_id(), Some(source.source_id()));
+        assert!(report
+            .display(source.as_str())
+            .to_string()
+            .contains(&format!("--> {}:1:9", path.display())));
+
+        let source = SourceText::from_reader("port = 80".as_bytes()).unwrap();
+        assert_eq!(source.source_id().as_str(), "<input>");
+        assert_eq!(exact("port = 80").parse(source.input()), Ok("port = 80"));
+    }
+
+    #[test]
+    fn test_utf16_input() {
+        let units: Vec<u16> = "héllo 🎉!".encode_utf16().collect();
+        let greeting = exact("héllo ")
+            .right_bind(any())
+            .left_bind(exact('!'))
+            .left_bind(end());
+        assert_eq!(greeting.parse(Utf16Input::new(&units)), Ok('🎉'));
+        let word = exact("héllo").parse(Utf16Input::new(&units)).unwrap();
+        assert_eq!(word.to_string(), "héllo");
+
+        // 'a', an unpaired high surrogate, '!'.
+        let units = [0x61, 0xd83c, 0x21];
+        let parser = exact('a').right_bind(any()).left_bind(exact('!'));
+        assert_eq!(
+            parser.parse(Utf16Input::lossy(&units)),
+            Ok(char::REPLACEMENT_CHARACTER)
+        );
+
+        let report = parser.parse(Utf16Input::new(&units)).unwrap_err();
+        assert_eq!(report.code(), ErrorCode::InvalidEncoding);
+        assert_eq!(report.span(), 1..2);
+        assert_eq!(
+            report.message(),
+            Some("invalid UTF-16 sequence at offset 1")
+        );
+
+        let report = exact('a')
+            .left_bind(end())
+            .parse(Utf16Input::new(&units))
+            .unwrap_err();
+        assert_eq!(report.code(), ErrorCode::InvalidEncoding);
+    }
+
+    #[test]
+    fn test_utf8_input() {
+        let header = exact("Host: ")
+            .right_bind(
+                any()
+                    .filter(|c: &char| *c != '\r')
+                    .repeated()
+                    .at_least(1)
+                    .collect::<String>(),
+            )
+            .left_bind(exact("\r\n"))
+            .left_bind(end());
+        let host = header.parse(Utf8Input::new(b"Host: caf\xc3\xa9.fr\r\n"));
+        assert_eq!(host.as_deref(), Ok("café.fr"));
+
+        let report = header
+            .parse(Utf8Input::new(b"Host: caf\xc3\x28.fr\r\n"))
+            .unwrap_err();
+        assert_eq!(report.code(), ErrorCode::InvalidEncoding);
+        assert_eq!(report.span(), 9..10);
+        assert_eq!(report.message(), Some("invalid UTF-8 sequence at offset 9"));
+
+        // Cut short by the end of input.
+        let report = exact("caf")
+            .left_bind(end())
+            .parse(Utf8Input::new(b"caf\xc3"))
+            .unwrap_err();
+        assert_eq!(report.span(), 3..4);
+    }
+
+    #[test]
+    fn test_shared_input() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let source: Arc<str> = Arc::from("key = value");
+        let key = thread::spawn({
+            let source = source.clone();
+            let key = exact("key").left_bind(exact(" = "));
+            move || key.parse(source)
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        assert_eq!(key.range(), 0..3);
+        assert_eq!(key.of(&source), "key");
+
+        let bytes: Arc<[u8]> = Arc::from(&b"\x01\x02\x03"[..]);
+        let slice = exact([1u8, 2].as_slice()).parse(bytes.clone()).unwrap();
+        assert_eq!(slice.of(&bytes), [1, 2]);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut input_ref = input_ref!("abc");
+        input_ref.next_token();
+        input_ref.emitter().note(0..1, "kept");
+
+        let checkpoint = input_ref.save();
+        assert_eq!(checkpoint.offset(), 1);
+        input_ref.next_token();
+        input_ref.emitter().warning(0..2, "rolled back");
+        input_ref.restore(checkpoint).unwrap();
+        assert_eq!(input_ref.offset(), 1);
+
+        let checkpoint = input_ref.save();
+        input_ref.next_token();
+        input_ref.emitter().note(1..2, "committed");
+        input_ref.commit(checkpoint);
+        assert_eq!(input_ref.offset(), 2);
+
+        let messages: Vec<_> = input_ref
+            .take_diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.message().to_owned())
+            .collect();
+        assert_eq!(messages, ["kept", "committed"]);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // parens := '(' parens? ')'
+        struct Parens;
+
+        impl<'input> Parser<'input, &'input str, usize> for Parens {
+            fn go(&self, input_ref: &mut InputRef<'input, '_, &'input str>) -> ParseResult<usize> {
+                exact('(')
+                    .right_bind(Parens.nested().or_not())
+                    .left_bind(exact(')'))
+                    .map(|inner| inner.map_or(1, |depth| depth + 1))
+                    .go(input_ref)
+            }
+        }
+
+        let config = ParseConfig::default().max_depth(4);
+        assert_eq!(Parens.parse_with_config("(((())))", config), Ok(4));
+
+        let errors = Parens.parse_with_config("((((()))))", config).unwrap_err();
+        assert_eq!(errors[0].code(), ErrorCode::DepthExceeded);
+        // The fifth level is already too deep to look for a sixth one.
+        assert_eq!(errors[0].span(), 5..6);
+
+        let deep = "(".repeat(100_000);
+        let errors = Parens.parse_with_config(deep.as_str(), config).unwrap_err();
+        assert_eq!(errors[0].code(), ErrorCode::DepthExceeded);
+
+        // The stack grows on demand, nesting is only limited by `max_depth`.
+        #[cfg(feature = "stacker")]
+        {
+            let depth = 100_000;
+            let deep = "(".repeat(depth) + &")".repeat(depth);
+            let config = ParseConfig::default().max_depth(depth);
+            assert_eq!(Parens.parse_with_config(deep.as_str(), config), Ok(depth));
+        }
+
+        // Recursing through a boxed parser or a rule counts without `nested`.
+        struct Unwrapped;
+
+        impl<'input> Parser<'input, &'input str, usize> for Unwrapped {
+            fn go(&self, input_ref: &mut InputRef<'input, '_, &'input str>) -> ParseResult<usize> {
+                exact('(')
+                    .right_bind(Unwrapped.boxed().or_not())
+                    .left_bind(exact(')'))
+                    .map(|inner| inner.map_or(1, |depth| depth + 1))
+                    .rule()
+                    .go(input_ref)
+            }
+        }
+
+        let unwrapped = Unwrapped.boxed();
+        assert_eq!(unwrapped.parse("(())"), Ok(2));
+        let error = unwrapped.parse(deep.as_str()).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::DepthExceeded);
+    }
+
+    #[test]
+    fn test_peek_n() {
+        let mut input_ref = input_ref!("if x");
+        input_ref.next_token();
+        assert_eq!(input_ref.peek_n(0), input_ref.peek());
+        let peek_n = |n| {
+            let (offset, token) = input_ref.peek_n(n);
+            (usize::from(offset), token)
+        };
+        assert_eq!(peek_n(1), (3, Some(' ')));
+        assert_eq!(peek_n(3), (4, None));
+        assert_eq!(peek_n(10), (4, None));
+        assert_eq!(input_ref.lookahead().collect::<String>(), "f x");
+        assert_eq!(input_ref.offset(), 1);
+    }
+
+    #[test]
+    fn test_consumed_remaining() {
+        let mut input_ref = input_ref!("key: value");
+        exact("key").go(&mut input_ref).unwrap();
+        let checkpoint = input_ref.save();
+        exact(": ").go(&mut input_ref).unwrap();
+        assert_eq!(input_ref.consumed(), "key: ");
+        assert_eq!(input_ref.slice_from(&checkpoint), ": ");
+        assert_eq!(input_ref.remaining(), "value");
+
+        let header = exact("HTTP/1.1 200 OK\r\n");
+        assert_eq!(
+            header.parse_prefix("HTTP/1.1 200 OK\r\n<html>"),
+            Ok(("HTTP/1.1 200 OK\r\n", "<html>"))
+        );
+
+        // The trivia at the end is left to parse too.
+        let spaced = SkipTrivia::new("key  value ", exact(' '));
+        assert_eq!(exact("key").parse_prefix(spaced), Ok(("key  ", "value ")));
+    }
+
+    #[test]
+    fn test_bit_input() {
+        // DNS header: id, then QR, OPCODE, AA, TC, RD, RA, Z and RCODE.
+        let header = bits(16).and(
+            bits(1)
+                .and(bits(4))
+                .and(bits(4).right_bind(bits(3)))
+                .and(bits(4)),
+        );
+        let bytes = [0xab, 0xcd, 0x81, 0x83];
+        let (id, (((qr, opcode), z), rcode)) = header.parse(BitInput::new(&bytes)).unwrap();
+        assert_eq!((id, qr, opcode, z, rcode), (0xabcd, 1, 0, 0, 3));
+
+        let slice = exact([true, false].as_slice())
+            .parse(BitInput::new(&[0x80]))
+            .unwrap();
+        assert_eq!(slice.bits().collect::<Vec<_>>(), [true, false]);
+        assert_eq!(slice.as_bytes(), None);
+
+        let report = bits(12).parse(BitInput::new(&[0xff])).unwrap_err();
+        assert_eq!(report.span(), 8..8);
+    }
+
+    #[test]
+    fn test_input_span() {
+        let word = exact("world").validate(|_, span, _| span);
+        let span = exact("hello\n")
+            .right_bind(word)
+            .parse(LocatedInput::new("hello\nworld"))
+            .unwrap();
+        assert_eq!((span.start.line(), span.start.column()), (1, 0));
+        assert_eq!(span.end.column(), 5);
+
+        let word = exact("world").validate(|_, span, _| span);
+        let (source_id, span) = exact("hello\n")
+            .right_bind(word)
+            .parse(Sourced::new("hello\nworld", "greeting.txt"))
+            .unwrap();
+        assert_eq!((source_id.as_str(), span), ("greeting.txt", 6..11));
+
+        let tokens = [('a', 0..3), ('b', 4..9)];
+        let span = exact(['a', 'b'].as_slice())
+            .validate(|_, span, _| span)
+            .parse(SpannedInput::new(&tokens));
+        assert_eq!(span, Ok(0..9));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "checkpoint saved by another parse")]
+    fn test_checkpoint_of_another_parse() {
+        let first = input_ref!("abc");
+        let mut second = input_ref!("abc");
+        _ = second.restore(first.save());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "offset of another parse")]
+    fn test_offset_of_another_parse() {
+        let first = input_ref!("abc");
+        let second = input_ref!("abc");
+        second.slice(first.start(), second.offset());
+    }
+
+    #[test]
+    fn test_owned_input() {
+        let name = exact("name").left_bind(exact(" = "));
+        let slice = name.parse(String::from("name = value")).unwrap();
+        assert_eq!(slice, "name");
+
+        let report = name.parse(String::from("name: value")).unwrap_err();
+        assert_eq!(report.span(), 4..5);
+
+        let value = exact([0xcau8, 0xfe].as_slice()).parse(vec![0xca, 0xfe, 0xba, 0xbe]);
+        assert_eq!(value, Ok(vec![0xca, 0xfe]));
+    }
+
+    #[test]
+    fn test_map_tokens() {
+        let keyword = exact("select").left_bind(exact(' '));
+        let input = "SeLeCt *".map_tokens(|c: char| c.to_ascii_lowercase());
+        assert_eq!(keyword.parse(input), Ok("SeLeCt"));
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Kind {
+            Number,
+            Plus,
+        }
+
+        let tokens = [(Kind::Number, 1), (Kind::Plus, 0), (Kind::Number, 2)];
+        let input = tokens.as_slice().map_tokens(|(kind, _)| kind);
+        let sum = exact([Kind::Number, Kind::Plus, Kind::Number].as_slice()).parse(input);
+        assert_eq!(sum.map(|slice| slice.len()), Ok(3));
+    }
+
+    #[test]
+    fn test_non_slicing_primitives() {
+        let method = one_of_index(vec!["GET", "POST", "PUT"]).left_bind(tag(" /"));
+        let input = IterInput::new("POST /index.html".chars());
+        assert_eq!(method.parse(input), Ok(1));
+
+        let report = method.parse(IterInput::new("PATCH /".chars())).unwrap_err();
+        assert_eq!(report.span(), 1..2);
+    }
+
+    #[test]
+    fn test_interned() {
+        let interner = Interner::new();
+        let ident = one_of(vec!["x", "y"]).interned(&interner);
+        let assignment = ident
+            .clone()
+            .left_bind(exact(" = "))
+            .and(ident)
+            .left_bind(exact(';'));
+
+        let (x, y) = assignment.parse("x = y;").unwrap();
+        let (y2, x2) = assignment.parse("y = x;").unwrap();
+        assert_eq!((x, y), (x2, y2));
+        assert_ne!(x, y);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(&*interner.resolve(y), "y");
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let keyword = exact("interface").or(exact("internal"));
+        let (result, stats) = keyword.parse_with_stats("internal");
+        assert_eq!(result, Ok("internal"));
+        assert_eq!(stats.tokens(), 13);
+        assert_eq!(stats.advanced(), 13);
+        assert_eq!((stats.rewinds(), stats.backtracked()), (1, 5));
+        assert_eq!(stats.furthest(), 8);
+    }
+
+    #[test]
+    fn test_max_backtrack() {
+        let keyword = exact("interface").or(exact("internal"));
+        let config = ParseConfig::default().max_backtrack(5);
+        assert_eq!(
+            keyword.parse_with_config("internal", config),
+            Ok("internal")
+        );
+
+        let config = ParseConfig::default().max_backtrack(4);
+        let errors = keyword.parse_with_config("internal", config).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code(), ErrorCode::BudgetExceeded);
+        assert_eq!(errors[0].span(), 0..1);
+
+        // Going back by hand is charged too.
+        struct Reread;
+
+        impl<'input> Parser<'input, &'input str, &'input str> for Reread {
+            fn go(
+                &self,
+                input_ref: &mut InputRef<'input, '_, &'input str>,
+            ) -> ParseResult<&'input str> {
+                let (start, mark) = (input_ref.offset(), input_ref.mark());
+                exact("ab").go(input_ref)?;
+                input_ref.rewind(start)?;
+                exact("ab").go(input_ref)?;
+                input_ref.resume(&mark)?;
+                exact("ab").go(input_ref)
+            }
+        }
+
+        let config = ParseConfig::default().max_backtrack(4);
+        assert_eq!(Reread.parse_with_config("ab", config), Ok("ab"));
+        let config = ParseConfig::default().max_backtrack(3);
+        let errors = Reread.parse_with_config("ab", config).unwrap_err();
+        assert_eq!(errors[0].code(), ErrorCode::BudgetExceeded);
+        assert_eq!(errors[0].span(), 0..1);
+    }
+
+    #[test]
+    fn test_parse_from_mark() {
+        let header = exact("Length: ")
+            .right_bind(exact('4'))
+            .left_bind(exact('\n'));
+        let message = "Length: 4\nbody";
+        let (_, mark) = header.parse_marked(message).unwrap();
+        assert_eq!(mark.offset(), 10);
+
+        let body = exact("body").or(exact("both"));
+        assert_eq!(body.parse_from(message, &mark), Ok("body"));
+        // Spans are relative to the whole message.
+        let error = exact("blob").parse_from(message, &mark).unwrap_err();
+        assert_eq!(error.span(), 11..12);
+
+        let error = body.parse_from("Length: 4\nhead", &mark).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::StaleMark);
+        let (_, mark) = exact("Length: ")
+            .parse_marked(Sourced::new(message, "message.txt"))
+            .unwrap();
+        let error = exact('4').parse_from(message, &mark).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::StaleMark);
+
+        let word = any()
+            .filter(|c: &char| c.is_alphabetic())
+            .repeated()
+            .at_least(1);
+        let (_, mark) = word.to_slice().parse_marked("helloworld ok").unwrap();
+        let error = word.to_slice().parse_from("hi", &mark).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::StaleMark);
+        let (_, mark) = exact('a').parse_marked("ab").unwrap();
+        let error = exact('b').parse_from("é", &mark).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::StaleMark);
+    }
+
+    #[test]
+    fn test_collect_map() {
+        use std::collections::{BTreeMap, HashMap};
+
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let key = any().filter(|c: &char| c.is_ascii_lowercase());
+        let entry = key.left_bind(exact('=')).and(digit).left_bind(exact(';'));
+
+        let map = entry.repeated().at_least(0).collect::<HashMap<_, _>>();
+        assert_eq!(
+            map.parse("a=1;b=2;a=3;"),
+            Ok(HashMap::from([('a', '3'), ('b', '2')]))
+        );
+
+        let map = entry.repeated().at_least(1).collect::<BTreeMap<_, _>>();
+        let (map, _) = map.parse_prefix("b=2;a=1;").unwrap();
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            [('a', '1'), ('b', '2')]
+        );
+    }
+
+    #[test]
+    fn test_collect_std_collections() {
+        use std::collections::{BTreeSet, BinaryHeap, VecDeque};
+
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let digits = "3141";
+
+        let set = digit.repeated().at_least(1).collect::<BTreeSet<_>>();
+        assert_eq!(set.parse(digits), Ok(BTreeSet::from(['1', '3', '4'])));
+
+        let deque = digit.repeated().at_least(1).collect::<VecDeque<_>>();
+        assert_eq!(
+            deque.parse(digits),
+            Ok(VecDeque::from(['3', '1', '4', '1']))
+        );
+
+        let heap = digit.repeated().at_least(1).collect::<BinaryHeap<_>>();
+        let heap = heap.parse(digits).unwrap();
+        assert_eq!(heap.into_sorted_vec(), ['1', '1', '3', '4']);
     }
 
-    // Sanity check `exact` combinator
-    // Success case
     #[test]
-    fn test_exact_simple_string_ok() {
-        let mut input_ref = input_ref!("hello world");
+    #[cfg(feature = "smallvec")]
+    fn test_collect_smallvec() {
+        use smallvec::SmallVec;
 
-        let parser = exact("hello");
+        let digits = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<SmallVec<[char; 4]>>();
 
-        assert_eq!(parser.go(&mut input_ref), Ok("hello"));
-        assert_eq!(input_ref.offset(), "hello".len());
+        let short = digits.parse("42").unwrap();
+        assert_eq!(short.as_slice(), ['4', '2']);
+        assert!(!short.spilled());
+        assert!(digits.parse("31415").unwrap().spilled());
+    }
 
-        let parser = exact(" world");
-        assert_eq!(parser.go(&mut input_ref), Ok(" world"));
-        assert_eq!(input_ref.offset(), "hello world".len());
+    #[test]
+    #[cfg(feature = "compact_str")]
+    fn test_collect_compact() {
+        let letter = any().filter(|c: &char| c.is_alphanumeric() || *c == '_');
+        let identifier = letter.repeated().at_least(1).collect_compact();
+
+        let short = identifier.parse("snake_case_name").unwrap();
+        assert_eq!(short, "snake_case_name");
+        assert!(!short.is_heap_allocated());
+        let long = identifier.parse("a_much_longer_identifier_name").unwrap();
+        assert!(long.is_heap_allocated());
+
+        let words = exact("ab").repeated().at_least(1).collect_compact();
+        assert_eq!(words.parse("abab").unwrap(), "abab");
     }
 
-    // Sanity check for `exact` combinator
-    // error case
     #[test]
-    fn test_exact_simple_string_err() {
-        let mut input_ref = input_ref!(b"hello world" as &[u8]);
+    fn test_collect_discarded() {
+        let number = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .left_bind(exact(' '));
 
-        let parser = exact(b"hellqasd" as &[u8]);
+        let numbers = number.repeated().at_least(1).collect::<()>();
+        let (_, rest) = numbers.parse_prefix("12 3 456 end").unwrap();
+        assert_eq!(rest, "end");
+    }
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), "hell".len());
+    #[test]
+    fn test_collect_count() {
+        let indent = exact(' ').repeated().at_least(0).collect::<Count>();
+        let line = indent.left_bind(exact("item"));
+        assert_eq!(line.parse("    item").map(|count| count.get()), Ok(4));
+        assert_eq!(line.parse("item").map(|count| count.get()), Ok(0));
     }
 
-    // Sanity check for `end` combinator
-    // Success case.
     #[test]
-    fn test_end_ok() {
-        let mut input_ref = input_ref!("");
-        let parser = end();
+    fn test_collect_into() {
+        let buffer = RefCell::new(String::new());
+        let word = any()
+            .filter(|c: &char| c.is_ascii_alphabetic())
+            .repeated()
+            .at_least(1)
+            .collect_into(&buffer);
 
-        assert_eq!(parser.go(&mut input_ref), Ok(()));
-        assert_eq!(input_ref.offset(), 0);
+        let mut words = Vec::new();
+        for line in ["first", "second"] {
+            buffer.borrow_mut().clear();
+            word.parse(line).unwrap();
+            words.push(buffer.borrow().clone());
+        }
+        assert_eq!(words, ["first", "second"]);
+        assert!(buffer.borrow().capacity() >= "second".len());
+
+        buffer.borrow_mut().clear();
+        let call = word.left_bind(exact('(')).or(exact("if").map(|_| ()));
+        assert_eq!(call.parse("if"), Ok(()));
+        assert_eq!(*buffer.borrow(), "if");
     }
 
-    // Sanity check for `end` combinator
-    // Error case.
     #[test]
-    fn test_end_err() {
-        let mut input_ref = input_ref!("characters left in the input");
-        let parser = end();
+    #[cfg(feature = "bumpalo")]
+    fn test_collect_in_arena() {
+        let bump = bumpalo::Bump::new();
+        let digits = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect_in(&bump);
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), 0);
+        let first = digits.parse("123").unwrap();
+        let second = digits.parse("45").unwrap();
+        assert_eq!(
+            (first.as_slice(), second.as_slice()),
+            (&['1', '2', '3'][..], &['4', '5'][..])
+        );
+        assert!(bump.allocated_bytes() > 0);
     }
 
-    // Sanity check for `any` combinator
-    // Success case
     #[test]
-    fn test_any_ok() {
-        let mut input_ref = input_ref!("any");
-        let parser = any();
+    fn test_escaped() {
+        let normal = any().filter(|c: &char| *c != '"' && *c != '\\');
+        let escape = exact('\\').right_bind(
+            exact('n')
+                .map(|_| '\n')
+                .or(exact('"').map(|_| '"'))
+                .or(exact('\\').map(|_| '\\')),
+        );
+        let string = exact('"')
+            .right_bind(escaped(normal, escape))
+            .left_bind(exact('"'));
 
-        assert_eq!(parser.go(&mut input_ref), Ok('a'));
-        assert_eq!(input_ref.offset(), "a".len());
+        let text = string.parse(r#""plain text""#).unwrap();
+        assert!(matches!(text, Cow::Borrowed("plain text")));
 
-        assert_eq!(parser.go(&mut input_ref), Ok('n'));
-        assert_eq!(input_ref.offset(), "ab".len());
+        let text = string.parse(r#""say \"hi\"\n""#).unwrap();
+        assert!(matches!(text, Cow::Owned(_)));
+        assert_eq!(text, "say \"hi\"\n");
 
-        assert_eq!(parser.go(&mut input_ref), Ok('y'));
-        assert_eq!(input_ref.offset(), "any".len());
+        assert!(matches!(string.parse(r#""""#), Ok(Cow::Borrowed(""))));
+        let error = string.parse(r#""bad \q""#).unwrap_err();
+        assert_eq!(error.span(), 6..7);
     }
 
-    // Sanity check for `any` combinator
-    // Success case
     #[test]
-    fn test_any_err() {
-        let mut input_ref = input_ref!("");
-        let parser = any();
+    fn test_collect_capacity_hint() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), 0);
+        let digits = digit.repeated().exactly(100).collect::<Vec<_>>();
+        let input = "7".repeat(100);
+        assert!(digits.parse(input.as_str()).unwrap().capacity() >= 100);
+        // A count way past the input is a parse error, not an allocation failure.
+        let digits = digit.repeated().exactly(usize::MAX / 2).collect::<Vec<_>>();
+        assert_eq!(digits.parse("123").unwrap_err().span(), 3..3);
+
+        let digits = digit
+            .repeated()
+            .at_least(0)
+            .at_most(usize::MAX)
+            .collect::<Vec<_>>();
+        assert!(digits.parse("12").unwrap().capacity() <= 64);
     }
 
-    // Sanity check for `map` operator
     #[test]
-    fn test_map() {
-        let mut input_ref = input_ref!("123");
-        let parser = exact("123").map(|string: &str| string.parse::<u32>().ok());
+    fn test_try_collect() {
+        let byte = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .map(|digits| digits.parse::<u8>().map_err(ParseError::caused_by))
+            .left_bind(exact(',').or_not());
+        let bytes = byte.repeated().at_least(1).try_collect::<Vec<_>>();
 
-        assert_eq!(parser.go(&mut input_ref), Ok(Some(123)));
-        assert_eq!(input_ref.offset(), "123".len());
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        assert_eq!(bytes.parse("1,20,255"), Ok(vec![1, 20, 255]));
+
+        let error = bytes.parse("1,256,3").unwrap_err();
+        assert_eq!(error.code(), ErrorCode::Custom);
+        assert_eq!(error.span(), 2..6);
+        assert_eq!(
+            error.message(),
+            Some("number too large to fit in target type")
+        );
     }
 
-    // Sanity check for `bind` operator
     #[test]
-    fn test_bind() {
-        let mut input_ref = input_ref!("123456");
-        let parser = exact("123").right_bind(exact("456")).left_bind(end());
-
-        assert_eq!(parser.go(&mut input_ref), Ok("456"));
-        assert_eq!(input_ref.offset(), "123456".len());
-
-        input_ref.rewind(input_ref.start());
+    fn test_collect_with_separators() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let comma = exact(',').padded(exact(' ').or_not());
+
+        let list = digit.separated_by(comma).collect::<String>();
+        let (digits, rest) = list.parse_prefix("1, 2,3 ,").unwrap();
+        assert_eq!((digits.as_str(), rest), ("123", " ,"));
+        assert_eq!(list.parse(""), Ok(String::new()));
+
+        let list = digit
+            .separated_by(comma)
+            .allow_trailing()
+            .collect_with_separators();
+        let list = list.parse("1, 2,3 ,").unwrap();
+        assert_eq!(list.items(), ['1', '2', '3']);
+        assert_eq!(list.separators(), [(",", 1..3), (",", 4..5), (",", 6..8)]);
+        assert!(list.has_trailing());
+    }
 
-        let parser = exact("123")
-            .right_bind(exact("456"))
-            .map(|string: &str| string.parse::<u32>().ok());
+    #[test]
+    fn test_collect_map_duplicates() {
+        use std::collections::{BTreeMap, HashMap};
 
-        assert_eq!(parser.go(&mut input_ref), Ok(Some(456)));
-        assert_eq!(input_ref.offset(), "123456".len());
+        let key = any().filter(|c: &char| c.is_ascii_lowercase());
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let entry = key.left_bind(exact('=')).and(digit).left_bind(exact(';'));
+        let config = "a=1;b=2;a=3;";
 
-        input_ref.rewind(input_ref.start());
+        let map = entry
+            .repeated()
+            .at_least(0)
+            .collect_map::<HashMap<_, _>>(DuplicateKeys::Overwrite);
+        assert_eq!(
+            map.parse(config),
+            Ok(HashMap::from([('a', '3'), ('b', '2')]))
+        );
 
-        let parser = exact("123")
-            .left_bind(exact("456"))
-            .map(|string: &str| string.parse::<u32>().ok());
+        let map = entry
+            .repeated()
+            .at_least(0)
+            .collect_map::<BTreeMap<_, _>>(DuplicateKeys::KeepFirst);
+        assert_eq!(
+            map.parse(config),
+            Ok(BTreeMap::from([('a', '1'), ('b', '2')]))
+        );
 
-        assert_eq!(parser.go(&mut input_ref), Ok(Some(123)));
-        assert_eq!(input_ref.offset(), "123456".len());
+        let map = entry
+            .repeated()
+            .at_least(0)
+            .collect_map::<BTreeMap<_, _>>(DuplicateKeys::Fail);
+        let error = map.parse(config).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::DuplicateKey);
+        assert_eq!(error.span(), 8..12);
     }
 
     #[test]
-    fn test_bind_with_separated_value() {
-        let mut input_ref = input_ref!("123-456");
-        let parser = exact("123")
-            .left_bind(exact("-"))
-            .right_bind(exact("456"))
-            .map(|string: &str| string.parse::<u32>().ok());
-
-        assert_eq!(parser.go(&mut input_ref), Ok(Some(456)));
+    fn test_reductions() {
+        let digit = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .map(|c| c.to_digit(10).unwrap());
 
-        input_ref.rewind(input_ref.start());
+        let number = digit
+            .repeated()
+            .at_least(1)
+            .fold_collect(0, |n, d| n * 10 + d);
+        assert_eq!(number.parse("1024"), Ok(1024));
 
-        let parser = exact("123")
-            .left_bind(exact("-"))
-            .left_bind(exact("456"))
-            .map(|string: &str| string.parse::<u32>().ok());
+        let word = any()
+            .filter(|c: &char| c.is_ascii_alphabetic())
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+        let last = word
+            .left_bind(exact(' ').or_not())
+            .repeated()
+            .at_least(0)
+            .last();
+        assert_eq!(last.parse("one two three"), Ok(Some(String::from("three"))));
+        assert_eq!(last.parse(""), Ok(None));
 
-        assert_eq!(parser.go(&mut input_ref), Ok(Some(123)));
-        assert_eq!(input_ref.offset(), "123-456".len());
+        let max = digit
+            .repeated()
+            .at_least(0)
+            .at_most(3)
+            .max_by(|a, b| a.cmp(b));
+        assert_eq!(max.parse("3829"), Ok(Some(8)));
     }
 
-    // Sanity check for `And` operator
     #[test]
-    fn test_and() {
-        let mut input_ref = input_ref!("https://");
-        let https = exact("https");
-        let slashes = exact("//");
+    fn test_for_each() {
+        use std::cell::Cell;
 
-        let parser = https.left_bind(exact(":")).and(slashes);
+        let total = Cell::new(0);
+        let record = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .map(|c| c.to_digit(10).unwrap())
+            .left_bind(exact('\n'));
+        let records = record
+            .repeated()
+            .at_least(0)
+            .for_each(|n| total.set(total.get() + n));
 
-        assert_eq!(parser.go(&mut input_ref), Ok(("https", "//")));
+        assert_eq!(records.parse("1\n2\n3\n"), Ok(()));
+        assert_eq!(total.get(), 6);
+    }
+
+    #[test]
+    fn test_parse_iter() {
+        let number = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .left_bind(exact(' ').or_not());
 
-        input_ref.rewind(input_ref.start());
+        let numbers: Vec<_> = number.parse_iter("1 22 333").collect();
+        assert_eq!(numbers, [Ok("1".into()), Ok("22".into()), Ok("333".into())]);
 
-        let parser = exact("https")
-            .left_bind(exact("er"))
-            .right_bind(exact("//"));
+        let mut numbers = number.parse_iter("1 x 3");
+        assert_eq!(numbers.next(), Some(Ok(String::from("1"))));
+        assert_eq!(numbers.next().unwrap().unwrap_err().span(), 2..3);
+        assert_eq!(numbers.next(), None);
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), "https".len());
+        assert_eq!(number.parse_iter("").next(), None);
+        assert_eq!(exact("").parse_iter("a").count(), 1);
     }
 
-    // Sanity check for `Repeated` operator
     #[test]
-    fn test_repeated() {
-        let mut input_ref = input_ref!("hhhhhhoooooo");
-
-        let parser = exact('h')
+    fn test_repeated_to_slice() {
+        let identifier = any()
+            .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_')
             .repeated()
-            .at_least(3)
-            .at_most(4)
-            .collect::<Vec<_>>();
+            .at_least(1)
+            .to_slice();
+        assert_eq!(
+            identifier.parse_prefix("max_len = 3"),
+            Ok(("max_len", " = 3"))
+        );
 
-        assert_eq!(parser.go(&mut input_ref), Ok(vec!["h"; 4]));
-        assert_eq!(input_ref.offset(), 4);
+        let byte_pairs = any().and(any()).repeated().exactly(2).to_slice();
+        assert_eq!(
+            byte_pairs.parse(&b"\x01\x02\x03\x04\x05"[..]),
+            Ok(&b"\x01\x02\x03\x04"[..])
+        );
+    }
 
-        input_ref.rewind(input_ref.start());
+    #[test]
+    fn test_collect_array() {
+        let octet = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+            .map(|digits| digits.parse::<u8>().unwrap());
+        let address = octet
+            .left_bind(exact('.').or_not())
+            .repeated()
+            .exactly_n::<4>()
+            .collect_array();
 
-        let parser = exact('h').repeated().at_least(1).collect::<Vec<_>>();
-        assert_eq!(parser.go(&mut input_ref), Ok(vec!["h"; 6]));
-        assert_eq!(input_ref.offset(), 6);
+        let [a, b, c, d] = address.parse("192.168.0.1").unwrap();
+        assert_eq!((a, b, c, d), (192, 168, 0, 1));
+        assert!(address.parse("10.0.1").is_err());
+    }
 
-        input_ref.rewind(input_ref.start());
+    #[test]
+    fn test_collect_parsed() {
+        use std::collections::HashMap;
+
+        // Header names are case insensitive.
+        #[derive(Debug, PartialEq)]
+        struct Headers(HashMap<String, String>);
+
+        impl FromParsedIter<(String, String), Range<usize>> for Headers {
+            fn from_parsed_iter(
+                items: impl Iterator<Item = ((String, String), Range<usize>)>,
+            ) -> Result<Self, CustomError> {
+                let mut headers = HashMap::new();
+                for ((name, value), _) in items {
+                    if headers.insert(name.to_lowercase(), value).is_some() {
+                        return Err(CustomError::new(format!("header {} given twice", name)));
+                    }
+                }
+                Ok(Headers(headers))
+            }
+        }
 
-        let parser = exact('h')
+        let word = any()
+            .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '-')
             .repeated()
             .at_least(1)
-            .collect::<Vec<_>>()
-            .and(exact('o').repeated().at_least(1).collect::<Vec<_>>())
-            .left_bind(end());
+            .collect::<String>();
+        let header = word
+            .clone()
+            .left_bind(exact(": "))
+            .and(word)
+            .left_bind(exact('\n'));
+        let headers = header.repeated().at_least(0).collect_parsed::<Headers>();
+
+        let expected = HashMap::from([
+            (String::from("host"), String::from("example")),
+            (String::from("accept"), String::from("text")),
+        ]);
+        assert_eq!(
+            headers.parse("Host: example\nAccept: text\n"),
+            Ok(Headers(expected))
+        );
 
-        assert_eq!(parser.go(&mut input_ref), Ok((vec!["h"; 6], vec!["o"; 6])));
+        let error = headers.parse("Host: a\nAccept: b\nHOST: c\n").unwrap_err();
+        assert_eq!(error.message(), Some("header HOST given twice"));
+        assert_eq!(error.span(), 18..26);
+
+        // Takes the first item only, or rejects it.
+        #[derive(Debug, PartialEq)]
+        struct First(char);
+
+        impl FromParsedIter<char, Range<usize>> for First {
+            fn from_parsed_iter(
+                mut items: impl Iterator<Item = (char, Range<usize>)>,
+            ) -> Result<Self, CustomError> {
+                match items.next() {
+                    Some(('!', _)) => Err(CustomError::new("no first item")),
+                    Some((first, _)) => Ok(First(first)),
+                    None => Err(CustomError::new("no first item")),
+                }
+            }
+        }
+
+        let letters = any()
+            .filter(|c: &char| !c.is_ascii_digit())
+            .repeated()
+            .at_least(3)
+            .collect_parsed::<First>()
+            .and(any().repeated().at_least(0).to_slice());
+        assert_eq!(letters.parse("abc1"), Ok((First('a'), "1")));
+        assert_eq!(letters.parse("ab1c").unwrap_err().span(), 2..3);
+        let error = letters.parse("!b1c").unwrap_err();
+        assert_eq!((error.message(), error.span()), (None, 2..3));
+        assert_eq!(
+            letters.parse("!bc").unwrap_err().message(),
+            Some("no first item")
+        );
     }
 
     #[test]
-    fn test_repeated_err() {
-        let mut input_ref = input_ref!("hhhhhooooo");
-        let parser = exact('h')
+    fn test_container_try_push() {
+        // At most 3 distinct flags.
+        #[derive(Debug, Default, PartialEq)]
+        struct Flags(Vec<char>);
+
+        impl Container<char> for Flags {
+            fn push(&mut self, item: char) {
+                self.0.push(item);
+            }
+
+            fn try_push(&mut self, item: char) -> Result<(), CustomError> {
+                if self.0.contains(&item) {
+                    Err(CustomError::new(format!("flag {} given twice", item)))
+                } else if self.0.len() == 3 {
+                    Err(CustomError::new("too many flags"))
+                } else {
+                    self.0.push(item);
+                    Ok(())
+                }
+            }
+        }
+
+        let flags = any()
+            .filter(|c: &char| c.is_ascii_alphabetic())
             .repeated()
-            .at_least(100)
-            .at_most(400)
-            .collect::<Vec<_>>();
+            .at_least(0)
+            .collect::<Flags>();
 
+        assert_eq!(flags.parse("xvf"), Ok(Flags(vec!['x', 'v', 'f'])));
+        let error = flags.parse("xvx").unwrap_err();
         assert_eq!(
-            parser.go(&mut input_ref),
-            Err::<Vec<_>, _>(ParseError::SyntaxError)
+            (error.message(), error.span()),
+            (Some("flag x given twice"), 2..3)
+        );
+        let error = flags.parse("xvfz").unwrap_err();
+        assert_eq!(
+            (error.message(), error.span()),
+            (Some("too many flags"), 3..4)
         );
     }
 
     #[test]
-    fn test_filter_ok() {
-        let mut input_ref = input_ref!("132letters");
+    fn test_exact_fast_path() {
+        let keyword = exact("fn").left_bind(exact(' '));
+        let (result, stats) = keyword.parse_with_stats("fn main");
+        assert_eq!(result, Ok("fn"));
+        assert_eq!((stats.tokens(), stats.advanced()), (3, 3));
 
-        let digits = any()
+        let error = exact("falsy").parse("false").unwrap_err();
+        assert_eq!(error.span(), 4..5);
+
+        let magic = exact(&b"\x7fELF"[..]);
+        assert_eq!(
+            magic.parse_prefix(&b"\x7fELF\x02"[..]),
+            Ok((&b"\x7fELF"[..], &b"\x02"[..]))
+        );
+        assert_eq!(exact("é").parse(Sourced::new("été", "a.txt")), Ok("é"));
+    }
+
+    #[test]
+    fn test_one_of_trie() {
+        let keywords: Vec<String> = (0..300).map(|n| format!("kw{n:03}")).collect();
+        let keyword = one_of_index(keywords.iter().map(String::as_str).collect());
+        assert_eq!(keyword.parse("kw299"), Ok(299));
+        let (result, stats) = keyword.parse_with_stats("kw001");
+        assert_eq!((result, stats.rewinds()), (Ok(1), 0));
+
+        // The first sequence that matches is picked, not the longest one.
+        assert_eq!(
+            one_of(vec!["for", "foreach"]).parse_prefix("foreach"),
+            Ok(("for", "each"))
+        );
+        assert_eq!(
+            one_of(vec!["foreach", "for"]).parse_prefix("foreach"),
+            Ok(("foreach", ""))
+        );
+
+        let report = one_of(vec!["false", "fn", "for"]).parse("fun").unwrap_err();
+        assert_eq!(report.span(), 1..2);
+        let expected = [
+            Expected::Token('a'),
+            Expected::Token('n'),
+            Expected::Token('o'),
+        ];
+        assert_eq!(report.expected(), &expected);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let number = any()
             .filter(|c: &char| c.is_ascii_digit())
             .repeated()
             .at_least(1)
-            .collect::<Vec<_>>();
+            .to_slice();
+        let value = dispatch()
+            .on(['t'], exact("true"))
+            .on(['t'], exact("teapot"))
+            .on(['f'], exact("false"))
+            .on('0'..='9', number);
+
+        assert_eq!(value.parse("false"), Ok("false"));
+        assert_eq!(value.parse("42"), Ok("42"));
+        let (result, stats) = value.parse_with_stats("teapot");
+        assert_eq!((result, stats.rewinds()), (Ok("teapot"), 1));
+
+        let report = value.parse("null").unwrap_err();
+        assert_eq!(report.span(), 0..1);
+        assert_eq!(report.expected().len(), 12);
+        assert_eq!(value.or(exact("null")).parse("null"), Ok("null"));
+    }
 
-        let letters = any()
-            .filter(|c: &char| c.is_ascii_alphabetic())
+    #[test]
+    fn test_packrat() {
+        let calls = Cell::new(0);
+        let digits = any()
+            .filter(|c: &char| c.is_ascii_digit())
             .repeated()
             .at_least(1)
-            .collect::<Vec<_>>();
-
-        assert_eq!(digits.go(&mut input_ref), Ok(vec!['1', '3', '2']));
-        assert_eq!(input_ref.offset(), "132".len());
+            .to_slice();
+        let term = digits.map(|digits| {
+            calls.set(calls.get() + 1);
+            digits
+        });
+        let term = term.rule();
+        let expr = (term.clone().left_bind(exact('+')))
+            .or(term.clone().left_bind(exact('-')))
+            .or(term.clone().left_bind(exact('*')))
+            .or(term);
+
+        assert_eq!(expr.parse("123"), Ok("123"));
+        assert_eq!(calls.take(), 4);
+
+        let config = ParseConfig::default().packrat(16);
+        let mut input_ref = InputRef::with_config(&"123", config);
+        assert_eq!(expr.go(&mut input_ref), Ok("123"));
+        assert_eq!((input_ref.stats().memo_hits(), calls.take()), (3, 1));
+
+        // The failures of the rule are reported the same when its result is reused.
+        let report = expr.parse_with_config("x", config).unwrap_err();
+        assert_eq!(report[0].span(), 0..1);
         assert_eq!(
-            letters.go(&mut input_ref),
-            Ok(vec!['l', 'e', 't', 't', 'e', 'r', 's'])
+            report[0].expected(),
+            expr.parse("x").unwrap_err().expected()
         );
+    }
+
+    #[test]
+    fn test_left_recursive() {
+        fn number<'input>() -> impl Parser<'input, &'input str, i64> {
+            any()
+                .filter(|c: &char| c.is_ascii_digit())
+                .repeated()
+                .at_least(1)
+                .to_slice()
+                .map(|digits: &str| digits.parse().unwrap())
+        }
 
-        input_ref.rewind(input_ref.start());
+        // expr := expr '-' number | number
+        struct Expr;
 
-        let parser = digits.left_bind::<_, Vec<_>>(letters).left_bind(end());
-        assert_eq!(parser.go(&mut input_ref), Ok(vec!['1', '3', '2']));
+        impl<'input> Parser<'input, &'input str, i64> for Expr {
+            fn go(&self, input_ref: &mut InputRef<'input, '_, &'input str>) -> ParseResult<i64> {
+                let difference = Expr
+                    .left_bind(exact('-'))
+                    .and(number())
+                    .map(|(lhs, rhs)| lhs - rhs);
+                difference.or(number()).left_recursive("expr").go(input_ref)
+            }
+        }
+
+        // Subtraction is left associative.
+        assert_eq!(Expr.parse("10-3-2"), Ok(5));
+        assert_eq!(Expr.left_bind(end()).parse("7"), Ok(7));
+        let error = Expr.left_bind(end()).parse("10-3-").unwrap_err();
+        assert_eq!(error.span(), 5..5);
     }
 
     #[test]
-    fn test_floating_point_number() {
-        let mut input_ref = input_ref!("-123.234");
-
-        let digit_seq = any()
+    fn test_check() {
+        let mapped = Cell::new(0);
+        let number = any()
             .filter(|c: &char| c.is_ascii_digit())
             .repeated()
             .at_least(1)
-            .collect::<Vec<_>>();
+            .collect::<String>()
+            .map(|digits| {
+                mapped.set(mapped.get() + 1);
+                digits
+            });
+        let list = number
+            .left_bind(exact(',').or_not())
+            .repeated()
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .delimited_by(exact('['), exact(']'))
+            .left_bind(end());
 
-        let parser = exact('-')
-            .right_bind(digit_seq.clone())
-            .left_bind(exact('.'))
-            .and(digit_seq);
+        assert_eq!(list.check("[1,22,333]"), Ok(()));
+        assert_eq!(mapped.get(), 0);
+        let (checked, parsed) = (list.check("[1,2"), list.parse("[1,2"));
+        assert_eq!(checked.unwrap_err(), parsed.unwrap_err());
+        assert_eq!(mapped.take(), 2);
+    }
 
+    #[test]
+    fn test_byte_scans() {
+        let spaces = take_while_in(b" \t\n");
+        assert_eq!(spaces.parse_prefix(" \t\nx"), Ok((" \t\n", "x")));
+        assert_eq!(spaces.parse_prefix("x"), Ok(("", "x")));
+        for len in [7, 8, 9, 21] {
+            let source = format!("{}\u{a0}", " \t\n".repeat(len).split_at(len).0);
+            let (run, rest) = spaces.parse_prefix(&source).unwrap();
+            assert_eq!((run.len(), rest), (len, "\u{a0}"));
+        }
+
+        let content = take_until(b"\"\\");
+        let (result, stats) = content.parse_with_stats("héllo\" world");
+        assert_eq!(result, Ok("héllo"));
+        assert_eq!((stats.tokens(), stats.advanced()), (5, 6));
         assert_eq!(
-            parser.go(&mut input_ref),
-            Ok((vec!['1', '2', '3'], vec!['2', '3', '4']))
+            take_until(b"\r\n").parse(&b"GET /\r\n"[..]),
+            Ok(&b"GET /"[..])
         );
-        assert_eq!(input_ref.offset(), "-123.234".len());
-
-        input_ref.rewind(input_ref.start());
-
-        let digit_seq = any()
-            .filter(|c: &char| c.is_ascii_digit())
-            .repeated()
-            .exactly(3)
-            .collect::<String>();
-
-        let parser = exact('-')
-            .right_bind(digit_seq.clone())
-            .left_bind(exact('.'))
-            .and(digit_seq);
-
         assert_eq!(
-            parser.go(&mut input_ref),
-            Ok((String::from("123"), String::from("234")))
+            take_until(b",").parse(Utf8Input::new(b"ab\xffc,")),
+            Ok("ab")
         );
-        assert_eq!(input_ref.offset(), "-123.234".len());
-
-        input_ref.rewind(input_ref.start());
-
-        let digit_seq = any()
-            .filter(|c: &char| c.is_ascii_digit())
-            .repeated()
-            .at_least(2)
-            .at_most(3)
-            .collect::<String>();
-
-        let parser = exact('-')
-            .right_bind(digit_seq.clone())
-            .left_bind(exact('.'))
-            .and(digit_seq);
 
+        let config = ParseConfig::default().partial(true);
+        let mut input_ref = InputRef::with_config(&"abc", config);
         assert_eq!(
-            parser.go(&mut input_ref),
-            Ok((String::from("123"), String::from("234")))
+            content.go(&mut input_ref),
+            Err(ParseError::Incomplete { needed: Some(1) })
         );
-        assert_eq!(input_ref.offset(), "-123.234".len());
     }
 
     #[test]
-    fn identifier() {
-        use std::collections::HashSet;
+    fn test_repeated_rewinds_failed_attempt() {
+        let pairs = exact("ab").repeated().at_least(0);
+        assert_eq!(pairs.to_slice().parse_prefix("abac"), Ok(("ab", "ac")));
+        let collected = pairs.collect::<Vec<_>>().left_bind(exact("ac"));
+        assert_eq!(collected.parse("ababac"), Ok(vec!["ab", "ab"]));
+
+        struct Items(usize);
+
+        impl<T> FromParsedIter<T, Range<usize>> for Items {
+            fn from_parsed_iter(
+                items: impl Iterator<Item = (T, Range<usize>)>,
+            ) -> Result<Self, CustomError> {
+                Ok(Items(items.count()))
+            }
+        }
 
-        let mut input_ref = input_ref!("ident_ifier");
-        let parser = any()
-            .filter(|c: &char| c.is_ascii())
-            .repeated()
-            .at_least(1)
-            .collect::<String>();
+        let parsed = pairs.collect_parsed::<Items>().left_bind(exact("ac"));
+        assert_eq!(parsed.parse("abac").map(|items| items.0), Ok(1));
+    }
 
-        assert_eq!(parser.go(&mut input_ref), Ok(String::from("ident_ifier")));
-        assert_eq!(input_ref.offset(), "ident_ifier".len());
+    #[test]
+    fn test_choice() {
+        let keyword = exact("if")
+            .or(exact("in"))
+            .or(exact("for"))
+            .or(exact("while"));
+        assert_eq!(keyword.parse("for"), Ok("for"));
+        assert_eq!(keyword.parse("in"), Ok("in"));
+
+        let keyword = exact("interface")
+            .or(exact("internet"))
+            .or(exact("internal"));
+        let (result, stats) = keyword.parse_with_stats("internal");
+        assert_eq!(result, Ok("internal"));
+        assert_eq!((stats.rewinds(), stats.backtracked()), (2, 11));
+
+        let keyword = choice((exact("if"), exact("in"), exact("for")));
+        let error = keyword.parse("ix").unwrap_err();
+        assert_eq!(error.span(), 1..2);
+        assert!(keyword.check("if").is_ok());
+    }
 
-        input_ref.rewind(input_ref.start());
+    #[test]
+    fn test_optimize() {
+        let keyword = exact("if")
+            .or(exact("in"))
+            .or(exact("for"))
+            .or(exact("while"));
+        let optimized: Choice<(_, _, _, _)> = keyword.optimize();
+        for input in ["for", "while", "in", "wile", ""] {
+            assert_eq!(optimized.parse(input), keyword.parse(input));
+        }
 
-        let parser = any()
-            .filter(|c: &char| c.is_ascii())
-            .repeated()
-            .at_least(1)
-            .collect::<HashSet<_>>();
+        let main = exact("fn").right_bind(exact(" main"));
+        let optimized: ExactThen<_, char> = main.optimize();
+        for input in ["fn main", "fn mai", "fx main", "fn"] {
+            assert_eq!(optimized.parse(input), main.parse(input));
+        }
+
+        let number = any().filter(|c: &char| c.is_ascii_digit());
+        let number = number.repeated().at_least(2).to_slice();
+        let optimized: TakeWhile<_, char, _> = number.optimize();
+        for input in ["123", "1", "12a", ""] {
+            assert_eq!(optimized.parse(input), number.parse(input));
+        }
 
+        // Parsers are optimized wherever they are in the grammar.
+        let call = (exact("f").or(exact("g")).or(exact("h")))
+            .and(exact("(").right_bind(exact(")")))
+            .map(|(name, _)| name);
+        let optimized: Map<_, And<_, Choice<(_, _, _)>, _, ExactThen<_, _>, _>, _, _, _> =
+            call.optimize();
+        assert_eq!(optimized.parse("g()"), Ok("g"));
         assert_eq!(
-            parser.go(&mut input_ref),
-            Ok(HashSet::from([
-                'i', 'd', 'e', 'n', 't', '_', 'i', 'f', 'i', 'e', 'r'
-            ]))
+            optimized.parse("g(").unwrap_err(),
+            call.parse("g(").unwrap_err()
         );
     }
 
     #[test]
-    fn test_one_of() {
-        let mut input_ref = input_ref!("12345");
-
-        let parser = one_of(vec!['1', '2', '3']);
+    fn test_compile() {
+        let digit = choice((exact('0'), exact('1'), exact('2'), exact('3')));
+        let number = digit.repeated().at_least(1).to_slice();
+        let item = number.padded(exact(' ').repeated().at_least(0).to_slice());
+        let items = exact(',').right_bind(item).repeated().at_least(0);
+        let tuple = (item.and(items.collect::<Vec<_>>()))
+            .delimited_by(exact('('), exact(')'))
+            .left_bind(end());
 
-        assert_eq!(parser.go(&mut input_ref), Ok("1"));
-        assert_eq!(input_ref.offset(), 1);
+        let program = tuple.compile();
+        assert_eq!(program.parse("(12, 3 ,0)"), Ok("(12, 3 ,0)"));
+        for input in ["(12, 4)", "(12, 3", "(1)2"] {
+            let error = program.parse(input).unwrap_err();
+            assert_eq!(error.span(), tuple.parse(input).unwrap_err().span());
+        }
 
-        input_ref.rewind(input_ref.start());
+        let program = digit.repeated().exactly(2).to_slice().compile();
+        assert_eq!(program.parse("12"), Ok("12"));
+        assert!(program.check("1").is_err());
+
+        // Repetitions are counted rather than unrolled.
+        let program = digit.repeated().exactly(100).to_slice().compile();
+        assert_eq!(program.len(), 12);
+        let digits = "3".repeat(100);
+        assert!(program.check(&digits).is_ok());
+        assert!(program.check(&digits[1..]).is_err());
+        let program = (exact('1').and(exact('2')).repeated().at_least(2).to_slice())
+            .and(exact('1'))
+            .compile();
+        assert_eq!(program.parse("12121"), Ok("12121"));
+        assert!(program.check("121").is_err());
+    }
 
-        let parser = one_of(vec!["124", "1235", "122", "12345"]).left_bind(end());
+    #[test]
+    fn test_grammar() {
+        use std::sync::OnceLock;
+
+        fn grammar() -> &'static Grammar<'static, Arc<str>, (SharedSlice, SharedSlice)> {
+            static GRAMMAR: OnceLock<Grammar<'static, Arc<str>, (SharedSlice, SharedSlice)>> =
+                OnceLock::new();
+            GRAMMAR.get_or_init(|| {
+                let word = any().filter(|c: &char| c.is_alphanumeric());
+                let word = word.repeated().at_least(1).to_slice();
+                word.clone()
+                    .left_bind(exact(" = "))
+                    .and(word)
+                    .into_grammar()
+            })
+        }
 
-        assert_eq!(parser.go(&mut input_ref), Ok("12345"));
-        assert_eq!(input_ref.offset(), "12345".len());
+        let threads: Vec<_> = (0..4)
+            .map(|n| {
+                std::thread::spawn(move || {
+                    let source: Arc<str> = Arc::from(format!("key{n} = value"));
+                    let (key, value) = grammar().parse(Arc::clone(&source)).unwrap();
+                    (key.of(&source).to_owned(), value.of(&source).to_owned())
+                })
+            })
+            .collect();
+        for (n, thread) in threads.into_iter().enumerate() {
+            assert_eq!(
+                thread.join().unwrap(),
+                (format!("key{n}"), "value".to_owned())
+            );
+        }
 
-        input_ref.rewind(input_ref.start());
+        let grammar = grammar().clone();
+        assert!(grammar.parse(Arc::from("key =")).is_err());
+    }
 
-        let parser = one_of(vec!["124", "1235", "122"]);
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parse_records_parallel() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let number = digit.repeated().at_least(1).to_slice();
+        let record = number.left_bind(exact(',')).and(number).left_bind(end());
+
+        let input: String = (0..1000).map(|n| format!("{n},{}\n", n * 2)).collect();
+        let results = parse_records_parallel(input.as_str(), Delimited(b'\n'), &record);
+        assert_eq!(results.len(), 1000);
+        assert_eq!(results[999], Ok(("999", "1998")));
+
+        let results = parse_records_parallel("1,2\n3;4\n5,6", Delimited(b'\n'), &record);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().span(), 5..6);
+
+        let input = b"\0\0\0\x03abc\0\0\0\x01d";
+        let word = any().repeated().at_least(1).to_slice();
+        let results = parse_records_parallel(&input[..], LengthPrefixed, &word);
+        assert_eq!(results, [Ok(&b"abc"[..]), Ok(&b"d"[..])]);
+        // Trailing bytes too few for a length are a record of their own.
+        let input = b"\0\0\0\x03abc\0\0";
+        let results = parse_records_parallel(&input[..], LengthPrefixed, &exact(&b"abc"[..]));
+        assert_eq!(results[0], Ok(&b"abc"[..]));
+        assert_eq!(results[1].as_ref().unwrap_err().span(), 7..8);
+    }
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), input_ref.start());
+    // Counts the allocations of each thread, so that the parses meant not to allocate (see
+    // `ParseConfig::zero_alloc`) are caught if they ever do.
+    struct CountingAllocator;
 
-        input_ref.rewind(input_ref.start());
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
 
-        let parser = one_of(vec!["124", "1235", "122"]);
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), input_ref.start());
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
     }
 
-    #[test]
-    fn test_padded_by() {
-        let mut input_ref = input_ref!(r#" { "key1": "value1", "key2": "value2", } "#);
-        assert_eq!(input_ref.offset(), 0);
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
 
-        let white_space = any()
-            .filter(|c: &char| c == &' ')
-            .repeated()
-            .at_least(0)
-            .collect::<String>();
+    fn allocations<O>(f: impl FnOnce() -> O) -> (O, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let out = f();
+        (out, ALLOCATIONS.with(Cell::get) - before)
+    }
 
-        let left_brace = exact('{').padded(white_space.clone());
-        let right_brace = exact('}').padded(white_space.clone());
-        let column = exact(':').padded(white_space.clone());
-        let comma = exact(',').padded(white_space.clone());
+    #[test]
+    fn test_zero_alloc() {
+        let config = ParseConfig::default().zero_alloc(true);
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let sign = exact('-').or(exact('+')).or_not();
+        let number = sign.and(digit.repeated().at_least(1).to_slice());
+        let count = (number.padded(take_while_in(b" ")).repeated())
+            .at_least(1)
+            .fold_collect(0, |count, _| count + 1);
+        let pair = (digit.repeated().exactly_n::<2>().collect_array())
+            .delimited_by(exact('('), exact(')'))
+            .labelled("pair");
+        let grammar = choice((pair.map(|_| 0), count)).left_bind(end());
+
+        let (result, count) = allocations(|| grammar.parse_with_config("12 -3 +4", config));
+        assert_eq!((result, count), (Ok(3), 0));
+        let (result, count) = allocations(|| grammar.parse_with_config("(42)", config));
+        assert_eq!((result, count), (Ok(0), 0));
+
+        // Merging what the alternatives expected allocates.
+        let (result, count) = allocations(|| grammar.parse("12 -3 +4"));
+        assert_eq!(result, Ok(3));
+        assert!(count > 0);
+    }
 
-        let string = exact('"')
-            .right_bind(
-                any()
-                    .filter(|c: &char| c.is_ascii_alphanumeric())
-                    .repeated()
-                    .at_least(1)
-                    .collect::<String>(),
-            )
-            .left_bind(exact('"'));
+    #[test]
+    fn test_scan_filtered_tokens() {
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let digits = digit.repeated().at_least(2).to_slice();
 
-        let kvp = string.clone().left_bind(column).and(string);
+        // The run is scanned at once, only the attempt ending it is rewound.
+        let (result, stats) = digits.left_bind(exact(';')).parse_with_stats("12345;");
+        assert_eq!(result, Ok("12345"));
+        assert_eq!((stats.tokens(), stats.rewinds()), (7, 1));
 
-        let json_file = left_brace
-            .clone()
-            .right_bind(kvp.clone())
-            .left_bind(comma.clone())
-            .and(kvp.clone())
-            .left_bind(comma)
-            .left_bind(right_brace);
+        let error = digits.left_bind(exact(';')).parse("123x").unwrap_err();
+        assert_eq!(
+            (error.span(), error.expected()),
+            (3..4, &[Expected::Token(';')][..])
+        );
+        assert_eq!(digits.parse("1x").unwrap_err().span(), 1..2);
+        assert!(digits.check("12").is_ok());
 
-        let kvp1 = (String::from("key1"), String::from("value1"));
-        let kvp2 = (String::from("key2"), String::from("value2"));
+        let at_most = digit.repeated().at_least(1).at_most(3).to_slice();
+        assert_eq!(at_most.parse_prefix("12345"), Ok(("123", "45")));
+    }
 
-        assert_eq!(json_file.go(&mut input_ref), Ok((kvp1, kvp2)));
+    #[test]
+    fn test_boxed() {
+        // Parsers of different types behind the same one.
+        let words: Vec<Boxed<&str, &str>> = vec![
+            exact("let").boxed(),
+            exact('x').repeated().at_least(1).to_slice().boxed(),
+            one_of(vec!["fn", "if"]).boxed(),
+        ];
+        let word = words[0].clone().or(words[1].clone()).or(words[2].clone());
+        assert_eq!(word.parse("xxx"), Ok("xxx"));
+        assert_eq!(word.parse("if"), Ok("if"));
+        assert!(word.check("let").is_ok());
+
+        let digits = any().filter(|c: &char| c.is_ascii_digit()).boxed();
+        let number = digits.repeated().at_least(1).to_slice();
+        assert_eq!(number.parse_prefix("123;"), Ok(("123", ";")));
     }
 
     #[test]
-    fn test_string() {
-        let mut input_ref = input_ref!(r#"    "       string"   "#);
-        let white_space = any()
-            .filter(|c: &char| c == &' ')
-            .repeated()
-            .at_least(0)
-            .collect::<String>();
+    fn test_parse_with_profile() {
+        let keyword = exact("interface").or(exact("internal")).labelled("keyword");
+        let statement = keyword.left_bind(exact(';')).labelled("statement");
+        let statements = statement.repeated().at_least(1).to_slice();
+
+        let (result, profile) = statements.parse_with_profile("internal;interface;internal;");
+        assert_eq!(result, Ok("internal;interface;internal;"));
+        assert_eq!(profile.stats().rewinds(), 2);
+        let keyword = profile.rule("keyword").unwrap();
+        assert_eq!(
+            (keyword.calls(), keyword.rewinds(), keyword.backtracked()),
+            (4, 2, 10)
+        );
+        let statement = profile.rule("statement").unwrap();
+        assert_eq!((statement.calls(), statement.rewinds()), (4, 0));
+        assert_eq!(profile.rules()[0].name(), "statement");
+        assert!(profile.tokens_per_sec() > 0.0);
+
+        let term = exact("ab").rule();
+        let expr = term.clone().left_bind(exact('+')).or(term);
+        let (result, profile) =
+            expr.parse_with_profile_config("ab", ParseConfig::default().packrat(4));
+        assert_eq!(result, Ok("ab"));
+        let stats = profile.stats();
+        assert_eq!((stats.memo_hits(), stats.memo_misses()), (1, 1));
+    }
 
-        let string = exact('"').padded(white_space).right_bind(
-            any()
-                .filter(|c: &char| c.is_ascii_alphabetic())
-                .repeated()
-                .at_least(1)
-                .collect::<String>(),
+    #[test]
+    fn test_rules_out() {
+        let keyword = choice((
+            exact("if").labelled("if"),
+            exact("for").labelled("for"),
+            exact("while").labelled("while"),
+        ));
+        let (result, profile) = keyword.parse_with_profile("while");
+        assert_eq!(result, Ok("while"));
+        assert_eq!(profile.rule("if").map(|rule| rule.calls()), None);
+        assert_eq!(profile.rule("while").unwrap().calls(), 1);
+
+        let report = keyword.parse("do").unwrap_err();
+        assert_eq!(report.span(), 0..1);
+        assert_eq!(
+            report.expected(),
+            &[
+                Expected::Token('i'),
+                Expected::Token('f'),
+                Expected::Token('w')
+            ]
         );
 
-        assert_eq!(string.go(&mut input_ref), Ok(String::from("string")));
+        let items = exact("ab").repeated().at_least(1).to_slice().or_not();
+        let (result, stats) = items.parse_with_stats("ababx");
+        assert_eq!(result, Ok(Some("abab")));
+        assert_eq!(stats.rewinds(), 0);
+        assert_eq!(items.parse("x"), Ok(None));
     }
 
     #[test]
-    fn test_or() {
-        let mut input_ref = input_ref!("http://localhost");
+    fn test_exact_bytes() {
+        let signature = exact(b"\x89PNG\r\n\x1a\n");
+        let png: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(signature.parse(png), Ok(&png[..8]));
 
-        let parser = exact("https").or(exact("http"));
-        assert_eq!(parser.go(&mut input_ref), Ok("http"));
+        let report = signature.parse(b"\x89PNG\n\x1a\n" as &[u8]).unwrap_err();
+        assert_eq!(report.span(), 4..5);
+        assert_eq!(report.expected(), &[Expected::Token(b'\r')]);
+
+        let input = b"\x89PN" as &[u8];
+        let mut input_ref = InputRef::with_config(&input, ParseConfig::default().partial(true));
+        assert_eq!(
+            signature.go(&mut input_ref),
+            Err(ParseError::Incomplete { needed: Some(5) })
+        );
+    }
 
-        input_ref.rewind(input_ref.start());
+    #[test]
+    fn test_const_primitives() {
+        type Char = Exact<&'static str, char>;
+        static DIGIT: TokenRange<&str, char> = range('0'..='9');
+        static SIGN: Choice<(Char, Char)> = choice((exact('+'), exact('-')));
+        const END: End<&str> = end();
+
+        let number = SIGN
+            .or_not()
+            .and(DIGIT.repeated().at_least(1).to_slice())
+            .left_bind(END);
+        assert_eq!(number.parse("-42"), Ok((Some("-"), "42")));
+
+        let report = number.parse("4x").unwrap_err();
+        assert_eq!(report.span(), 1..2);
+        assert_eq!(
+            report.expected(),
+            &[Expected::Range('0', '9'), Expected::EndOfInput]
+        );
+        assert_eq!(Expected::Range('0', '9').to_string(), "'0'..='9'");
+    }
 
-        let parser = exact("http::").or(exact("httppp")).or(exact("htttt"));
+    #[test]
+    fn test_parse_all() {
+        let number = any()
+            .filter(|c: &char| c.is_ascii_digit())
+            .repeated()
+            .at_least(1);
+        let field = number
+            .to_slice()
+            .map(|digits: &str| digits.parse::<u32>().unwrap());
+        let record = field
+            .separated_by(exact(','))
+            .collect::<Vec<_>>()
+            .left_bind(end());
 
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
+        let results: Vec<_> = record.parse_all(["1,2", "30", "4,x"]).collect();
+        assert_eq!(results[0], Ok(vec![1, 2]));
+        assert_eq!(results[1], Ok(vec![30]));
+        assert_eq!(results[2].as_ref().unwrap_err().span(), 2..3);
+
+        let results: Vec<_> = record.parse_lines("1,2,3\n\n45\n").collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(vec![1, 2, 3]));
+        assert_eq!(results[1], Ok(vec![]));
+        assert_eq!(results[2], Ok(vec![45]));
+
+        // The buffer of the diagnostics is allocated once for all the lines. An empty message
+        // doesn't allocate.
+        let noted = number
+            .to_slice()
+            .validate(|_, span, emitter| emitter.note(span, String::new()))
+            .left_bind(end());
+        let text = "12\n".repeat(1000);
+        let (parsed, count) =
+            allocations(|| noted.parse_lines(&text).filter(Result::is_ok).count());
+        assert_eq!((parsed, count), (1000, 1));
     }
 
     #[test]
-    fn test_long_string() {
-        const SIZE: usize = 1_000_000;
-        let long_string = vec!['c'; SIZE]
-            .into_iter()
-            .chain(vec!['d'; SIZE].into_iter())
-            .into_iter()
-            .collect::<String>();
+    fn test_write_into() {
+        use std::fmt::Write as _;
+        use std::io::Write as _;
 
-        let long_string_as_str = long_string.as_str();
-        let mut input_ref = input_ref!(long_string_as_str);
+        let word = any()
+            .filter(|c: &char| c.is_alphabetic())
+            .repeated()
+            .at_least(1);
+        let word = word.to_slice().left_bind(exact(' ').or_not());
 
-        let bounded_parser = |at_least, at_most| {
-            any()
-                .filter(|c: &char| c == &'c')
-                .repeated()
-                .at_least(at_least)
-                .at_most(at_most)
-                .collect::<String>()
-                .and(
-                    any()
-                        .filter(|c: &char| c == &'d')
-                        .repeated()
-                        .at_least(at_least)
-                        .at_most(at_most)
-                        .collect::<String>(),
-                )
-        };
+        let text = RefCell::new(String::new());
+        let upper = word
+            .repeated()
+            .at_least(0)
+            .write_into(&text, |text, word: &str| {
+                write!(text, "{};", word.to_uppercase())
+            });
+        assert_eq!(upper.parse("hello big world"), Ok(()));
+        assert_eq!(text.into_inner(), "HELLO;BIG;WORLD;");
+
+        let bytes = RefCell::new(Vec::new());
+        let lines = word
+            .repeated()
+            .at_least(0)
+            .write_into(&bytes, |bytes, word: &str| writeln!(bytes, "{}", word));
+        assert_eq!(lines.parse("a b"), Ok(()));
+        assert_eq!(bytes.into_inner(), b"a\nb\n");
 
-        let string = |c, size| vec![c; size].into_iter().collect::<String>();
+        let mut buffer = [0; 4];
+        let full = RefCell::new(&mut buffer[..]);
+        let lines = word
+            .repeated()
+            .at_least(0)
+            .write_into(&full, |bytes, word: &str| writeln!(bytes, "{}", word));
+        let report = lines.parse("abc de").unwrap_err();
+        assert_eq!(report.span(), 4..6);
+        assert_eq!(report.code(), ErrorCode::Custom);
+
+        let out = RefCell::new(String::new());
+        let digit = any().filter(|c: &char| c.is_ascii_digit());
+        let number = (digit.repeated().at_least(1)).write_into(&out, |out, c| write!(out, "{}", c));
+        let item = number.left_bind(exact(',').or_not());
+        let list = (item.repeated().at_least(0))
+            .write_into(&out, |out, ()| write!(out, ";"))
+            .delimited_by(exact('['), exact(']'));
+        assert_eq!(list.parse("[12,3]"), Ok(()));
+        assert_eq!(out.into_inner(), "12;3;");
+    }
 
-        let parser = bounded_parser(1, SIZE);
+    #[test]
+    fn test_text_int() {
         assert_eq!(
-            parser.go(&mut input_ref),
-            Ok((string('c', SIZE), string('d', SIZE)))
+            text::int::<u64>(10).parse("18446744073709551615"),
+            Ok(u64::MAX)
+        );
+        assert_eq!(text::int::<i8>(10).parse("-128"), Ok(i8::MIN));
+        assert_eq!(text::int::<i32>(10).parse("+42"), Ok(42));
+        assert_eq!(text::int::<u32>(16).parse("0x2A"), Ok(42));
+        assert_eq!(text::int::<u32>(16).parse("ff"), Ok(255));
+        let hex = text::int::<u32>(16).and(exact("xz"));
+        assert_eq!(hex.parse("0xz"), Ok((0, "xz")));
+        assert_eq!(text::int::<u8>(2).parse(b"0b101" as &[u8]), Ok(5));
+
+        let report = text::int::<u8>(10).parse("256").unwrap_err();
+        assert_eq!(report.span(), 0..3);
+        assert_eq!(report.message(), Some("integer literal out of range"));
+        let report = text::int::<u32>(10).parse("-1").unwrap_err();
+        assert_eq!(report.span(), 0..1);
+        let report = text::int::<i32>(10).parse("-x").unwrap_err();
+        assert_eq!(
+            (report.span(), report.expected()),
+            (1..2, &[Expected::Label("digit")][..])
         );
 
-        assert_eq!(input_ref.offset(), SIZE << 1);
+        let pair = text::digits(16).left_bind(exact(',')).and(text::digits(8));
+        assert_eq!(pair.parse("c0ffee,755"), Ok(("c0ffee", "755")));
+    }
 
-        input_ref.rewind(input_ref.start());
+    #[test]
+    fn test_text_float() {
+        let float = text::float::<f64>();
+        assert_eq!(float.parse("-1.5e-3"), Ok(-1.5e-3));
+        assert_eq!(float.parse("42"), Ok(42.0));
+        assert_eq!(float.parse(".5"), Ok(0.5));
+        assert_eq!(float.parse("+2E10"), Ok(2e10));
+        assert_eq!(float.parse("0.1"), Ok(0.1));
+        assert_eq!(float.parse("-Infinity"), Ok(f64::NEG_INFINITY));
+        assert!(float.parse("NaN").unwrap().is_nan());
+        let bytes = text::float::<f32>();
+        assert_eq!(bytes.parse(b"3.25" as &[u8]), Ok(3.25));
+
+        let call = float.and(exact(".max"));
+        assert_eq!(call.parse("1.max"), Ok((1.0, ".max")));
+        let unit = float.and(exact("em"));
+        assert_eq!(unit.parse("2em"), Ok((2.0, "em")));
+
+        let report = float.finite_only().parse("inf").unwrap_err();
+        assert_eq!(
+            (report.span(), report.expected()),
+            (0..1, &[Expected::Label("digit")][..])
+        );
+        assert!(float.check("-.").is_err());
+        let word = float.and(exact("o"));
+        assert!(word.parse("info").is_err());
+        let tail = float.and(exact(")"));
+        assert_eq!(tail.parse("inf)"), Ok((f64::INFINITY, ")")));
+    }
 
-        let parser = bounded_parser(SIZE + 1, SIZE + 2);
-        assert_eq!(parser.go(&mut input_ref), Err(ParseError::SyntaxError));
-        assert_eq!(input_ref.offset(), SIZE);
+    #[test]
+    fn test_input_handle() {
+        let text = "let x";
+        let handle: ByValue<&str> = text.handle();
+        assert_eq!(*handle, text);
+        let owned = String::from(text);
+        let handle: &String = owned.handle();
+        assert!(std::ptr::eq(handle, &owned));
+
+        let keyword = exact("let");
+        assert_eq!(keyword.parse_prefix(text), Ok(("let", " x")));
+        assert!(exact("let").check(owned).is_ok());
     }
 }
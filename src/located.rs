@@ -0,0 +1,110 @@
+// Text input keeping track of the line and column of the cursor as it moves forward, so that
+// positions are known without scanning the source again, e.g to store them in the output.
+use crate::error::Position;
+use crate::input::{ByValue, Input, InputRef, SliceInput};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocatedInput<'input> {
+    source: &'input str,
+}
+
+impl<'input> LocatedInput<'input> {
+    pub fn new(source: &'input str) -> Self {
+        Self { source }
+    }
+}
+
+impl From<Position> for usize {
+    #[inline]
+    fn from(position: Position) -> Self {
+        position.byte()
+    }
+}
+
+// Text parsed by a parser, along with where it starts and ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocatedSlice<'input> {
+    text: &'input str,
+    start: Position,
+    end: Position,
+}
+
+impl<'input> LocatedSlice<'input> {
+    #[inline]
+    pub fn as_str(&self) -> &'input str {
+        self.text
+    }
+
+    #[inline]
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    #[inline]
+    pub fn end(&self) -> Position {
+        self.end
+    }
+}
+
+impl<'input> Input<'input> for LocatedInput<'input> {
+    type Token = char;
+
+    type Offset = Position;
+
+    type Span = Range<Position>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.source.is_char_boundary(offset.byte())
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.source[offset.byte()..].chars().next() {
+            // "\r\n" line breaks end with '\n' as well.
+            Some(c) => (offset.advance(c), Some(c)),
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline]
+    fn start(&self) -> Self::Offset {
+        Position::locate(self.source, 0)
+    }
+}
+
+impl<'input> SliceInput<'input> for LocatedInput<'input> {
+    type Slice = LocatedSlice<'input>;
+
+    #[inline]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        LocatedSlice {
+            text: &self.source[start.byte()..end.byte()],
+            start,
+            end,
+        }
+    }
+}
+
+impl<'input> InputRef<'input, '_, LocatedInput<'input>> {
+    // Line and column of the cursor.
+    #[inline]
+    pub fn position(&self) -> Position {
+        self.raw_offset()
+    }
+}
@@ -0,0 +1,557 @@
+// Rewrites of a grammar into an equivalent one that parses faster, see `Parser::optimize`. The
+// combinators are types, so the rewrites are done by the type system: each parser that can be
+// optimized tells what it becomes through `Optimize`, and the parsers it is made of are asked in
+// turn how they combine with their neighbours through the traits below, whose default is to
+// leave them as they are. Three patterns are rewritten:
+// - chains of `or` become a single `choice`, which doesn't go through a nested call per branch,
+// - `exact(a).right_bind(exact(b))` becomes an `ExactThen` matching `ab` in one go,
+// - `any().filter(f)` repeated and sliced with `to_slice` becomes a `TakeWhile` scanning the tokens
+//   `f` accepts, without the bookkeeping of a repetition.
+use crate::input::{Input, InputRef, SliceInput};
+use crate::sequence::OrderedSequence;
+use crate::{
+    choice, And, Any, Bind, Choice, End, Exact, Filter, Labelled, LeftBind, Map, Or, OrNot,
+    ParseResult, Parser, Repeated, RepeatedRange, RightBind, Tag, ToSlice,
+};
+use std::marker::PhantomData;
+
+// Parser that can be optimized, see `Parser::optimize`. The parsers it is made of are optimized
+// with `rewrite`.
+pub trait Optimize<'input, I, O>
+where
+    I: Input<'input>,
+{
+    type Optimized: Parser<'input, I, O>;
+
+    fn rewrite(self) -> Self::Optimized;
+}
+
+// Optimized parser followed by `P` as an alternative, see `Or`. Choices take one more branch.
+pub trait OrElse<P> {
+    type Output;
+
+    fn or_else(self, next: P) -> Self::Output;
+}
+
+// Optimized parser followed by `P` whose output only is kept, see `RightBind`. Exact sequences
+// ask `P` in turn with `AfterExact`.
+pub trait Then<I, OP1, P, OP2> {
+    type Output;
+
+    fn then(self, next: P) -> Self::Output;
+}
+
+// Optimized parser following `exact(seq)`, see `Then`. Exact sequences are joined to it.
+pub trait AfterExact<I, T, OP1, OP2> {
+    type Output;
+
+    fn after_exact(self, first: Exact<I, T>) -> Self::Output;
+}
+
+// Optimized parser repeated and sliced with `to_slice`. Filters ask the parser they filter in
+// turn with `FilterSlice`.
+pub trait IntoSlice<I, OP>: Sized {
+    type Output;
+
+    fn into_slice(slice: ToSlice<I, Self, OP>) -> Self::Output;
+}
+
+// Optimized parser filtered with `F`, repeated and sliced with `to_slice`, see `IntoSlice`. `any`
+// becomes a scan of the tokens `F` accepts.
+pub trait FilterSlice<I, OP, F>: Sized {
+    type Output;
+
+    fn filter_slice(slice: ToSlice<I, Filter<I, Self, OP, F>, OP>) -> Self::Output;
+}
+
+macro_rules! impl_or_else {
+    ($([$($param:ident),*] $parser:ty),+ $(,)?) => {
+        $(
+            impl<$($param,)* Next> OrElse<Next> for $parser {
+                type Output = Choice<(Self, Next)>;
+
+                fn or_else(self, next: Next) -> Self::Output {
+                    choice((self, next))
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_then {
+    ($([$($param:ident),*] $parser:ty),+ $(,)?) => {
+        $(
+            impl<$($param,)* In, Out, Next, NextOut> Then<In, Out, Next, NextOut> for $parser {
+                type Output = RightBind<In, Self, Out, Next, NextOut>;
+
+                fn then(self, next: Next) -> Self::Output {
+                    RightBind(Bind {
+                        first_parser: self,
+                        second_parser: next,
+                        phantom: PhantomData,
+                    })
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_after_exact {
+    ($([$($param:ident),*] $parser:ty),+ $(,)?) => {
+        $(
+            impl<$($param,)* In, Seq, Out, NextOut> AfterExact<In, Seq, Out, NextOut> for $parser {
+                type Output = RightBind<In, Exact<In, Seq>, Out, Self, NextOut>;
+
+                fn after_exact(self, first: Exact<In, Seq>) -> Self::Output {
+                    RightBind(Bind {
+                        first_parser: first,
+                        second_parser: self,
+                        phantom: PhantomData,
+                    })
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_into_slice {
+    ($([$($param:ident),*] $parser:ty),+ $(,)?) => {
+        $(
+            impl<$($param,)* In, Out> IntoSlice<In, Out> for $parser {
+                type Output = ToSlice<In, Self, Out>;
+
+                fn into_slice(slice: ToSlice<In, Self, Out>) -> Self::Output {
+                    slice
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_filter_slice {
+    ($([$($param:ident),*] $parser:ty),+ $(,)?) => {
+        $(
+            impl<$($param,)* In, Out, Func> FilterSlice<In, Out, Func> for $parser {
+                type Output = ToSlice<In, Filter<In, Self, Out, Func>, Out>;
+
+                fn filter_slice(slice: ToSlice<In, Filter<In, Self, Out, Func>, Out>) -> Self::Output {
+                    slice
+                }
+            }
+        )+
+    };
+}
+
+// Every parser an optimization can output combines with its neighbours as it is, save for the
+// patterns rewritten.
+macro_rules! impl_rewrites {
+    ($macro:ident, $($([$($param:ident),*] $parser:ty),+ $(,)?)?) => {
+        $macro!(
+            $($([$($param),*] $parser,)+)?
+            [I, T] Tag<I, T>,
+            [I] End<I>,
+            [P1, P2] Or<P1, P2>,
+            [I, P1, OP1, P2, OP2] And<I, P1, OP1, P2, OP2>,
+            [I, P1, OP1, P2, OP2] LeftBind<I, P1, OP1, P2, OP2>,
+            [I, P1, OP1, P2, OP2] RightBind<I, P1, OP1, P2, OP2>,
+            [I, P, OP, F, U] Map<I, P, OP, F, U>,
+            [I, P, O] Labelled<I, P, O>,
+            [I, P, O] OrNot<I, P, O>,
+            [I, P, OP] ToSlice<I, P, OP>,
+            [I, T] ExactThen<I, T>,
+            [I, T, F] TakeWhile<I, T, F>,
+        );
+    };
+}
+
+impl_rewrites!(impl_or_else, [I, T] Exact<I, T>, [I] Any<I>, [I, P, O, F] Filter<I, P, O, F>);
+impl_rewrites!(impl_then, [I] Any<I>, [I, P, O, F] Filter<I, P, O, F>, [T] Choice<T>);
+impl_rewrites!(impl_after_exact, [I] Any<I>, [I, P, O, F] Filter<I, P, O, F>, [T] Choice<T>);
+impl_rewrites!(impl_into_slice, [I, T] Exact<I, T>, [I] Any<I>, [T] Choice<T>);
+impl_rewrites!(
+    impl_filter_slice,
+    [I, T] Exact<I, T>,
+    [I, P, O, F] Filter<I, P, O, F>,
+    [T] Choice<T>,
+);
+
+macro_rules! impl_or_else_choice {
+    ($($parser:ident),+) => {
+        impl<$($parser,)+ Next> OrElse<Next> for Choice<($($parser,)+)> {
+            type Output = Choice<($($parser,)+ Next)>;
+
+            #[allow(non_snake_case)]
+            fn or_else(self, next: Next) -> Self::Output {
+                let ($($parser,)+) = self.0;
+                choice(($($parser,)+ next))
+            }
+        }
+    };
+}
+
+impl_or_else_choice!(P1, P2);
+impl_or_else_choice!(P1, P2, P3);
+impl_or_else_choice!(P1, P2, P3, P4);
+impl_or_else_choice!(P1, P2, P3, P4, P5);
+impl_or_else_choice!(P1, P2, P3, P4, P5, P6);
+impl_or_else_choice!(P1, P2, P3, P4, P5, P6, P7);
+impl_or_else!([P1, P2, P3, P4, P5, P6, P7, P8] Choice<(P1, P2, P3, P4, P5, P6, P7, P8)>);
+
+impl<I, T, OP1, P, OP2> Then<I, OP1, P, OP2> for Exact<I, T>
+where
+    P: AfterExact<I, T, OP1, OP2>,
+{
+    type Output = P::Output;
+
+    fn then(self, next: P) -> Self::Output {
+        next.after_exact(self)
+    }
+}
+
+impl<I, T1, T2, OP1, OP2> AfterExact<I, T1, OP1, OP2> for Exact<I, T2>
+where
+    T1: OrderedSequence,
+    T2: OrderedSequence<Token = T1::Token>,
+{
+    type Output = ExactThen<I, T1::Token>;
+
+    fn after_exact(self, first: Exact<I, T1>) -> Self::Output {
+        let tokens: Vec<_> = first.seq.iterator().chain(self.seq.iterator()).collect();
+        let text = (first.seq.as_str())
+            .zip(self.seq.as_str())
+            .map(|(first, second)| [first, second].concat());
+        ExactThen {
+            split: first.seq.iterator().count(),
+            exact: Exact {
+                seq: Joined { tokens, text },
+                phantom: PhantomData,
+            },
+        }
+    }
+}
+
+impl<I, P, O, F> IntoSlice<I, O> for Filter<I, P, O, F>
+where
+    P: FilterSlice<I, O, F>,
+{
+    type Output = P::Output;
+
+    fn into_slice(slice: ToSlice<I, Self, O>) -> Self::Output {
+        P::filter_slice(slice)
+    }
+}
+
+impl<I, O, F> FilterSlice<I, O, F> for Any<I> {
+    type Output = TakeWhile<I, O, F>;
+
+    fn filter_slice(slice: ToSlice<I, Filter<I, Self, O, F>, O>) -> Self::Output {
+        let Repeated { parser, range, .. } = slice.0;
+        TakeWhile {
+            filter: parser,
+            range,
+        }
+    }
+}
+
+// Two sequences one after the other, see `ExactThen`. The text is kept along with the tokens when
+// both are strings, for inputs comparing it at once.
+#[derive(Debug, Clone)]
+struct Joined<T> {
+    tokens: Vec<T>,
+    text: Option<String>,
+}
+
+impl<T> OrderedSequence for Joined<T>
+where
+    T: Copy,
+{
+    type Token = T;
+
+    type Iter<'seq>
+        = std::iter::Copied<std::slice::Iter<'seq, T>>
+    where
+        Self: 'seq;
+
+    fn iterator(&self) -> Self::Iter<'_> {
+        self.tokens.iter().copied()
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    fn as_tokens(&self) -> Option<&[Self::Token]> {
+        Some(&self.tokens)
+    }
+}
+
+// `exact(a).right_bind(exact(b))` optimized: matches `ab` in one go and outputs the slice `b`
+// matched. Running out of a partial input within `a`, `b` is counted in the tokens needed.
+#[derive(Clone)]
+pub struct ExactThen<I, T> {
+    exact: Exact<I, Joined<T>>,
+    // Number of tokens of `a`.
+    split: usize,
+}
+
+impl<'input, I> Parser<'input, I, I::Slice> for ExactThen<I, I::Token>
+where
+    I: SliceInput<'input>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let mut offset = input_ref.raw_offset();
+        self.exact.matches(input_ref)?;
+        for _ in 0..self.split {
+            offset = input_ref.peek_at(offset).0;
+        }
+        Ok(input_ref.slice(input_ref.tag(offset), input_ref.offset()))
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.exact.matches(input_ref)
+    }
+
+    #[inline]
+    fn rules_out(&self, input_ref: &mut InputRef<'input, '_, I>) -> bool {
+        self.exact.rules_out(input_ref)
+    }
+}
+
+// `any().filter(f).repeated()` sliced with `to_slice` optimized: moves past the tokens `f` accepts
+// in one go and outputs the slice of them. The token ending the run is then rejected by the filter
+// as the last attempt of the repetition would be, so that the error is recorded the same way.
+#[derive(Clone, Copy)]
+pub struct TakeWhile<I, T, F> {
+    filter: Filter<I, Any<I>, T, F>,
+    range: RepeatedRange,
+}
+
+impl<'input, I, F> Parser<'input, I, I::Slice> for TakeWhile<I, I::Token, F>
+where
+    I: SliceInput<'input>,
+    F: Fn(&I::Token) -> bool,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let start = input_ref.offset();
+        let at_most = self.range.end().unwrap_or(usize::MAX);
+        let count = self.filter.go_scan(input_ref, at_most, &|_| true);
+        if count < at_most {
+            // The scan stopped on a token the filter rejects or at the end, the attempt fails.
+            match self.filter.go(input_ref) {
+                Err(error) if count < self.range.start() || error.is_fatal() => return Err(error),
+                _ => {}
+            }
+        }
+        Ok(input_ref.slice(start, input_ref.offset()))
+    }
+}
+
+impl<'input, I, T> Optimize<'input, I, I::Slice> for Exact<I, T>
+where
+    I: SliceInput<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    type Optimized = Self;
+
+    fn rewrite(self) -> Self::Optimized {
+        self
+    }
+}
+
+impl<'input, I, T> Optimize<'input, I, ()> for Tag<I, T>
+where
+    I: Input<'input>,
+    T: OrderedSequence<Token = I::Token>,
+{
+    type Optimized = Self;
+
+    fn rewrite(self) -> Self::Optimized {
+        self
+    }
+}
+
+impl<'input, I> Optimize<'input, I, I::Token> for Any<I>
+where
+    I: Input<'input>,
+{
+    type Optimized = Self;
+
+    fn rewrite(self) -> Self::Optimized {
+        self
+    }
+}
+
+impl<'input, I> Optimize<'input, I, ()> for End<I>
+where
+    I: Input<'input>,
+{
+    type Optimized = Self;
+
+    fn rewrite(self) -> Self::Optimized {
+        self
+    }
+}
+
+impl<'input, I, P, O, F> Optimize<'input, I, O> for Filter<I, P, O, F>
+where
+    I: Input<'input>,
+    P: Optimize<'input, I, O>,
+    F: Fn(&O) -> bool,
+{
+    type Optimized = Filter<I, P::Optimized, O, F>;
+
+    fn rewrite(self) -> Self::Optimized {
+        Filter {
+            parser: self.parser.rewrite(),
+            filter_func: self.filter_func,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I, P1, P2, OP> Optimize<'input, I, OP> for Or<P1, P2>
+where
+    I: Input<'input>,
+    P1: Optimize<'input, I, OP>,
+    P2: Optimize<'input, I, OP>,
+    P1::Optimized: OrElse<P2::Optimized>,
+    <P1::Optimized as OrElse<P2::Optimized>>::Output: Parser<'input, I, OP>,
+{
+    type Optimized = <P1::Optimized as OrElse<P2::Optimized>>::Output;
+
+    fn rewrite(self) -> Self::Optimized {
+        (self.first_parser.rewrite()).or_else(self.second_parser.rewrite())
+    }
+}
+
+macro_rules! impl_optimize_choice {
+    ($($parser:ident $idx:tt),+) => {
+        impl<'input, I, OP, $($parser),+> Optimize<'input, I, OP> for Choice<($($parser,)+)>
+        where
+            I: Input<'input>,
+            $($parser: Optimize<'input, I, OP>,)+
+        {
+            type Optimized = Choice<($($parser::Optimized,)+)>;
+
+            fn rewrite(self) -> Self::Optimized {
+                choice(($(self.0.$idx.rewrite(),)+))
+            }
+        }
+    };
+}
+
+impl_optimize_choice!(P1 0, P2 1);
+impl_optimize_choice!(P1 0, P2 1, P3 2);
+impl_optimize_choice!(P1 0, P2 1, P3 2, P4 3);
+impl_optimize_choice!(P1 0, P2 1, P3 2, P4 3, P5 4);
+impl_optimize_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5);
+impl_optimize_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5, P7 6);
+impl_optimize_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5, P7 6, P8 7);
+
+macro_rules! impl_optimize_bind {
+    ($($bind:ident => $out:ty),+) => {
+        $(
+            impl<'input, I, P1, OP1, P2, OP2> Optimize<'input, I, $out>
+                for $bind<I, P1, OP1, P2, OP2>
+            where
+                I: Input<'input>,
+                P1: Optimize<'input, I, OP1>,
+                P2: Optimize<'input, I, OP2>,
+            {
+                type Optimized = $bind<I, P1::Optimized, OP1, P2::Optimized, OP2>;
+
+                fn rewrite(self) -> Self::Optimized {
+                    $bind(Bind {
+                        first_parser: self.0.first_parser.rewrite(),
+                        second_parser: self.0.second_parser.rewrite(),
+                        phantom: PhantomData,
+                    })
+                }
+            }
+        )+
+    };
+}
+
+impl_optimize_bind!(And => (OP1, OP2), LeftBind => OP1);
+
+impl<'input, I, P1, OP1, P2, OP2> Optimize<'input, I, OP2> for RightBind<I, P1, OP1, P2, OP2>
+where
+    I: Input<'input>,
+    P1: Optimize<'input, I, OP1>,
+    P2: Optimize<'input, I, OP2>,
+    P1::Optimized: Then<I, OP1, P2::Optimized, OP2>,
+    <P1::Optimized as Then<I, OP1, P2::Optimized, OP2>>::Output: Parser<'input, I, OP2>,
+{
+    type Optimized = <P1::Optimized as Then<I, OP1, P2::Optimized, OP2>>::Output;
+
+    fn rewrite(self) -> Self::Optimized {
+        (self.0.first_parser.rewrite()).then(self.0.second_parser.rewrite())
+    }
+}
+
+impl<'input, I, P, OP, F, U> Optimize<'input, I, U> for Map<I, P, OP, F, U>
+where
+    I: Input<'input>,
+    P: Optimize<'input, I, OP>,
+    F: Fn(OP) -> U,
+{
+    type Optimized = Map<I, P::Optimized, OP, F, U>;
+
+    fn rewrite(self) -> Self::Optimized {
+        Map {
+            mapper: self.mapper,
+            parser: self.parser.rewrite(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I, P, O> Optimize<'input, I, O> for Labelled<I, P, O>
+where
+    I: Input<'input>,
+    P: Optimize<'input, I, O>,
+{
+    type Optimized = Labelled<I, P::Optimized, O>;
+
+    fn rewrite(self) -> Self::Optimized {
+        Labelled {
+            parser: self.parser.rewrite(),
+            name: self.name,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I, P, O> Optimize<'input, I, Option<O>> for OrNot<I, P, O>
+where
+    I: Input<'input>,
+    P: Optimize<'input, I, O>,
+{
+    type Optimized = OrNot<I, P::Optimized, O>;
+
+    fn rewrite(self) -> Self::Optimized {
+        OrNot {
+            parser: self.parser.rewrite(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I, P, OP> Optimize<'input, I, I::Slice> for ToSlice<I, P, OP>
+where
+    I: SliceInput<'input>,
+    P: Optimize<'input, I, OP>,
+    P::Optimized: IntoSlice<I, OP>,
+    <P::Optimized as IntoSlice<I, OP>>::Output: Parser<'input, I, I::Slice>,
+{
+    type Optimized = <P::Optimized as IntoSlice<I, OP>>::Output;
+
+    fn rewrite(self) -> Self::Optimized {
+        let Repeated { parser, range, .. } = self.0;
+        IntoSlice::into_slice(ToSlice(Repeated {
+            parser: parser.rewrite(),
+            range,
+            phantom: PhantomData,
+        }))
+    }
+}
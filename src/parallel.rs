@@ -0,0 +1,132 @@
+// Inputs made of independent records, e.g the lines of a log file, a CSV or an NDJSON document,
+// parsed in parallel with `rayon`. The records are found with a cheap scan of the input for their
+// delimiters, then each of them is parsed on its own.
+use crate::error::ErrorReport;
+use crate::input::Input;
+use crate::Parser;
+use rayon::prelude::*;
+use std::ops::Range;
+
+// Input whose records can be parsed on their own, see `parse_records_parallel`.
+pub trait RecordInput<'input>: Input<'input> + Copy + Send + Sync {
+    fn as_bytes(&self) -> &'input [u8];
+
+    // Input of the record over `range` of the bytes. Text inputs panic if the range doesn't lie
+    // on character boundaries.
+    fn record(&self, range: Range<usize>) -> Self;
+}
+
+impl<'input> RecordInput<'input> for &'input str {
+    #[inline]
+    fn as_bytes(&self) -> &'input [u8] {
+        str::as_bytes(self)
+    }
+
+    #[inline]
+    fn record(&self, range: Range<usize>) -> Self {
+        &self[range]
+    }
+}
+
+impl<'input> RecordInput<'input> for &'input [u8] {
+    #[inline]
+    fn as_bytes(&self) -> &'input [u8] {
+        self
+    }
+
+    #[inline]
+    fn record(&self, range: Range<usize>) -> Self {
+        &self[range]
+    }
+}
+
+// Finds the records of an input, see `parse_records_parallel`.
+pub trait RecordSplitter {
+    // Range of the first record of `rest`, along with the number of bytes it takes up including
+    // its framing, e.g the delimiter or the length prefix. `None` once there are no records left.
+    fn split(&self, rest: &[u8]) -> Option<(Range<usize>, usize)>;
+}
+
+// Records ended by a delimiter, e.g `b'\n'` for lines. The delimiter may be left out after the
+// last record.
+#[derive(Debug, Clone, Copy)]
+pub struct Delimited(pub u8);
+
+impl RecordSplitter for Delimited {
+    fn split(&self, rest: &[u8]) -> Option<(Range<usize>, usize)> {
+        if rest.is_empty() {
+            return None;
+        }
+        Some(match rest.iter().position(|&byte| byte == self.0) {
+            Some(end) => (0..end, end + 1),
+            None => (0..rest.len(), rest.len()),
+        })
+    }
+}
+
+// Records preceded by their length, in bytes, as a big endian `u32`. A truncated record is
+// handed over as it is, so that it fails to parse, and so are trailing bytes too few to make up a
+// length, prefix included.
+#[derive(Debug, Clone, Copy)]
+pub struct LengthPrefixed;
+
+impl RecordSplitter for LengthPrefixed {
+    fn split(&self, rest: &[u8]) -> Option<(Range<usize>, usize)> {
+        match rest.first_chunk::<4>() {
+            Some(prefix) => {
+                let end = (4 + u32::from_be_bytes(*prefix) as usize).min(rest.len());
+                Some((4..end, end))
+            }
+            None if rest.is_empty() => None,
+            None => Some((0..rest.len(), rest.len())),
+        }
+    }
+}
+
+impl<F> RecordSplitter for F
+where
+    F: Fn(&[u8]) -> Option<(Range<usize>, usize)>,
+{
+    #[inline]
+    fn split(&self, rest: &[u8]) -> Option<(Range<usize>, usize)> {
+        self(rest)
+    }
+}
+
+// Splits `input` in records with `splitter` and parses each of them with `parser`, in parallel.
+// The results are in the order of the records and the spans of the errors are offsets in `input`.
+pub fn parse_records_parallel<'input, I, S, P, O>(
+    input: I,
+    splitter: S,
+    parser: &P,
+) -> Vec<Result<O, ErrorReport<I::Token>>>
+where
+    I: RecordInput<'input>,
+    I::Token: Send,
+    S: RecordSplitter,
+    P: Parser<'input, I, O> + Sync,
+    O: Send,
+{
+    let bytes = input.as_bytes();
+    let mut records = Vec::new();
+    let mut start = 0;
+    while let Some((range, len)) = splitter.split(&bytes[start..]) {
+        records.push(start + range.start..start + range.end);
+        // A splitter not moving forward would find the same record forever.
+        if len == 0 {
+            break;
+        }
+        start += len;
+    }
+
+    records
+        .into_par_iter()
+        .map(|range| {
+            let offset = range.start;
+            parser.parse(input.record(range)).map_err(|mut report| {
+                report.shift(offset);
+                report
+            })
+        })
+        .collect()
+}
@@ -1,4 +1,6 @@
-use std::collections::HashSet;
+use crate::error::CustomError;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::hash::Hash;
 
 pub trait OrderedSequence {
@@ -9,6 +11,15 @@ pub trait OrderedSequence {
         Self: 'seq;
 
     fn iterator(&self) -> Self::Iter<'_>;
+
+    // The sequence as a whole, for inputs able to compare it at once, see `Input::match_str`.
+    fn as_str(&self) -> Option<&str> {
+        None
+    }
+
+    fn as_tokens(&self) -> Option<&[Self::Token]> {
+        None
+    }
 }
 
 impl OrderedSequence for &str {
@@ -19,16 +30,44 @@ impl OrderedSequence for &str {
     fn iterator(&self) -> Self::Iter<'_> {
         self.chars()
     }
+
+    fn as_str(&self) -> Option<&str> {
+        Some(self)
+    }
 }
 
-impl OrderedSequence for &[u8] {
-    type Token = u8;
+impl<T> OrderedSequence for &[T]
+where
+    T: Copy,
+{
+    type Token = T;
 
-    type Iter<'seq> = std::iter::Copied<std::slice::Iter<'seq, u8>> where Self: 'seq;
+    type Iter<'seq> = std::iter::Copied<std::slice::Iter<'seq, T>> where Self: 'seq;
 
     fn iterator(&self) -> Self::Iter<'_> {
         self.iter().copied()
     }
+
+    fn as_tokens(&self) -> Option<&[Self::Token]> {
+        Some(self)
+    }
+}
+
+impl<T, const N: usize> OrderedSequence for &[T; N]
+where
+    T: Copy,
+{
+    type Token = T;
+
+    type Iter<'seq> = std::iter::Copied<std::slice::Iter<'seq, T>> where Self: 'seq;
+
+    fn iterator(&self) -> Self::Iter<'_> {
+        self.iter().copied()
+    }
+
+    fn as_tokens(&self) -> Option<&[Self::Token]> {
+        Some(self.as_slice())
+    }
 }
 
 impl OrderedSequence for char {
@@ -39,6 +78,10 @@ impl OrderedSequence for char {
     fn iterator(&self) -> Self::Iter<'_> {
         core::iter::once(*self)
     }
+
+    fn as_tokens(&self) -> Option<&[Self::Token]> {
+        Some(std::slice::from_ref(self))
+    }
 }
 
 impl OrderedSequence for u8 {
@@ -49,43 +92,204 @@ impl OrderedSequence for u8 {
     fn iterator(&self) -> Self::Iter<'_> {
         core::iter::once(*self)
     }
+
+    fn as_tokens(&self) -> Option<&[Self::Token]> {
+        Some(std::slice::from_ref(self))
+    }
 }
 
-pub trait Container: Default {
-    type Item;
+// Collection of the outputs of a repeated parser, see `collect`. Containers may accept several
+// item types, e.g `()` discards outputs of any type.
+pub trait Container<T>: Default {
+    // Container about to receive around `hint` items, see `Collect`.
+    fn with_capacity_hint(hint: usize) -> Self {
+        _ = hint;
+        Self::default()
+    }
+
+    fn push(&mut self, item: T);
 
-    fn push(&mut self, item: Self::Item);
+    // Same as `push` but the item can be rejected, e.g a set refusing duplicates. The rejection
+    // makes the parse fail over the item's span. Items are always accepted by default.
+    fn try_push(&mut self, item: T) -> Result<(), CustomError> {
+        self.push(item);
+        Ok(())
+    }
 }
 
-impl<T> Container for Vec<T> {
-    type Item = T;
+impl<T> Container<T> for Vec<T> {
+    fn with_capacity_hint(hint: usize) -> Self {
+        Vec::with_capacity(hint)
+    }
 
-    fn push(&mut self, item: Self::Item) {
+    fn push(&mut self, item: T) {
         self.push(item);
     }
 }
 
-impl Container for String {
-    type Item = char;
+impl Container<char> for String {
+    // Characters are at least one byte long.
+    fn with_capacity_hint(hint: usize) -> Self {
+        String::with_capacity(hint)
+    }
 
-    fn push(&mut self, item: Self::Item) {
+    fn push(&mut self, item: char) {
         self.push(item);
     }
 }
 
-impl Container for () {
-    type Item = char;
+impl<'a> Container<&'a str> for String {
+    fn push(&mut self, item: &'a str) {
+        self.push_str(item);
+    }
+}
+
+// Text borrowed from the input as long as it's made of a single slice, e.g a string literal
+// without escape sequences. Only text that had to change is allocated.
+impl<'a> Container<&'a str> for Cow<'a, str> {
+    fn push(&mut self, item: &'a str) {
+        if self.is_empty() {
+            *self = Cow::Borrowed(item);
+        } else if !item.is_empty() {
+            self.to_mut().push_str(item);
+        }
+    }
+}
+
+impl Container<char> for Cow<'_, str> {
+    fn push(&mut self, item: char) {
+        self.to_mut().push(item);
+    }
+}
 
-    fn push(&mut self, _: Self::Item) {}
+impl<T> Container<T> for () {
+    fn push(&mut self, _: T) {}
 }
 
-impl<K> Container for HashSet<K>
+impl<K> Container<K> for HashSet<K>
 where
     K: Hash + Eq,
 {
-    type Item = K;
+    fn with_capacity_hint(hint: usize) -> Self {
+        HashSet::with_capacity(hint)
+    }
+
+    fn push(&mut self, item: K) {
+        self.insert(item);
+    }
+}
 
-    fn push(&mut self, item: Self::Item) {
+impl<K> Container<K> for BTreeSet<K>
+where
+    K: Ord,
+{
+    fn push(&mut self, item: K) {
         self.insert(item);
     }
 }
+
+impl<T> Container<T> for VecDeque<T> {
+    fn with_capacity_hint(hint: usize) -> Self {
+        VecDeque::with_capacity(hint)
+    }
+
+    fn push(&mut self, item: T) {
+        self.push_back(item);
+    }
+}
+
+impl<T> Container<T> for BinaryHeap<T>
+where
+    T: Ord,
+{
+    fn with_capacity_hint(hint: usize) -> Self {
+        BinaryHeap::with_capacity(hint)
+    }
+
+    fn push(&mut self, item: T) {
+        self.push(item);
+    }
+}
+
+// Number of outputs, which are discarded, e.g the indentation level of a line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Count(usize);
+
+impl Count {
+    #[inline]
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+impl<T> Container<T> for Count {
+    fn push(&mut self, _: T) {
+        self.0 += 1;
+    }
+}
+
+// Later entries override earlier ones with the same key.
+impl<K, V> Container<(K, V)> for HashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    fn with_capacity_hint(hint: usize) -> Self {
+        HashMap::with_capacity(hint)
+    }
+
+    fn push(&mut self, (key, value): (K, V)) {
+        self.insert(key, value);
+    }
+}
+
+// Later entries override earlier ones with the same key.
+impl<K, V> Container<(K, V)> for BTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn push(&mut self, (key, value): (K, V)) {
+        self.insert(key, value);
+    }
+}
+
+// What `collect_map` does with an entry whose key is already in the map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    // The later entry replaces the earlier one.
+    #[default]
+    Overwrite,
+    // The later entry is dropped.
+    KeepFirst,
+    // The parse fails with a `DuplicateKey` error over the later entry.
+    Fail,
+}
+
+// Containers of key-value pairs, see `collect_map`.
+pub trait MapContainer<K, V>: Container<(K, V)> {
+    fn contains_key(&self, key: &K) -> bool;
+}
+
+impl<K, V> MapContainer<K, V> for HashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+}
+
+impl<K, V> MapContainer<K, V> for BTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+}
+
+// Aggregates built from the outputs of a repetition along with their spans, see `collect_parsed`.
+// Works like `FromIterator` but an output can be rejected, which makes the parse fail over the
+// span of the last output taken from `items`, e.g a header given twice.
+pub trait FromParsedIter<T, S>: Sized {
+    fn from_parsed_iter(items: impl Iterator<Item = (T, S)>) -> Result<Self, CustomError>;
+}
@@ -0,0 +1,302 @@
+// Inputs owning their source, e.g a `String` read at runtime, or an `Arc<str>` so that what's
+// parsed can outlive the frame that loaded the source, or be sent to another thread. The source of
+// a `String` or a `Vec` is gone once parsed, their slices are copied out of it. Slices of an `Arc`
+// are mere offsets into the source, which the caller keeps a handle of.
+use crate::input::{Input, SliceInput};
+use std::ops::{Deref, Index, Range};
+use std::sync::Arc;
+
+// Part of an `Arc` input, it's retrieved with e.g `slice.of(&source)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SharedSlice {
+    start: usize,
+    end: usize,
+}
+
+impl SharedSlice {
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // `source` must be the one the slice was parsed from.
+    #[inline]
+    pub fn of<'a, S>(&self, source: &'a S) -> &'a <S::Target as Index<Range<usize>>>::Output
+    where
+        S: Deref,
+        S::Target: Index<Range<usize>>,
+    {
+        &source[self.range()]
+    }
+}
+
+impl<'input> Input<'input> for Arc<str> {
+    type Token = char;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.is_char_boundary(offset)
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        if let Some(c) = self[offset..].chars().next() {
+            (offset + c.len_utf8(), Some(c))
+        } else {
+            (offset, None)
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    #[inline]
+    fn match_str(&self, offset: Self::Offset, prefix: &str) -> Option<Self::Offset> {
+        (&**self).match_str(offset, prefix)
+    }
+}
+
+impl<'input> SliceInput<'input> for Arc<str> {
+    type Slice = SharedSlice;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        SharedSlice { start, end }
+    }
+
+    #[inline(always)]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        SharedSlice {
+            start,
+            end: self.len(),
+        }
+    }
+}
+
+impl<'input, T> Input<'input> for Arc<[T]>
+where
+    T: Copy + Eq + 'input,
+{
+    type Token = T;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset <= self.len()
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.get(offset) {
+            Some(token) => (offset + 1, Some(*token)),
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    #[inline]
+    fn match_tokens(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<Self::Offset> {
+        (&**self).match_tokens(offset, prefix)
+    }
+
+    #[inline]
+    fn match_prefix(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<(usize, usize)> {
+        (&**self).match_prefix(offset, prefix)
+    }
+}
+
+impl<'input, T> SliceInput<'input> for Arc<[T]>
+where
+    T: Copy + Eq + 'input,
+{
+    type Slice = SharedSlice;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        SharedSlice { start, end }
+    }
+
+    #[inline(always)]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        SharedSlice {
+            start,
+            end: self.len(),
+        }
+    }
+}
+
+impl<'input> Input<'input> for String {
+    type Token = char;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        self.is_char_boundary(offset)
+    }
+
+    #[inline]
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        self.as_str().next(offset)
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    #[inline]
+    fn match_str(&self, offset: Self::Offset, prefix: &str) -> Option<Self::Offset> {
+        self.as_str().match_str(offset, prefix)
+    }
+}
+
+impl<'input> SliceInput<'input> for String {
+    type Slice = String;
+
+    #[inline]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        self[start..end].to_owned()
+    }
+
+    #[inline]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        self[start..].to_owned()
+    }
+}
+
+impl<'input, T> Input<'input> for Vec<T>
+where
+    T: Copy + Eq + 'input,
+{
+    type Token = T;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset <= self.len()
+    }
+
+    #[inline]
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        self.as_slice().next(offset)
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    #[inline]
+    fn match_tokens(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<Self::Offset> {
+        self.as_slice().match_tokens(offset, prefix)
+    }
+
+    #[inline]
+    fn match_prefix(&self, offset: Self::Offset, prefix: &[Self::Token]) -> Option<(usize, usize)> {
+        self.as_slice().match_prefix(offset, prefix)
+    }
+}
+
+impl<'input, T> SliceInput<'input> for Vec<T>
+where
+    T: Copy + Eq + 'input,
+{
+    type Slice = Vec<T>;
+
+    #[inline]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        self[start..end].to_vec()
+    }
+
+    #[inline]
+    fn slice_to_end(&self, start: Self::Offset) -> Self::Slice {
+        self[start..].to_vec()
+    }
+}
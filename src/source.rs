@@ -0,0 +1,60 @@
+// Text read from a file, the standard input or any reader, owned so that simple tools don't have to
+// keep a buffer alive next to the input borrowing it. Errors reported on it carry where the text
+// comes from.
+use crate::input::{SourceId, Sourced};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct SourceText {
+    text: String,
+    source_id: SourceId,
+}
+
+impl SourceText {
+    pub fn new(text: impl Into<String>, source_id: impl Into<SourceId>) -> Self {
+        Self {
+            text: text.into(),
+            source_id: source_id.into(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Ok(Self::new(text, path.to_string_lossy().into_owned()))
+    }
+
+    pub fn from_stdin() -> io::Result<Self> {
+        Self::from_reader(io::stdin().lock()).map(|source| source.with_source_id("<stdin>"))
+    }
+
+    // Reads `reader` to the end, the text must be valid UTF-8.
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Ok(Self::new(text, "<input>"))
+    }
+
+    pub fn with_source_id(mut self, source_id: impl Into<SourceId>) -> Self {
+        self.source_id = source_id.into();
+        self
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    #[inline]
+    pub fn source_id(&self) -> &SourceId {
+        &self.source_id
+    }
+
+    // The text as an input, e.g `parser.parse(source.input())`.
+    #[inline]
+    pub fn input(&self) -> Sourced<&str> {
+        Sourced::new(self.as_str(), self.source_id.clone())
+    }
+}
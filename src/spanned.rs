@@ -0,0 +1,150 @@
+// Input over the tokens produced by a lexer, each token comes with its span in the source it was
+// lexed from. Parsers see the tokens, while offsets, and thus the spans of the errors, are
+// translated back to the source.
+use crate::input::{ByValue, Input, SliceInput};
+use std::ops::Range;
+
+#[derive(Debug)]
+pub struct SpannedInput<'input, T> {
+    tokens: &'input [(T, Range<usize>)],
+}
+
+impl<'input, T> SpannedInput<'input, T> {
+    pub fn new(tokens: &'input [(T, Range<usize>)]) -> Self {
+        Self { tokens }
+    }
+
+    // Offset in the source of the token at `index`, the end of the last token past the end.
+    fn source_offset(&self, index: usize) -> usize {
+        match self.tokens.get(index) {
+            Some((_, span)) => span.start,
+            None => self.tokens.last().map_or(0, |(_, span)| span.end),
+        }
+    }
+}
+
+impl<T> Clone for SpannedInput<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SpannedInput<'_, T> {}
+
+// Position in a `SpannedInput`: the index of the next token, along with where it starts in the
+// source. Converting it to `usize` yields the latter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpannedOffset {
+    index: usize,
+    source: usize,
+}
+
+impl SpannedOffset {
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl From<SpannedOffset> for usize {
+    #[inline]
+    fn from(offset: SpannedOffset) -> Self {
+        offset.source
+    }
+}
+
+// Tokens parsed by a parser, along with the part of the source they were lexed from.
+#[derive(Debug)]
+pub struct SpannedSlice<'input, T> {
+    tokens: &'input [(T, Range<usize>)],
+    span: (usize, usize),
+}
+
+impl<'input, T> SpannedSlice<'input, T> {
+    pub fn tokens(&self) -> impl Iterator<Item = &'input T> {
+        self.tokens.iter().map(|(token, _)| token)
+    }
+
+    #[inline]
+    pub fn span(&self) -> Range<usize> {
+        self.span.0..self.span.1
+    }
+}
+
+impl<T> Clone for SpannedSlice<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SpannedSlice<'_, T> {}
+
+impl<'input, T> Input<'input> for SpannedInput<'input, T>
+where
+    T: Copy + Eq + 'input,
+{
+    type Token = T;
+
+    type Offset = SpannedOffset;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset.index <= self.tokens.len()
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.tokens.get(offset.index) {
+            Some((token, _)) => {
+                let index = offset.index + 1;
+                let next = SpannedOffset {
+                    index,
+                    source: self.source_offset(index),
+                };
+                (next, Some(*token))
+            }
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        self.slice(start, end).span()
+    }
+
+    #[inline]
+    fn start(&self) -> Self::Offset {
+        SpannedOffset {
+            index: 0,
+            source: self.source_offset(0),
+        }
+    }
+}
+
+impl<'input, T> SliceInput<'input> for SpannedInput<'input, T>
+where
+    T: Copy + Eq + 'input,
+{
+    type Slice = SpannedSlice<'input, T>;
+
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        let tokens = &self.tokens[start.index..end.index];
+        // The source between two tokens, e.g white space, isn't part of the slice.
+        let span = match (tokens.first(), tokens.last()) {
+            (Some((_, first)), Some((_, last))) => (first.start, last.end),
+            _ => (start.source, start.source),
+        };
+        SpannedSlice { tokens, span }
+    }
+}
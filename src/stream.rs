@@ -0,0 +1,549 @@
+// Inputs pulling their tokens from a source on demand rather than holding all of them up front.
+// The tokens pulled are buffered so that the parser can still rewind to any offset.
+use crate::error::ErrorReport;
+use crate::input::{Input, InputRef, ParseConfig, SliceInput};
+use crate::Parser;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::ops::Range;
+#[cfg(feature = "async")]
+use std::time::Duration;
+
+// Slice of a buffered input. The tokens live in the buffer of the input, they are retrieved with
+// e.g `IterInput::tokens`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferSlice {
+    start: usize,
+    end: usize,
+}
+
+impl BufferSlice {
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+// Input over the tokens yielded by an iterator, e.g a token generator or a `char_indices` adapter,
+// without collecting them first. Offsets count the tokens yielded.
+pub struct IterInput<It>
+where
+    It: Iterator,
+{
+    iter: RefCell<It>,
+    buffer: RefCell<Vec<It::Item>>,
+}
+
+impl<It> IterInput<It>
+where
+    It: Iterator,
+{
+    pub fn new(iter: impl IntoIterator<IntoIter = It>) -> Self {
+        Self {
+            iter: RefCell::new(iter.into_iter()),
+            buffer: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Tokens covered by `slice`.
+    pub fn tokens(&self, slice: BufferSlice) -> Vec<It::Item>
+    where
+        It::Item: Clone,
+    {
+        self.buffer.borrow()[slice.range()].to_vec()
+    }
+
+    // Pulls tokens from the iterator until the one at `offset` is buffered, if there is one.
+    fn fill(&self, offset: usize) {
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.len() > offset {
+            return;
+        }
+
+        let mut iter = self.iter.borrow_mut();
+        let missing = offset + 1 - buffer.len();
+        buffer.extend(iter.by_ref().take(missing));
+    }
+}
+
+impl<'input, It> Input<'input> for IterInput<It>
+where
+    It: Iterator + 'input,
+    It::Item: Copy + Eq + 'input,
+{
+    type Token = It::Item;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        self.fill(offset);
+        match self.buffer.borrow().get(offset) {
+            Some(token) => (offset + 1, Some(*token)),
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+}
+
+impl<'input, It> SliceInput<'input> for IterInput<It>
+where
+    It: Iterator + 'input,
+    It::Item: Copy + Eq + 'input,
+{
+    type Slice = BufferSlice;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        BufferSlice { start, end }
+    }
+}
+
+// Failure of `ReaderInput::parse_next`.
+#[derive(Debug)]
+pub enum StreamError<T> {
+    Io(io::Error),
+    Parse(ErrorReport<T>),
+    // No byte arrived within the read timeout, see `AsyncReaderInput::with_timeout`.
+    Timeout,
+    // The record being parsed grew past `limit` bytes, see `AsyncReaderInput::max_frame_size`.
+    FrameTooLarge { limit: usize },
+}
+
+impl<T> From<io::Error> for StreamError<T> {
+    fn from(error: io::Error) -> Self {
+        StreamError::Io(error)
+    }
+}
+
+impl<T> fmt::Display for StreamError<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(error) => write!(f, "{}", error),
+            StreamError::Parse(report) => write!(f, "{}", report),
+            StreamError::Timeout => write!(f, "timed out waiting for more input"),
+            StreamError::FrameTooLarge { limit } => {
+                write!(f, "record exceeds the maximum size of {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl<T> Error for StreamError<T>
+where
+    T: fmt::Debug,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StreamError::Io(error) => Some(error),
+            StreamError::Parse(report) => report.source(),
+            StreamError::Timeout | StreamError::FrameTooLarge { .. } => None,
+        }
+    }
+}
+
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+// Bytes buffered by a `ReaderInput`, this is the input its parsers run on. Offsets are relative to
+// the start of the buffer.
+pub struct ReaderChunk {
+    bytes: Vec<u8>,
+    // Bytes already parsed, dropped on the next refill.
+    parsed: usize,
+}
+
+impl ReaderChunk {
+    // Bytes covered by `slice`.
+    #[inline]
+    pub fn bytes(&self, slice: BufferSlice) -> &[u8] {
+        &self.bytes[slice.range()]
+    }
+
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            parsed: 0,
+        }
+    }
+
+    // Bytes buffered past the previous record.
+    #[cfg(feature = "async")]
+    #[inline]
+    fn pending(&self) -> usize {
+        self.bytes.len() - self.parsed
+    }
+
+    // Runs `parser` on the bytes buffered past the previous record, `None` means more bytes are
+    // needed. `dropped` is the number of bytes of the stream dropped so far.
+    pub(crate) fn parse_next<P, O>(
+        &mut self,
+        parser: &P,
+        eof: bool,
+        dropped: usize,
+    ) -> Option<Result<Option<O>, StreamError<u8>>>
+    where
+        P: Parser<'static, ReaderChunk, O>,
+    {
+        if self.parsed == self.bytes.len() {
+            return eof.then_some(Ok(None));
+        }
+
+        let config = ParseConfig::default().partial(!eof);
+        let mut input_ref = InputRef::with_config(&*self, config);
+        match parser.go(&mut input_ref) {
+            Ok(out) => {
+                self.parsed = input_ref.offset().into();
+                Some(Ok(Some(out)))
+            }
+            Err(error) if error.is_incomplete() => None,
+            Err(error) => {
+                let mut report = input_ref.take_error(error);
+                report.shift(dropped);
+                Some(Err(StreamError::Parse(report)))
+            }
+        }
+    }
+
+    // Drops the bytes parsed so far and makes room for `len` more bytes, returns the number of
+    // bytes dropped and the room to read into. `filled` must be called once read.
+    pub(crate) fn reserve(&mut self, len: usize) -> (usize, &mut [u8]) {
+        let dropped = self.parsed;
+        self.bytes.drain(..dropped);
+        self.parsed = 0;
+
+        let filled = self.bytes.len();
+        self.bytes.resize(filled + len, 0);
+        (dropped, &mut self.bytes[filled..])
+    }
+
+    // Keeps the `read` first bytes of the room made by `reserve`.
+    pub(crate) fn filled(&mut self, len: usize, read: usize) {
+        self.bytes.truncate(self.bytes.len() - len + read);
+    }
+}
+
+impl<'input> Input<'input> for ReaderChunk {
+    type Token = u8;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = &'parse Self
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        self
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset <= self.bytes.len()
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.bytes.get(offset) {
+            Some(byte) => (offset + 1, Some(*byte)),
+            None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        self.parsed
+    }
+}
+
+impl<'input> SliceInput<'input> for ReaderChunk {
+    type Slice = BufferSlice;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        BufferSlice { start, end }
+    }
+}
+
+// Bytes read from a `Read` chunk by chunk, e.g to parse the records of a log file too big to be
+// loaded in memory. Records are parsed one at a time with `parse_next`, only the bytes of the
+// record being parsed are kept in memory.
+pub struct ReaderInput<R> {
+    reader: R,
+    chunk: ReaderChunk,
+    // Bytes dropped from the buffer so far, offsets of the errors are relative to the start of
+    // the stream.
+    dropped: usize,
+    chunk_size: usize,
+    eof: bool,
+}
+
+impl<R> ReaderInput<R>
+where
+    R: Read,
+{
+    pub fn new(reader: R) -> Self {
+        Self::with_chunk_size(reader, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(reader: R, chunk_size: usize) -> Self {
+        Self {
+            reader,
+            chunk: ReaderChunk::new(),
+            dropped: 0,
+            chunk_size: chunk_size.max(1),
+            eof: false,
+        }
+    }
+
+    // Offset, from the start of the stream, of the next byte to parse.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.dropped + self.chunk.parsed
+    }
+
+    // Runs `parser` on the bytes following the previous record. The parser is run on what is
+    // buffered as a partial input: every time it runs out of input (see `ParseError::Incomplete`),
+    // another chunk is read and the parser is run again. `None` is returned once the stream is
+    // exhausted.
+    pub fn parse_next<P, O>(&mut self, parser: &P) -> Result<Option<O>, StreamError<u8>>
+    where
+        P: Parser<'static, ReaderChunk, O>,
+    {
+        loop {
+            match self.chunk.parse_next(parser, self.eof, self.dropped) {
+                Some(result) => return result,
+                None => self.refill()?,
+            }
+        }
+    }
+
+    // Drops the bytes parsed so far and reads another chunk.
+    fn refill(&mut self) -> io::Result<()> {
+        let (dropped, room) = self.chunk.reserve(self.chunk_size);
+        self.dropped += dropped;
+
+        let read = loop {
+            match self.reader.read(room) {
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                read => break read,
+            }
+        };
+        self.chunk
+            .filled(self.chunk_size, *read.as_ref().unwrap_or(&0));
+        self.eof = read? == 0;
+        Ok(())
+    }
+}
+
+// Outcome of feeding a `ParseState`.
+#[derive(Debug, PartialEq)]
+pub enum Status<O> {
+    // The parser ran out of input, feed more to resume.
+    NeedMore,
+    Done(O),
+    Error(ErrorReport<u8>),
+}
+
+// Push based counterpart of `ReaderInput`: the caller feeds the bytes as they arrive, e.g from a
+// non-blocking socket, and gets each record once it was fully received. The bytes of the record
+// being parsed are kept until it is, the parser is run again from the start of the record on each
+// feed.
+pub struct ParseState<P, O> {
+    parser: P,
+    chunk: ReaderChunk,
+    dropped: usize,
+    eof: bool,
+    phantom: PhantomData<O>,
+}
+
+impl<P, O> ParseState<P, O>
+where
+    P: Parser<'static, ReaderChunk, O>,
+{
+    pub fn new(parser: P) -> Self {
+        Self {
+            parser,
+            chunk: ReaderChunk::new(),
+            dropped: 0,
+            eof: false,
+            phantom: PhantomData,
+        }
+    }
+
+    // Offset, from the start of the stream, of the next byte to parse.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.dropped + self.chunk.parsed
+    }
+
+    // Appends `bytes` to the input and parses the next record. Several records may be complete
+    // after a single feed, feed an empty slice to get the next ones.
+    pub fn feed(&mut self, bytes: &[u8]) -> Status<O> {
+        let (dropped, room) = self.chunk.reserve(bytes.len());
+        room.copy_from_slice(bytes);
+        self.dropped += dropped;
+        self.resume()
+    }
+
+    // Signals the end of the input, the record being parsed must be complete with what was fed
+    // so far. `NeedMore` means no bytes are left.
+    pub fn finish(&mut self) -> Status<O> {
+        self.eof = true;
+        self.resume()
+    }
+
+    fn resume(&mut self) -> Status<O> {
+        match self.chunk.parse_next(&self.parser, self.eof, self.dropped) {
+            Some(Ok(Some(out))) => Status::Done(out),
+            Some(Err(StreamError::Parse(report))) => Status::Error(report),
+            Some(Err(_)) => unreachable!("no IO is done on fed bytes"),
+            Some(Ok(None)) | None => Status::NeedMore,
+        }
+    }
+}
+
+// Same as `ReaderInput` but reads from a Tokio `AsyncRead`, e.g a socket: `parse_next` waits for
+// more bytes to arrive whenever the parser runs out of input. Protocol servers facing untrusted
+// peers should bound how long they wait and how big a record can grow.
+#[cfg(feature = "async")]
+pub struct AsyncReaderInput<R> {
+    reader: R,
+    chunk: ReaderChunk,
+    dropped: usize,
+    chunk_size: usize,
+    timeout: Option<Duration>,
+    max_frame_size: Option<usize>,
+    eof: bool,
+}
+
+#[cfg(feature = "async")]
+impl<R> AsyncReaderInput<R>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    pub fn new(reader: R) -> Self {
+        Self::with_chunk_size(reader, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(reader: R, chunk_size: usize) -> Self {
+        Self {
+            reader,
+            chunk: ReaderChunk::new(),
+            dropped: 0,
+            chunk_size: chunk_size.max(1),
+            timeout: None,
+            max_frame_size: None,
+            eof: false,
+        }
+    }
+
+    // Fails with `StreamError::Timeout` when no byte arrives for `timeout`. The Tokio runtime
+    // must have its time driver enabled.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Fails with `StreamError::FrameTooLarge` when a record needs more than `limit` bytes.
+    pub fn max_frame_size(mut self, limit: usize) -> Self {
+        self.max_frame_size = Some(limit);
+        self
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.dropped + self.chunk.parsed
+    }
+
+    // See `ReaderInput::parse_next`.
+    pub async fn parse_next<P, O>(&mut self, parser: &P) -> Result<Option<O>, StreamError<u8>>
+    where
+        P: Parser<'static, ReaderChunk, O>,
+    {
+        loop {
+            match self.chunk.parse_next(parser, self.eof, self.dropped) {
+                Some(result) => return result,
+                None => match self.max_frame_size {
+                    Some(limit) if self.chunk.pending() >= limit => {
+                        return Err(StreamError::FrameTooLarge { limit })
+                    }
+                    _ => self.refill().await?,
+                },
+            }
+        }
+    }
+
+    async fn refill(&mut self) -> Result<(), StreamError<u8>> {
+        use tokio::io::AsyncReadExt;
+
+        // Records can't grow past the maximum frame size, even by a partial chunk.
+        let len = match self.max_frame_size {
+            Some(limit) => self.chunk_size.min(limit - self.chunk.pending()),
+            None => self.chunk_size,
+        };
+        let (dropped, room) = self.chunk.reserve(len);
+        self.dropped += dropped;
+
+        let read = loop {
+            let read = self.reader.read(room);
+            let read = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, read).await,
+                None => Ok(read.await),
+            };
+            match read {
+                Ok(Err(error)) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Ok(read) => break read,
+                Err(_) => {
+                    self.chunk.filled(len, 0);
+                    return Err(StreamError::Timeout);
+                }
+            }
+        };
+        self.chunk.filled(len, *read.as_ref().unwrap_or(&0));
+        self.eof = read? == 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,457 @@
+// Ready-made primitives for the numbers written in text, e.g `text::int::<u64>(10)` or
+// `text::float::<f64>()`, over inputs of characters (`&str`) or bytes (`&[u8]`). The digits are
+// walked through once: integers are built as they go, floats are scanned along with their text,
+// which is then handed to `str::parse` for correct rounding.
+use crate::error::Expected;
+use crate::input::{Input, InputRef, SliceInput};
+use crate::{ParseError, ParseResult, Parser};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+// Tokens of text, characters or bytes.
+pub trait TextToken: Copy {
+    // The token as an ASCII character, `None` if it's not one.
+    fn as_ascii(self) -> Option<u8>;
+
+    #[inline]
+    fn to_digit(self, radix: u32) -> Option<u32> {
+        self.as_ascii()
+            .and_then(|byte| char::from(byte).to_digit(radix))
+    }
+}
+
+impl TextToken for char {
+    #[inline]
+    fn as_ascii(self) -> Option<u8> {
+        self.is_ascii().then_some(self as u8)
+    }
+}
+
+impl TextToken for u8 {
+    #[inline]
+    fn as_ascii(self) -> Option<u8> {
+        self.is_ascii().then_some(self)
+    }
+}
+
+// Integer types `int` produces.
+pub trait Integer: Copy {
+    const SIGNED: bool;
+
+    const ZERO: Self;
+
+    // `self * radix + digit`, or `self * radix - digit` for negative numbers, which are built
+    // below zero so that the smallest one doesn't overflow. `None` on overflow.
+    fn push_digit(self, radix: u32, digit: u32, negative: bool) -> Option<Self>;
+}
+
+macro_rules! impl_integer {
+    ($signed:literal: $($ty:ty),*) => {
+        $(
+            impl Integer for $ty {
+                const SIGNED: bool = $signed;
+
+                const ZERO: Self = 0;
+
+                #[inline]
+                fn push_digit(self, radix: u32, digit: u32, negative: bool) -> Option<Self> {
+                    // Radixes and digits are below 36, they fit in any integer type.
+                    let shifted = self.checked_mul(radix as $ty)?;
+                    if negative {
+                        shifted.checked_sub(digit as $ty)
+                    } else {
+                        shifted.checked_add(digit as $ty)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(false: u8, u16, u32, u64, u128, usize);
+impl_integer!(true: i8, i16, i32, i64, i128, isize);
+
+fn check_radix(radix: u32) {
+    assert!((2..=36).contains(&radix), "radix must be between 2 and 36");
+}
+
+// Offset past the run of digits at `offset` along with its number of tokens, calling `push` with
+// each digit. Runs into the end of a partial input are incomplete: more digits may follow.
+fn scan_digits<'input, I>(
+    input_ref: &InputRef<'input, '_, I>,
+    mut offset: I::Offset,
+    radix: u32,
+    mut push: impl FnMut(u32),
+) -> ParseResult<(I::Offset, usize)>
+where
+    I: Input<'input>,
+    I::Token: TextToken,
+{
+    let mut count = 0;
+    loop {
+        match input_ref.peek_at(offset) {
+            (next_offset, Some(token)) => match token.to_digit(radix) {
+                Some(digit) => {
+                    push(digit);
+                    offset = next_offset;
+                    count += 1;
+                }
+                None => return Ok((offset, count)),
+            },
+            (_, None) if input_ref.is_partial() => {
+                return Err(ParseError::Incomplete { needed: Some(1) })
+            }
+            (_, None) => return Ok((offset, count)),
+        }
+    }
+}
+
+// Fails unless at least a digit was found at `offset`.
+fn expect_digits<'input, I>(
+    input_ref: &mut InputRef<'input, '_, I>,
+    offset: I::Offset,
+    count: usize,
+) -> ParseResult<()>
+where
+    I: Input<'input>,
+{
+    if count == 0 {
+        input_ref.record_expected_at(offset, Expected::Label("digit"));
+        return Err(input_ref.mismatch_at(offset, Some(1)));
+    }
+    Ok(())
+}
+
+// `Digits` primitive, outputs the run of one or more digits in `radix` under the cursor.
+#[derive(Debug, Clone, Copy)]
+pub struct Digits {
+    radix: u32,
+}
+
+// Panics if `radix` isn't between 2 and 36.
+pub fn digits(radix: u32) -> Digits {
+    check_radix(radix);
+    Digits { radix }
+}
+
+impl<'input, I> Parser<'input, I, I::Slice> for Digits
+where
+    I: SliceInput<'input>,
+    I::Token: TextToken,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let start = input_ref.offset();
+        let (end, count) = scan_digits(input_ref, start.get(), self.radix, |_| {})?;
+        expect_digits(input_ref, end, count)?;
+        input_ref.advance(end, count);
+        Ok(input_ref.slice(start, input_ref.offset()))
+    }
+}
+
+// `Int` primitive, parses an integer in `radix`, e.g `-42` or `0x2A`. A `+` sign is accepted, a
+// `-` one only for signed types. Hexadecimal, octal and binary numbers may start with `0x`, `0o`
+// and `0b` respectively, when a digit follows the prefix: `0xz` is a `0` followed by `xz`.
+// Numbers that don't fit in `T` are an error over the whole literal.
+#[derive(Debug, Clone, Copy)]
+pub struct Int<T> {
+    radix: u32,
+    phantom: PhantomData<T>,
+}
+
+// Panics if `radix` isn't between 2 and 36.
+pub fn int<T: Integer>(radix: u32) -> Int<T> {
+    check_radix(radix);
+    Int {
+        radix,
+        phantom: PhantomData,
+    }
+}
+
+impl<'input, I, T> Parser<'input, I, T> for Int<T>
+where
+    I: Input<'input>,
+    I::Token: TextToken,
+    T: Integer,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<T> {
+        let start = input_ref.offset();
+        let ascii = |offset| {
+            let (next_offset, token) = input_ref.peek_at(offset);
+            (next_offset, token.and_then(TextToken::as_ascii))
+        };
+
+        let (mut offset, mut tokens, mut negative) = (start.get(), 0, false);
+        match ascii(offset) {
+            (next_offset, Some(b'+')) => (offset, tokens) = (next_offset, 1),
+            (next_offset, Some(b'-')) if T::SIGNED => {
+                (offset, tokens, negative) = (next_offset, 1, true);
+            }
+            _ => {}
+        }
+        let prefix = match self.radix {
+            16 => Some(b'x'),
+            8 => Some(b'o'),
+            2 => Some(b'b'),
+            _ => None,
+        };
+        if let (after_zero, Some(b'0')) = ascii(offset) {
+            if let (after_prefix, Some(found)) = ascii(after_zero) {
+                if Some(found.to_ascii_lowercase()) == prefix {
+                    match input_ref.peek_at(after_prefix).1 {
+                        Some(token) if token.to_digit(self.radix).is_some() => {
+                            (offset, tokens) = (after_prefix, tokens + 2);
+                        }
+                        None if input_ref.is_partial() => {
+                            return Err(ParseError::Incomplete { needed: Some(1) });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut value = Some(T::ZERO);
+        let (end, count) = scan_digits(input_ref, offset, self.radix, |digit| {
+            value = value.and_then(|value| value.push_digit(self.radix, digit, negative));
+        })?;
+        expect_digits(input_ref, end, count)?;
+        input_ref.advance(end, tokens + count);
+        value.ok_or_else(|| {
+            let error = ParseError::custom("integer literal out of range");
+            input_ref.record_parse_error(start.into()..end.into(), error)
+        })
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for f32 {}
+
+    impl Sealed for f64 {}
+}
+
+// Floating point types `float` produces. It's sealed: their `FromStr` must accept every literal
+// `float` scans.
+pub trait Float: FromStr + sealed::Sealed {}
+
+impl Float for f32 {}
+
+impl Float for f64 {}
+
+// ASCII text of a literal, kept on the stack unless it's unusually long.
+struct LiteralText {
+    inline: [u8; 64],
+    len: usize,
+    heap: Vec<u8>,
+}
+
+impl LiteralText {
+    fn new() -> Self {
+        LiteralText {
+            inline: [0; 64],
+            len: 0,
+            heap: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.heap.is_empty() && self.len < self.inline.len() {
+            self.inline[self.len] = byte;
+            self.len += 1;
+        } else {
+            if self.heap.is_empty() {
+                self.heap.extend_from_slice(&self.inline);
+            }
+            self.heap.push(byte);
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        let bytes = if self.heap.is_empty() {
+            &self.inline[..self.len]
+        } else {
+            &self.heap
+        };
+        std::str::from_utf8(bytes).expect("literals are made of ASCII characters")
+    }
+}
+
+// Cursor over a literal being scanned, ahead of the input's, along with its text.
+struct Literal<Off> {
+    offset: Off,
+    tokens: usize,
+    text: LiteralText,
+}
+
+impl<Off: Copy> Literal<Off> {
+    // ASCII character `ahead` tokens past the cursor of the literal. The end of a partial input
+    // is incomplete: the literal may go on.
+    fn peek<'input, I>(
+        &self,
+        input_ref: &InputRef<'input, '_, I>,
+        ahead: usize,
+    ) -> ParseResult<Option<u8>>
+    where
+        I: Input<'input, Offset = Off>,
+        I::Token: TextToken,
+    {
+        let mut offset = self.offset;
+        for _ in 0..ahead {
+            offset = input_ref.peek_at(offset).0;
+        }
+        match input_ref.peek_at(offset) {
+            (_, None) if input_ref.is_partial() => Err(ParseError::Incomplete { needed: Some(1) }),
+            (_, token) => Ok(token.and_then(TextToken::as_ascii)),
+        }
+    }
+
+    fn bump<'input, I>(&mut self, input_ref: &InputRef<'input, '_, I>, byte: u8)
+    where
+        I: Input<'input, Offset = Off>,
+    {
+        self.offset = input_ref.peek_at(self.offset).0;
+        self.tokens += 1;
+        self.text.push(byte);
+    }
+
+    // Moves past the run of decimal digits under the cursor, along with its length.
+    fn digits<'input, I>(&mut self, input_ref: &InputRef<'input, '_, I>) -> ParseResult<usize>
+    where
+        I: Input<'input, Offset = Off>,
+        I::Token: TextToken,
+    {
+        let mut count = 0;
+        while let Some(digit) = self.peek(input_ref, 0)?.filter(u8::is_ascii_digit) {
+            self.bump(input_ref, digit);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Moves past `word` if it's under the cursor, whatever the case, and isn't the start of a
+    // longer word: `info` isn't `inf`.
+    fn word<'input, I>(
+        &mut self,
+        input_ref: &InputRef<'input, '_, I>,
+        word: &str,
+    ) -> ParseResult<bool>
+    where
+        I: Input<'input, Offset = Off>,
+        I::Token: TextToken,
+    {
+        for (ahead, expected) in word.bytes().enumerate() {
+            match self.peek(input_ref, ahead)? {
+                Some(byte) if byte.eq_ignore_ascii_case(&expected) => {}
+                _ => return Ok(false),
+            }
+        }
+        if let Some(byte) = self.peek(input_ref, word.len())? {
+            if byte.is_ascii_alphanumeric() || byte == b'_' {
+                return Ok(false);
+            }
+        }
+        word.bytes().for_each(|byte| self.bump(input_ref, byte));
+        Ok(true)
+    }
+}
+
+// `FloatLiteral` primitive, parses a floating point number, e.g `-1.5e-3`, rounded to the
+// nearest `T` as `str::parse` does. The sign and the exponent are optional, as are the integer or
+// the fractional part (not both). A `.` is only part of the number if a digit follows it, so that
+// `1.max` stops before the dot. `inf`, `infinity` and `nan`, whatever the case, are accepted
+// too, unless the parser is `finite_only`.
+#[derive(Debug, Clone, Copy)]
+pub struct FloatLiteral<T> {
+    finite_only: bool,
+    phantom: PhantomData<T>,
+}
+
+pub fn float<T: Float>() -> FloatLiteral<T> {
+    FloatLiteral {
+        finite_only: false,
+        phantom: PhantomData,
+    }
+}
+
+impl<T> FloatLiteral<T> {
+    // Rejects `inf` and `nan`, e.g for languages where they are identifiers.
+    pub fn finite_only(mut self) -> Self {
+        self.finite_only = true;
+        self
+    }
+}
+
+impl<T> FloatLiteral<T> {
+    fn scan<'input, I>(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+    ) -> ParseResult<Literal<I::Offset>>
+    where
+        I: Input<'input>,
+        I::Token: TextToken,
+    {
+        let mut literal = Literal {
+            offset: input_ref.raw_offset(),
+            tokens: 0,
+            text: LiteralText::new(),
+        };
+        if let Some(sign @ (b'+' | b'-')) = literal.peek(input_ref, 0)? {
+            literal.bump(input_ref, sign);
+        }
+        if !self.finite_only {
+            for word in ["infinity", "inf", "nan"] {
+                if literal.word(input_ref, word)? {
+                    return Ok(literal);
+                }
+            }
+        }
+
+        let mut count = literal.digits(input_ref)?;
+        if literal.peek(input_ref, 0)? == Some(b'.')
+            && literal
+                .peek(input_ref, 1)?
+                .is_some_and(|byte| byte.is_ascii_digit())
+        {
+            literal.bump(input_ref, b'.');
+            count += literal.digits(input_ref)?;
+        }
+        expect_digits(input_ref, literal.offset, count)?;
+
+        if let Some(e @ (b'e' | b'E')) = literal.peek(input_ref, 0)? {
+            let sign = literal
+                .peek(input_ref, 1)?
+                .filter(|byte| matches!(byte, b'+' | b'-'));
+            let ahead = 1 + usize::from(sign.is_some());
+            if literal
+                .peek(input_ref, ahead)?
+                .is_some_and(|byte| byte.is_ascii_digit())
+            {
+                literal.bump(input_ref, e);
+                sign.inspect(|&sign| literal.bump(input_ref, sign));
+                literal.digits(input_ref)?;
+            }
+        }
+        Ok(literal)
+    }
+}
+
+impl<'input, I, T> Parser<'input, I, T> for FloatLiteral<T>
+where
+    I: Input<'input>,
+    I::Token: TextToken,
+    T: Float,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<T> {
+        let literal = self.scan(input_ref)?;
+        let value = (literal.text.as_str().parse().ok()).expect("the literal is a valid number");
+        input_ref.advance(literal.offset, literal.tokens);
+        Ok(value)
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        let literal = self.scan(input_ref)?;
+        input_ref.advance(literal.offset, literal.tokens);
+        Ok(())
+    }
+}
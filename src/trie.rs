@@ -0,0 +1,177 @@
+// Prefix tree of the sequences of `one_of`, so that matching any of hundreds of keywords is a single
+// pass over the input rather than one attempt per sequence. The earliest sequence that matches is
+// the one picked, as if they were tried in order.
+use crate::error::{Expected, ExpectedSet};
+use crate::input::{Input, InputRef};
+use crate::sequence::OrderedSequence;
+use crate::{ParseError, ParseResult};
+
+const ROOT: usize = 0;
+
+// Index past the sequences, when none goes through a node.
+const NONE: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Trie<T> {
+    nodes: Vec<Node<T>>,
+    // Number of tokens of each sequence.
+    lengths: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    // In the order of the first sequence going through each of them.
+    children: Vec<(T, usize)>,
+    // First sequence going through or ending at the node.
+    first: usize,
+    // First sequence going past the node.
+    first_deeper: usize,
+    // First sequence ending at the node.
+    terminal: Option<usize>,
+}
+
+impl<T> Node<T> {
+    fn new(first: usize) -> Self {
+        Node {
+            children: Vec::new(),
+            first,
+            first_deeper: NONE,
+            terminal: None,
+        }
+    }
+}
+
+impl<T> Trie<T>
+where
+    T: Copy + PartialEq,
+{
+    pub(crate) fn new<S>(sequences: &[S]) -> Self
+    where
+        S: OrderedSequence<Token = T>,
+    {
+        let mut trie = Trie {
+            nodes: vec![Node::new(0)],
+            lengths: Vec::with_capacity(sequences.len()),
+        };
+        for (idx, seq) in sequences.iter().enumerate() {
+            let mut node = ROOT;
+            let mut length = 0;
+            for token in seq.iterator() {
+                let deeper = &mut trie.nodes[node].first_deeper;
+                *deeper = (*deeper).min(idx);
+                node = match trie.child(node, token) {
+                    Some(child) => child,
+                    None => {
+                        let child = trie.nodes.len();
+                        trie.nodes.push(Node::new(idx));
+                        trie.nodes[node].children.push((token, child));
+                        child
+                    }
+                };
+                length += 1;
+            }
+            trie.nodes[node].terminal.get_or_insert(idx);
+            trie.lengths.push(length);
+        }
+        trie
+    }
+
+    #[inline]
+    fn child(&self, node: usize, token: T) -> Option<usize> {
+        let children = &self.nodes[node].children;
+        children
+            .iter()
+            .find(|(t, _)| *t == token)
+            .map(|(_, child)| *child)
+    }
+
+    // Index of the sequence that matched.
+    pub(crate) fn matches<'input, I>(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+    ) -> ParseResult<usize>
+    where
+        I: Input<'input, Token = T>,
+    {
+        let start = input_ref.raw_offset();
+        let (mut node, mut offset, mut depth) = (ROOT, start, 0);
+        // Sequence that matched along with where it ends.
+        let mut best = self.nodes[ROOT].terminal.map(|idx| (idx, start, 0));
+        loop {
+            let before = best.map_or(NONE, |(idx, _, _)| idx);
+            // The sequences further down come after the one that matched.
+            if self.nodes[node].first_deeper >= before {
+                break;
+            }
+            let (next_offset, token) = input_ref.peek_at(offset);
+            match token.and_then(|token| self.child(node, token)) {
+                Some(child) => {
+                    (node, offset, depth) = (child, next_offset, depth + 1);
+                    if let Some(idx) = self.nodes[node].terminal.filter(|&idx| idx < before) {
+                        best = Some((idx, offset, depth));
+                    }
+                }
+                None => break,
+            }
+        }
+
+        let before = best.map_or(NONE, |(idx, _, _)| idx);
+        let first_deeper = self.nodes[node].first_deeper;
+        if first_deeper < before {
+            // An earlier sequence takes precedence, the later ones can't be tried until it is known
+            // whether it matches.
+            let needed = self.lengths[first_deeper] - depth;
+            let error = input_ref.mismatch_at(offset, Some(needed));
+            if error.is_incomplete() {
+                self.record_expected(input_ref, start, depth, first_deeper + 1);
+                return Err(error);
+            }
+        }
+
+        self.record_expected(input_ref, start, depth, before);
+        match best {
+            Some((idx, end, tokens)) => {
+                input_ref.advance(end, tokens);
+                Ok(idx)
+            }
+            None => Err(ParseError::SyntaxError),
+        }
+    }
+
+    // Records what the sequences before `before` that went down the path matched expected where
+    // they left it. Only the furthest failure is kept, so the one where the deepest left counts.
+    fn record_expected<'input, I>(
+        &self,
+        input_ref: &mut InputRef<'input, '_, I>,
+        start: I::Offset,
+        depth: usize,
+        before: usize,
+    ) where
+        I: Input<'input, Token = T>,
+    {
+        let (mut node, mut offset) = (ROOT, start);
+        let mut furthest = None;
+        for at in 0..=depth {
+            let (next_offset, token) = input_ref.peek_at(offset);
+            let next = token
+                .filter(|_| at < depth)
+                .and_then(|token| self.child(node, token));
+            if (self.nodes[node].children.iter())
+                .any(|&(_, child)| Some(child) != next && self.nodes[child].first < before)
+            {
+                furthest = Some((node, offset, next));
+            }
+            match next {
+                Some(child) => (node, offset) = (child, next_offset),
+                None => break,
+            }
+        }
+
+        if let Some((node, offset, next)) = furthest {
+            let expected = (self.nodes[node].children.iter())
+                .filter(|&&(_, child)| Some(child) != next && self.nodes[child].first < before)
+                .map(|&(token, _)| Expected::Token(token));
+            input_ref.record_expected_at(offset, expected.collect::<ExpectedSet<T>>());
+        }
+    }
+}
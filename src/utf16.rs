@@ -0,0 +1,120 @@
+// UTF-16 text, e.g strings from Windows APIs or JavaScript, decoded on the fly rather than
+// transcoded to UTF-8 first. Offsets are indices of code units.
+use crate::error::EncodingError;
+use crate::input::{ByValue, Input, SliceInput};
+use std::char::REPLACEMENT_CHARACTER;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Utf16Input<'input> {
+    units: &'input [u16],
+    lossy: bool,
+}
+
+impl<'input> Utf16Input<'input> {
+    // Unpaired surrogates are encoding errors.
+    pub fn new(units: &'input [u16]) -> Self {
+        Self {
+            units,
+            lossy: false,
+        }
+    }
+
+    // Unpaired surrogates are decoded as U+FFFD.
+    pub fn lossy(units: &'input [u16]) -> Self {
+        Self { units, lossy: true }
+    }
+
+    // Character at `offset` along with its length, or the unpaired surrogate found there.
+    fn decode(&self, offset: usize) -> Option<Result<(char, usize), u16>> {
+        let units = self.units.get(offset..)?.iter().copied();
+        char::decode_utf16(units).next().map(|c| match c {
+            Ok(c) => Ok((c, c.len_utf16())),
+            Err(error) => Err(error.unpaired_surrogate()),
+        })
+    }
+}
+
+// Code units parsed by a parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utf16Slice<'input> {
+    units: &'input [u16],
+}
+
+impl<'input> Utf16Slice<'input> {
+    #[inline]
+    pub fn as_units(&self) -> &'input [u16] {
+        self.units
+    }
+
+    // Unpaired surrogates are decoded as U+FFFD.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'input {
+        char::decode_utf16(self.units.iter().copied()).map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+    }
+}
+
+impl fmt::Display for Utf16Slice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars().try_for_each(|c| write!(f, "{}", c))
+    }
+}
+
+impl<'input> Input<'input> for Utf16Input<'input> {
+    type Token = char;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset <= self.units.len()
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.decode(offset) {
+            Some(Ok((c, len))) => (offset + len, Some(c)),
+            Some(Err(_)) if self.lossy => (offset + 1, Some(REPLACEMENT_CHARACTER)),
+            Some(Err(_)) | None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    fn encoding_error(&self, offset: Self::Offset) -> Option<EncodingError> {
+        match self.decode(offset) {
+            Some(Err(_)) if !self.lossy => Some(EncodingError::new(offset..offset + 1, "UTF-16")),
+            _ => None,
+        }
+    }
+}
+
+impl<'input> SliceInput<'input> for Utf16Input<'input> {
+    type Slice = Utf16Slice<'input>;
+
+    #[inline(always)]
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        Utf16Slice {
+            units: &self.units[start..end],
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Bytes parsed as UTF-8 text, e.g a text protocol read into a byte buffer, validated on the fly
+// rather than with an upfront `str::from_utf8` pass. Offsets are byte offsets.
+use crate::bytes::ByteInput;
+use crate::error::EncodingError;
+use crate::input::{ByValue, Input, SliceInput};
+use std::ops::Range;
+use std::str;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Utf8Input<'input> {
+    bytes: &'input [u8],
+}
+
+impl<'input> Utf8Input<'input> {
+    pub fn new(bytes: &'input [u8]) -> Self {
+        Self { bytes }
+    }
+
+    // Character at `offset`, or the length of the invalid sequence found there.
+    fn decode(&self, offset: usize) -> Option<Result<char, usize>> {
+        let rest = self.bytes.get(offset..).filter(|rest| !rest.is_empty())?;
+        // A character is 4 bytes long at most.
+        let bytes = &rest[..rest.len().min(4)];
+        let valid = match str::from_utf8(bytes) {
+            Ok(valid) => valid,
+            Err(error) if error.valid_up_to() > 0 => {
+                str::from_utf8(&bytes[..error.valid_up_to()]).unwrap()
+            }
+            // Sequences cut short by the end of input are invalid as well.
+            Err(error) => return Some(Err(error.error_len().unwrap_or(bytes.len()))),
+        };
+        valid.chars().next().map(Ok)
+    }
+}
+
+impl<'input> Input<'input> for Utf8Input<'input> {
+    type Token = char;
+
+    type Offset = usize;
+
+    type Span = Range<usize>;
+
+    type Handle<'parse>
+        = ByValue<Self>
+    where
+        Self: 'parse;
+
+    #[inline(always)]
+    fn handle(&self) -> Self::Handle<'_> {
+        ByValue(*self)
+    }
+
+    #[inline]
+    fn is_valid_offset(&self, offset: Self::Offset) -> bool {
+        offset <= self.bytes.len()
+    }
+
+    fn next(&self, offset: Self::Offset) -> (Self::Offset, Option<Self::Token>) {
+        match self.decode(offset) {
+            Some(Ok(c)) => (offset + c.len_utf8(), Some(c)),
+            Some(Err(_)) | None => (offset, None),
+        }
+    }
+
+    #[inline]
+    fn span(&self, start: Self::Offset, end: Self::Offset) -> Self::Span {
+        start..end
+    }
+
+    #[inline(always)]
+    fn start(&self) -> Self::Offset {
+        0
+    }
+
+    fn encoding_error(&self, offset: Self::Offset) -> Option<EncodingError> {
+        match self.decode(offset) {
+            Some(Err(len)) => Some(EncodingError::new(offset..offset + len, "UTF-8")),
+            _ => None,
+        }
+    }
+    // A valid prefix matching byte for byte is matched character for character.
+    #[inline]
+    fn match_str(&self, offset: Self::Offset, prefix: &str) -> Option<Self::Offset> {
+        self.bytes[offset..]
+            .starts_with(prefix.as_bytes())
+            .then_some(offset + prefix.len())
+    }
+}
+
+// Runs are cut short before the first invalid sequence, which the input doesn't decode past.
+impl<'input> ByteInput<'input> for Utf8Input<'input> {
+    fn scan_bytes(&self, offset: usize, scan: impl FnOnce(&[u8]) -> usize) -> (usize, usize) {
+        let rest = &self.bytes[offset..];
+        let run = &rest[..scan(rest)];
+        let valid = match str::from_utf8(run) {
+            Ok(valid) => valid,
+            Err(error) => str::from_utf8(&run[..error.valid_up_to()]).unwrap(),
+        };
+        (offset + valid.len(), valid.chars().count())
+    }
+}
+
+impl<'input> SliceInput<'input> for Utf8Input<'input> {
+    type Slice = &'input str;
+
+    fn slice(&self, start: Self::Offset, end: Self::Offset) -> Self::Slice {
+        // Offsets only ever land on character boundaries of the valid part of the input.
+        str::from_utf8(&self.bytes[start..end]).expect("slices of decoded characters are valid")
+    }
+}
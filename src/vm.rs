@@ -0,0 +1,438 @@
+// Grammars compiled to a flat list of instructions run by a small backtracking machine, for
+// parsing millions of small inputs with the same grammar without going through the deep chains of
+// generic calls of the combinators every time. The combinators are still how the grammar is
+// written, `Parser::compile` turns it into a `Program`. A program only recognizes the input and
+// outputs the slice it matched: the closures of `map` and the like are left out, and the
+// combinators that depend on user code to match (`filter`, `guard`, recovery, ...) can't be
+// compiled.
+use crate::error::Expected;
+use crate::input::{Checkpoint, Input, InputRef, SliceInput};
+use crate::sequence::OrderedSequence;
+use crate::{
+    And, Any, Choice, Collect, DelimitedBy, End, Exact, Labelled, LeftBind, Map, Nested, Or, OrNot,
+    Padded, ParseResult, Parser, RepeatedRange, RightBind, Tag, ToSlice,
+};
+use std::marker::PhantomData;
+
+#[derive(Debug, Clone)]
+enum Instruction<T> {
+    // Matches the token under the cursor, `needed` being the number of tokens left in the
+    // sequence it belongs to when running out of a partial input.
+    Token(T, usize),
+    Any,
+    End,
+    // Tries the instructions that follow, going on at `0` with the input rewound if they fail.
+    Choice(usize),
+    // Drops the last choice once its instructions matched and goes on at `0`.
+    Commit(usize),
+    // Starts a repetition of the instructions that follow, a choice going on at `0` once an
+    // iteration fails. The repetition fails as a whole if it was short of `1` iterations.
+    Repeat(usize, usize),
+    // Ends an iteration of a repetition: goes back to `0` for another one, unless the iteration
+    // didn't consume anything or there were `1` of them already.
+    Loop(usize, usize),
+}
+
+// Attempt to roll back to on failure, see `Instruction::Choice` and `Instruction::Repeat`.
+struct Backtrack<O> {
+    target: usize,
+    checkpoint: Checkpoint<O>,
+    iterations: usize,
+    // Iterations a repetition can't fail short of, none for choices.
+    at_least: usize,
+}
+
+// Instructions of a grammar being compiled, see `Compile`.
+pub struct Compiler<T> {
+    instructions: Vec<Instruction<T>>,
+}
+
+impl<T> Compiler<T> {
+    // Index of the next instruction.
+    #[inline]
+    fn here(&self) -> usize {
+        self.instructions.len()
+    }
+
+    #[inline]
+    fn push(&mut self, instruction: Instruction<T>) -> usize {
+        self.instructions.push(instruction);
+        self.here() - 1
+    }
+
+    // Points the choice or commit at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+        match &mut self.instructions[at] {
+            Instruction::Choice(target)
+            | Instruction::Commit(target)
+            | Instruction::Repeat(target, _) => *target = here,
+            _ => unreachable!("only choices, commits and repetitions are patched"),
+        }
+    }
+
+    fn optional(&mut self, parser: &impl Compile<T>) {
+        let choice = self.push(Instruction::Choice(0));
+        parser.compile_into(self);
+        let commit = self.push(Instruction::Commit(0));
+        self.patch(commit);
+        self.patch(choice);
+    }
+
+    fn repeated(&mut self, parser: &impl Compile<T>, range: RepeatedRange) {
+        let at_most = range.end().unwrap_or(usize::MAX);
+        if at_most > 0 {
+            let repeat = self.push(Instruction::Repeat(0, range.start()));
+            parser.compile_into(self);
+            self.push(Instruction::Loop(repeat + 1, at_most));
+            self.patch(repeat);
+        }
+    }
+}
+
+// Parser that can be compiled into a `Program`, see `Parser::compile`. The parsers it is made of
+// are compiled with `compile_into`.
+pub trait Compile<T> {
+    fn compile_into(&self, compiler: &mut Compiler<T>);
+}
+
+// Grammar compiled by `Parser::compile`, outputs the slice of input it matched.
+pub struct Program<'input, I: Input<'input>> {
+    instructions: Vec<Instruction<I::Token>>,
+    phantom: PhantomData<I>,
+}
+
+impl<'input, I> Clone for Program<'input, I>
+where
+    I: Input<'input>,
+{
+    fn clone(&self) -> Self {
+        Program {
+            instructions: self.instructions.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'input, I> Program<'input, I>
+where
+    I: Input<'input>,
+{
+    pub(crate) fn new<P>(parser: &P) -> Self
+    where
+        P: Compile<I::Token>,
+    {
+        let mut compiler = Compiler {
+            instructions: Vec::new(),
+        };
+        parser.compile_into(&mut compiler);
+        Program {
+            instructions: compiler.instructions,
+            phantom: PhantomData,
+        }
+    }
+
+    // Number of instructions of the program.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    fn run(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        let mut backtracks: Vec<Backtrack<I::Offset>> = Vec::new();
+        let mut pc = 0;
+        while let Some(instruction) = self.instructions.get(pc) {
+            let error = match *instruction {
+                Instruction::Token(token, needed) => {
+                    if input_ref.peek_token() == Some(token) {
+                        input_ref.next_token();
+                        pc += 1;
+                        continue;
+                    }
+                    input_ref.record_expected(Expected::Token(token));
+                    input_ref.mismatch(Some(needed))
+                }
+                Instruction::Any => {
+                    if input_ref.next_token().is_some() {
+                        pc += 1;
+                        continue;
+                    }
+                    input_ref.record_expected(Expected::Any);
+                    input_ref.mismatch(Some(1))
+                }
+                Instruction::End => match crate::end().go(input_ref) {
+                    Ok(()) => {
+                        pc += 1;
+                        continue;
+                    }
+                    Err(error) => error,
+                },
+                Instruction::Choice(target) => {
+                    backtracks.push(Backtrack {
+                        target,
+                        checkpoint: input_ref.save(),
+                        iterations: 0,
+                        at_least: 0,
+                    });
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Repeat(target, at_least) => {
+                    backtracks.push(Backtrack {
+                        target,
+                        checkpoint: input_ref.save(),
+                        iterations: 0,
+                        at_least,
+                    });
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Commit(target) => {
+                    let backtrack = backtracks.pop().expect("commit without a choice");
+                    input_ref.commit(backtrack.checkpoint);
+                    pc = target;
+                    continue;
+                }
+                Instruction::Loop(body, at_most) => {
+                    let backtrack = backtracks.pop().expect("loop without a repetition");
+                    let (target, iterations) = (backtrack.target, backtrack.iterations + 1);
+                    // An iteration that consumed nothing would be repeated forever, and would match
+                    // all the same if more are needed.
+                    let progressed = backtrack.checkpoint.offset() != input_ref.offset();
+                    input_ref.commit(backtrack.checkpoint);
+                    if progressed && iterations < at_most {
+                        backtracks.push(Backtrack {
+                            target,
+                            checkpoint: input_ref.save(),
+                            iterations,
+                            at_least: backtrack.at_least,
+                        });
+                        pc = body;
+                    } else {
+                        pc += 1;
+                    }
+                    continue;
+                }
+            };
+
+            if error.is_fatal() {
+                return Err(error);
+            }
+            let backtrack = loop {
+                match backtracks.pop() {
+                    // The repetition failed short of its minimum, it fails as a whole.
+                    Some(backtrack) if backtrack.iterations < backtrack.at_least => {}
+                    Some(backtrack) => break backtrack,
+                    None => return Err(error),
+                }
+            };
+            input_ref.restore(backtrack.checkpoint)?;
+            pc = backtrack.target;
+        }
+        Ok(())
+    }
+}
+
+impl<'input, I> Parser<'input, I, I::Slice> for Program<'input, I>
+where
+    I: SliceInput<'input>,
+{
+    fn go(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<I::Slice> {
+        let start = input_ref.offset();
+        self.run(input_ref)?;
+        Ok(input_ref.slice(start, input_ref.offset()))
+    }
+
+    fn go_check(&self, input_ref: &mut InputRef<'input, '_, I>) -> ParseResult<()> {
+        self.run(input_ref)
+    }
+}
+
+impl<I, T> Compile<T::Token> for Exact<I, T>
+where
+    T: OrderedSequence,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T::Token>) {
+        let len = self.seq.iterator().count();
+        for (idx, token) in self.seq.iterator().enumerate() {
+            compiler.push(Instruction::Token(token, len - idx));
+        }
+    }
+}
+
+impl<I, T> Compile<T::Token> for Tag<I, T>
+where
+    T: OrderedSequence,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T::Token>) {
+        self.0.compile_into(compiler);
+    }
+}
+
+impl<I, T> Compile<T> for Any<I> {
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        compiler.push(Instruction::Any);
+    }
+}
+
+impl<I, T> Compile<T> for End<I> {
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        compiler.push(Instruction::End);
+    }
+}
+
+impl<T, P1, P2> Compile<T> for Or<P1, P2>
+where
+    P1: Compile<T>,
+    P2: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        let choice = compiler.push(Instruction::Choice(0));
+        self.first_parser.compile_into(compiler);
+        let commit = compiler.push(Instruction::Commit(0));
+        compiler.patch(choice);
+        self.second_parser.compile_into(compiler);
+        compiler.patch(commit);
+    }
+}
+
+macro_rules! impl_compile_choice {
+    ($($parser:ident $idx:tt),+; $last:ident $last_idx:tt) => {
+        impl<T, $($parser,)+ $last> Compile<T> for Choice<($($parser,)+ $last,)>
+        where
+            $($parser: Compile<T>,)+
+            $last: Compile<T>,
+        {
+            fn compile_into(&self, compiler: &mut Compiler<T>) {
+                let mut commits = Vec::new();
+                $(
+                    let choice = compiler.push(Instruction::Choice(0));
+                    self.0.$idx.compile_into(compiler);
+                    commits.push(compiler.push(Instruction::Commit(0)));
+                    compiler.patch(choice);
+                )+
+                self.0.$last_idx.compile_into(compiler);
+                for commit in commits {
+                    compiler.patch(commit);
+                }
+            }
+        }
+    };
+}
+
+impl_compile_choice!(P1 0; P2 1);
+impl_compile_choice!(P1 0, P2 1; P3 2);
+impl_compile_choice!(P1 0, P2 1, P3 2; P4 3);
+impl_compile_choice!(P1 0, P2 1, P3 2, P4 3; P5 4);
+impl_compile_choice!(P1 0, P2 1, P3 2, P4 3, P5 4; P6 5);
+impl_compile_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5; P7 6);
+impl_compile_choice!(P1 0, P2 1, P3 2, P4 3, P5 4, P6 5, P7 6; P8 7);
+
+macro_rules! impl_compile_bind {
+    ($($bind:ident),+) => {
+        $(
+            impl<T, I, P1, OP1, P2, OP2> Compile<T> for $bind<I, P1, OP1, P2, OP2>
+            where
+                P1: Compile<T>,
+                P2: Compile<T>,
+            {
+                fn compile_into(&self, compiler: &mut Compiler<T>) {
+                    self.0.first_parser.compile_into(compiler);
+                    self.0.second_parser.compile_into(compiler);
+                }
+            }
+        )+
+    };
+}
+
+impl_compile_bind!(And, LeftBind, RightBind);
+
+impl<T, I, P, OP, F, U> Compile<T> for Map<I, P, OP, F, U>
+where
+    P: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        self.parser.compile_into(compiler);
+    }
+}
+
+impl<T, I, P, O> Compile<T> for Labelled<I, P, O>
+where
+    P: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        self.parser.compile_into(compiler);
+    }
+}
+
+impl<T, I, P, O> Compile<T> for Nested<I, P, O>
+where
+    P: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        self.parser.compile_into(compiler);
+    }
+}
+
+impl<T, I, P, O> Compile<T> for OrNot<I, P, O>
+where
+    P: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        compiler.optional(&self.parser);
+    }
+}
+
+impl<T, I, P1, OP1, P2, OP2> Compile<T> for Padded<I, P1, OP1, P2, OP2>
+where
+    P1: Compile<T>,
+    P2: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        compiler.optional(&self.padded_by);
+        self.parser.compile_into(compiler);
+        compiler.optional(&self.padded_by);
+    }
+}
+
+impl<T, I, P, O, P1, OP1, P2, OP2> Compile<T> for DelimitedBy<I, P, O, P1, OP1, P2, OP2>
+where
+    P: Compile<T>,
+    P1: Compile<T>,
+    P2: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        self.open.compile_into(compiler);
+        self.parser.compile_into(compiler);
+        self.close.compile_into(compiler);
+    }
+}
+
+macro_rules! impl_compile_repeated {
+    ($($repeated:ident),+) => {
+        $(
+            impl<T, I, P, OP> Compile<T> for $repeated<I, P, OP>
+            where
+                P: Compile<T>,
+            {
+                fn compile_into(&self, compiler: &mut Compiler<T>) {
+                    compiler.repeated(&self.0.parser, self.0.range);
+                }
+            }
+        )+
+    };
+}
+
+impl_compile_repeated!(ToSlice);
+
+impl<T, I, P, OP, C> Compile<T> for Collect<I, P, OP, C>
+where
+    P: Compile<T>,
+{
+    fn compile_into(&self, compiler: &mut Compiler<T>) {
+        compiler.repeated(&self.parser, self.range);
+    }
+}